@@ -1,12 +1,17 @@
 use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::sync::{Arc, Mutex};
-use webserver::http::send_http_request;
 use webserver::http::server::Route;
 use webserver::http::HttpServer;
-use webserver::http::{HttpRequest, HttpResponse};
+use webserver::http::websocket::Message;
+use webserver::http::{byte_ranges, conditional_get, error_page, static_files, ETagStrength};
+use webserver::http::{extract, Path};
+use webserver::http::{AccessLog, LogFormat};
+use webserver::http::{HttpRequest, HttpResponse, PlainHttp, Proxy, Router};
 use webserver::json::JsonValue;
 use webserver::templating::render;
+use webserver::tls::TlsConfig;
 
 fn read_file(path: &'static str) -> String {
     fs::read_to_string(path).unwrap()
@@ -37,11 +42,7 @@ fn main() {
         }),
     });
 
-    server.get("/id/:id", &|request: &HttpRequest,
-                            response: &mut HttpResponse,
-                            _| {
-        response.set_body(format!("url id: {}", request.params.get("id").unwrap()));
-    });
+    server.route("GET", "/id/:id<int>", extract(|Path(id): Path<u64>| format!("url id: {}", id)));
 
     server.get("/query", &|request: &HttpRequest,
                            response: &mut HttpResponse,
@@ -95,20 +96,58 @@ fn main() {
         ));
     });
 
+    server.add_route(Route {
+        method: String::from("GET"),
+        uri: String::from("/static/:path"),
+        middleware: Arc::new(vec![]),
+        handler: Arc::new(static_files("./examples/static")),
+    });
+
+    server.websocket("/echo", |_, mut websocket, _| loop {
+        match websocket.recv() {
+            Ok(Message::Text(text)) => {
+                if websocket.send_text(&text).is_err() {
+                    break;
+                }
+            }
+            Ok(Message::Binary(data)) => {
+                if websocket.send_binary(&data).is_err() {
+                    break;
+                }
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("{}", err);
+                break;
+            }
+        }
+    });
+
+    // Same process, different site for api.localhost
+    let mut api = Router::new();
+    api.get("/", &|request: &HttpRequest, response: &mut HttpResponse, _| {
+        response.set_header("Content-Type", "application/json".to_owned());
+        response.set_body(format!(
+            "{{\"host\": \"{}\"}}",
+            request.header("Host").unwrap_or("")
+        ));
+    });
+    server.host("api.localhost", api);
+
     let mutex = server.state();
     let mut state = mutex.lock().unwrap();
     state.insert(String::from("visits"), String::from("0"));
     drop(state);
     drop(mutex);
 
-    server.get("/httpreq", &|_request: &HttpRequest,
-                             response: &mut HttpResponse,
-                             _| {
-        response
-            .headers_mut()
-            .push(("Content-Type".to_owned(), "application/json".to_owned()));
-        let bin_response = send_http_request("http://httpbin.org/get").unwrap();
-        response.set_body(bin_response.body);
+    let mut httpbin = Proxy::new("http://httpbin.org/get");
+    httpbin.set_strip_prefix("/httpreq");
+    server.add_route(Route {
+        method: String::from("*"),
+        uri: String::from("/httpreq"),
+        middleware: Arc::new(vec![]),
+        handler: Arc::new(httpbin.handler()),
     });
 
     server.get(
@@ -125,5 +164,25 @@ fn main() {
         },
     );
 
+    server.serve_openapi("/openapi.json", "Moon web server", "0.1.0");
+    server.serve_metrics("/metrics");
+
+    server.set_error_handler(404, error_page(&read_file("./examples/templates/404.html")));
+    if env::var("DEV_MODE").is_ok() {
+        server.set_dev_mode(true);
+    }
+
+    server.after(conditional_get(ETagStrength::Strong));
+    server.after(byte_ranges());
+    server.after(AccessLog::stderr(LogFormat::Combined).middleware());
+
+    if let (Ok(cert), Ok(key)) = (env::var("TLS_CERT"), env::var("TLS_KEY")) {
+        let config = TlsConfig::from_pem_files(&cert, &key).unwrap();
+        server.set_tls(config, PlainHttp::Serve);
+    }
+    if let Ok(path) = env::var("UNIX_SOCKET") {
+        server.set_unix_socket(path, 0o660);
+    }
+
     server.start();
 }
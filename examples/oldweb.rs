@@ -1,15 +1,21 @@
 use std::collections::HashMap;
+use std::env;
 use std::fs;
-use std::sync::{mpsc::channel, Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use webserver::http::send_http_request_with_headers;
-use webserver::http::{HttpHeaders, HttpResponse, HttpServer};
+use webserver::http::server::Route;
+use webserver::http::Auth;
+use webserver::http::{conditional_get, ETagStrength, HttpError, HttpHeaders, HttpResponse, HttpServer, IntoResponse};
+use webserver::http::{Event, EventBroadcaster};
+use webserver::http::server::State;
+use webserver::http::{get, Path, RouteUrls};
 use webserver::json::JsonValue;
-use webserver::templating::render_with_partials;
+use webserver::templating::render_with_helpers;
 
-fn read_file(path: &'static str) -> String {
-    fs::read_to_string(path).unwrap()
+fn read_file(path: &'static str) -> Result<String, HttpError> {
+    Ok(fs::read_to_string(path)?)
 }
 
 fn html(res: &mut HttpResponse) {
@@ -26,43 +32,57 @@ fn headers() -> HttpHeaders {
     vec![("x-requested-with".to_owned(), ("rust lol".to_owned()))]
 }
 
-fn request(url: &str) -> JsonValue {
-    let response = send_http_request_with_headers(url, headers()).unwrap();
-    response.json()
+fn api_error(message: &str) -> HttpError {
+    HttpError::new(502, message)
 }
 
-fn get_max_id() -> u64 {
-    let response =
-        send_http_request_with_headers(&format!("{}/maxitem.json", HN_API_URL), headers()).unwrap();
-    response.json().as_number().expect("max_id is a number") as u64
+fn request(url: &str) -> Result<JsonValue, HttpError> {
+    let response = send_http_request_with_headers(url, headers())
+        .map_err(|err| api_error("Error fetching from HN API").with_source(err.to_string()))?;
+    Ok(response.json())
 }
 
-fn get_updates() -> JsonValue {
-    let response =
-        send_http_request_with_headers(&format!("{}/updates.json", HN_API_URL), headers()).unwrap();
-    response.json()
+fn lock(items_cache: &ItemsCacheMutex) -> Result<MutexGuard<'_, ItemsCache>, HttpError> {
+    items_cache
+        .lock()
+        .map_err(|_| HttpError::internal("Items cache is poisoned"))
 }
 
-fn get_changed_items() -> Vec<u64> {
-    match get_updates().as_object() {
-        Some(updates) => {
-            return updates
-                .get("items")
-                .unwrap()
-                .as_array()
-                .expect("updates is an array")
-                .into_iter()
-                .map(|value| value.as_number().expect("value is a number") as u64)
-                .collect()
-        }
-        None => return Vec::new(),
-    };
+fn ids(values: &JsonValue) -> Result<Vec<u64>, HttpError> {
+    values
+        .as_array()
+        .ok_or_else(|| api_error("Expected an array of ids"))?
+        .iter()
+        .map(|id| {
+            id.as_number()
+                .map(|id| id as u64)
+                .ok_or_else(|| api_error("Expected ids to be numbers"))
+        })
+        .collect()
+}
+
+fn get_max_id() -> Result<u64, HttpError> {
+    let max_id = request(&format!("{}/maxitem.json", HN_API_URL))?;
+    max_id
+        .as_number()
+        .map(|id| id as u64)
+        .ok_or_else(|| api_error("max_id is not a number"))
+}
+
+fn get_changed_items() -> Result<Vec<u64>, HttpError> {
+    match request(&format!("{}/updates.json", HN_API_URL))?.as_object() {
+        Some(updates) => match updates.get("items") {
+            Some(items) => ids(items),
+            None => Err(api_error("updates don't have items")),
+        },
+        None => Ok(Vec::new()),
+    }
 }
 
 fn get_time_ago(time: f64) -> String {
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
-        .unwrap()
+        .unwrap_or_default()
         .as_secs() as f64;
     let minutes = (now - time) / 60f64;
     if minutes < 60f64 {
@@ -80,111 +100,88 @@ fn get_time_ago(time: f64) -> String {
     return format!("{} years ago", years.round());
 }
 
-fn fetch_item(items_cache: &ItemsCacheMutex, id: u64, force: bool) -> JsonValue {
+fn add_relative_time(item: &mut HashMap<String, JsonValue>) -> Result<(), HttpError> {
+    let time = item
+        .get("time")
+        .and_then(|time| time.as_number())
+        .ok_or_else(|| api_error("item doesn't have a time"))?;
+    item.insert(
+        "relative_time".to_owned(),
+        JsonValue::String(get_time_ago(time)),
+    );
+    Ok(())
+}
+
+fn kids_ids(item: &HashMap<String, JsonValue>) -> Result<Vec<u64>, HttpError> {
+    match item.get("kids") {
+        Some(kids) => ids(kids),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn fetch_item(items_cache: &ItemsCacheMutex, id: u64, force: bool) -> Result<JsonValue, HttpError> {
     if !force {
-        let item_cache_read = items_cache.lock().unwrap();
-        if item_cache_read.contains_key(&id) {
+        if let Some(item) = lock(items_cache)?.get(&id) {
             println!("Cache hit for {}", id);
-            return item_cache_read.get(&id).unwrap().clone();
+            return Ok(item.clone());
         }
     }
-    let response =
-        send_http_request_with_headers(&format!("{}/item/{}.json", HN_API_URL, id), headers()).unwrap();
-    let item = response.json();
+    let item = request(&format!("{}/item/{}.json", HN_API_URL, id))?;
     let item = match item {
         JsonValue::Object(_) => item,
         _ => {
             eprintln!("API item id {} is not an object {:?}", id, item);
-            return JsonValue::Null;
+            return Ok(JsonValue::Null);
         }
     };
     println!("fetched item {}", id);
-    let clone = item.clone();
-    let mut item_cache = items_cache.lock().unwrap();
-    item_cache.insert(id, item);
-    drop(item_cache);
-    clone
+    lock(items_cache)?.insert(id, item.clone());
+    Ok(item)
 }
 
-fn get_item(items_cache: ItemsCacheMutex, id: u64) -> JsonValue {
-    let mut item = fetch_item(&items_cache, id, false);
-
-    if item == JsonValue::Null {
-        return item;
-    }
+fn get_item(items_cache: ItemsCacheMutex, id: u64) -> Result<JsonValue, HttpError> {
+    let mut item = fetch_item(&items_cache, id, false)?;
 
-    let item_map = item.as_object_mut().expect("item is an object");
-    let time = item_map
-        .get(&"time".to_owned())
-        .expect("item doesn't have time")
-        .as_number()
-        .expect("time is a number");
-    item_map.insert(
-        "relative_time".to_owned(),
-        JsonValue::String(get_time_ago(time)),
-    );
-    let kids = match item_map.get("kids") {
-        Some(value) => value.as_array().expect("kids is an array"),
-        None => return item,
+    let item_map = match item.as_object_mut() {
+        Some(item_map) => item_map,
+        None => return Ok(item),
     };
-    if kids.is_empty() {
-        return item;
+    add_relative_time(item_map)?;
+    let kids_ids = kids_ids(item_map)?;
+    if kids_ids.is_empty() {
+        return Ok(item);
     }
-    let kids_ids: Vec<u64> = kids
-        .into_iter()
-        .map(|id| {
-            return match id {
-                JsonValue::Number(id) => *id as u64,
-                _ => panic!("expected ids to be numbers"),
-            };
-        })
-        .collect();
-    let kid_items = map_id_to_objects(&items_cache, kids_ids, true);
+    let kid_items = map_id_to_objects(&items_cache, kids_ids, true)?;
     item_map.insert("kids".to_owned(), kid_items);
 
-    item
+    Ok(item)
 }
 
-fn get_items(items_cache: &ItemsCacheMutex, ids: &Vec<u64>) -> JsonValue {
-    let (tx, rx) = channel();
-    let mut joins = Vec::new();
-    let stories_len = ids.len();
-    for (i, id) in ids.into_iter().enumerate() {
-        let tx = tx.clone();
-        let id = id.clone();
-        let mutex = items_cache.clone();
-        joins.push(thread::spawn(move || {
-            let item = fetch_item(&mutex, id, false);
-            drop(mutex);
-            tx.send((i, item)).expect("Error sending item");
-        }));
-    }
-    let mut stories_results = Vec::with_capacity(stories_len);
-    for _ in 0..stories_len {
-        let (i, item) = rx.recv().expect("Error receiving item");
-        stories_results.push((i, item));
-    }
-
-    for child in joins {
-        child.join().unwrap();
-    }
-
-    stories_results.sort_by_key(|(i, _)| *i);
-
-    let stories_results = stories_results
-        .into_iter()
-        .map(|(_, value)| value.clone())
+fn get_items(items_cache: &ItemsCacheMutex, ids: &[u64]) -> Result<Vec<JsonValue>, HttpError> {
+    let joins: Vec<_> = ids
+        .iter()
+        .map(|&id| {
+            let mutex = items_cache.clone();
+            thread::spawn(move || fetch_item(&mutex, id, false))
+        })
         .collect();
 
-    JsonValue::Array(stories_results)
+    joins
+        .into_iter()
+        .map(|child| {
+            child
+                .join()
+                .map_err(|_| HttpError::internal("Error fetching item"))?
+        })
+        .collect()
 }
 
-fn map_id_to_objects(items_cache: &ItemsCacheMutex, ids: Vec<u64>, fetch_kids: bool) -> JsonValue {
+fn map_id_to_objects(items_cache: &ItemsCacheMutex, ids: Vec<u64>, fetch_kids: bool) -> Result<JsonValue, HttpError> {
     if ids.is_empty() {
-        return JsonValue::Null;
+        return Ok(JsonValue::Null);
     }
-    let mut items = get_items(&items_cache, &ids);
-    for item in items.as_array_mut().expect("items is an array") {
+    let mut items = get_items(&items_cache, &ids)?;
+    for item in items.iter_mut() {
         let item = match item {
             JsonValue::Object(item) => item,
             _ => {
@@ -192,85 +189,65 @@ fn map_id_to_objects(items_cache: &ItemsCacheMutex, ids: Vec<u64>, fetch_kids: b
             }
         };
 
-        let time = item
-            .get(&"time".to_owned())
-            .expect("item doesn't have time")
-            .as_number()
-            .expect("time is a number");
-        item.insert(
-            "relative_time".to_owned(),
-            JsonValue::String(get_time_ago(time)),
-        );
+        add_relative_time(item)?;
 
         if !fetch_kids {
             continue;
         }
 
-        let kids = match item.get("kids") {
-            Some(value) => value.as_array().expect("kids is an array"),
-            None => continue,
-        };
-        if kids.is_empty() {
+        let ids = kids_ids(item)?;
+        if ids.is_empty() {
             continue;
         }
-        let ids: Vec<u64> = kids
-            .into_iter()
-            .map(|id| {
-                return match id {
-                    JsonValue::Number(id) => *id as u64,
-                    _ => panic!("expected ids to be numbers"),
-                };
-            })
-            .collect();
-        let items = map_id_to_objects(items_cache, ids, true);
+        let items = map_id_to_objects(items_cache, ids, true)?;
         item.insert("kids".to_owned(), items);
     }
 
-    return items;
+    Ok(JsonValue::Array(items))
 }
 
-fn fetch_stories(path: &str) -> JsonValue {
-    request(&format!("{}/{}.json", HN_API_URL, path))
+fn fetch_stories(path: &str) -> Result<Vec<u64>, HttpError> {
+    ids(&request(&format!("{}/{}.json", HN_API_URL, path))?)
 }
 
-fn get_stories(items_cache: &ItemsCacheMutex, path: &str) -> JsonValue {
-    let stories_ids = fetch_stories(path);
-    let stories_ids: Vec<u64> = stories_ids
-        .as_array()
-        .expect("stories_ids is an array")
-        .into_iter()
-        .take(30)
-        .map(|id| id.as_number().expect("id is a number") as u64)
-        .collect();
+fn get_stories(items_cache: &ItemsCacheMutex, path: &str) -> Result<JsonValue, HttpError> {
+    let stories_ids = fetch_stories(path)?.into_iter().take(30).collect();
     map_id_to_objects(items_cache, stories_ids, false)
 }
 
-fn get_top_stories(items_cache: &ItemsCacheMutex) -> JsonValue {
+fn get_top_stories(items_cache: &ItemsCacheMutex) -> Result<JsonValue, HttpError> {
     get_stories(items_cache, "topstories")
 }
 
 fn warmup(server: &HttpServer<ItemsCache>) {
     let items_cache = server.state().clone();
     thread::spawn(move || {
-        let top_stories = fetch_stories("topstories");
-        let kids: Vec<u64> = top_stories
-            .as_array()
-            .expect("top_stories is an array")
-            .into_iter()
-            .map(|value| value.as_number().expect("value is a number") as u64)
-            .collect();
-        for id in kids {
-            map_id_to_objects(&items_cache, vec![id], true);
+        let top_stories = match fetch_stories("topstories") {
+            Ok(top_stories) => top_stories,
+            Err(err) => return eprintln!("Error warming up the cache: {}", err),
+        };
+        for id in top_stories {
+            if let Err(err) = map_id_to_objects(&items_cache, vec![id], true) {
+                eprintln!("Error warming up item {}: {}", id, err);
+            }
         }
     });
 }
 
-fn watch_changed_items(server: &HttpServer<ItemsCache>) {
+fn watch_changed_items(server: &HttpServer<ItemsCache>, updates: EventBroadcaster) {
     let items_cache = server.state().clone();
     thread::spawn(move || loop {
         println!("fetching updates");
-        for id in get_changed_items() {
-            fetch_item(&items_cache, id, true);
+        match get_changed_items() {
+            Ok(ids) => {
+                for id in ids {
+                    match fetch_item(&items_cache, id, true) {
+                        Ok(_) => updates.send(Event::new(&id.to_string()).event("item")),
+                        Err(err) => eprintln!("Error updating item {}: {}", id, err),
+                    }
+                }
+            }
+            Err(err) => eprintln!("Error fetching updates: {}", err),
         }
         thread::sleep(Duration::from_secs(60));
     });
@@ -279,11 +256,20 @@ fn watch_changed_items(server: &HttpServer<ItemsCache>) {
 fn watch_new_items(server: &HttpServer<ItemsCache>) {
     let items_cache = server.state().clone();
     thread::spawn(move || {
-        let mut max_id = get_max_id();
+        let mut max_id = match get_max_id() {
+            Ok(max_id) => max_id,
+            Err(err) => return eprintln!("Error fetching the max item id, not watching new items: {}", err),
+        };
         loop {
             thread::sleep(Duration::from_secs(20));
             println!("fetching new items from {}", max_id);
-            let new_max_id = get_max_id();
+            let new_max_id = match get_max_id() {
+                Ok(new_max_id) => new_max_id,
+                Err(err) => {
+                    eprintln!("Error fetching the max item id: {}", err);
+                    continue;
+                }
+            };
             if new_max_id < 27017975 {
                 // not possible to get something older than this id
                 eprintln!(
@@ -296,75 +282,89 @@ fn watch_new_items(server: &HttpServer<ItemsCache>) {
                 continue;
             }
             for id in max_id + 1..new_max_id + 1 {
-                fetch_item(&items_cache, id, false);
+                if let Err(err) = fetch_item(&items_cache, id, false) {
+                    eprintln!("Error fetching item {}: {}", id, err);
+                }
             }
             max_id = new_max_id;
         }
     });
 }
 
+#[get("/hn/:id<int>")]
+fn hn_item(
+    Path(id): Path<u64>,
+    items_cache: State<ItemsCache>,
+    urls: RouteUrls,
+) -> Result<String, HttpError> {
+    let item = get_item(items_cache, id)?;
+
+    if item == JsonValue::Null {
+        return Err(HttpError::new(502, "Error fetching item from HN API"));
+    }
+
+    let layout = read_file("./examples/templates/oldweb/layout.hbs")?;
+    let hnitem = read_file("./examples/templates/oldweb/hnitem.hbs")?;
+    let hncomment = read_file("./examples/templates/oldweb/partials/hncomment.hbs")?;
+    let hnitemsummary = read_file("./examples/templates/oldweb/partials/hnitemsummary.hbs")?;
+    let mut partials = HashMap::new();
+    partials.insert("body".to_owned(), hnitem);
+    partials.insert("hncomment".to_owned(), hncomment);
+    partials.insert("hnitemsummary".to_owned(), hnitemsummary);
+    Ok(render_with_helpers(&layout, &item, &partials, &urls.helpers()))
+}
+
 fn oldweb(server: &mut HttpServer<ItemsCache>) {
-    server.get("/hn", &|_req, mut res, items_cache| {
+    server.route("GET", "/hn", |req, mut res, items_cache| -> Result<(), HttpError> {
         html(&mut res);
-        let hn_response = get_top_stories(&items_cache);
+        let hn_response = get_top_stories(&items_cache)?;
         let mut context = HashMap::new();
         context.insert("stories".to_owned(), hn_response);
-        let layout = read_file("./examples/templates/oldweb/layout.hbs");
-        let hn = read_file("./examples/templates/oldweb/hn.hbs");
-        let hnitemsummary = read_file("./examples/templates/oldweb/partials/hnitemsummary.hbs");
+        let layout = read_file("./examples/templates/oldweb/layout.hbs")?;
+        let hn = read_file("./examples/templates/oldweb/hn.hbs")?;
+        let hnitemsummary = read_file("./examples/templates/oldweb/partials/hnitemsummary.hbs")?;
         let mut partials = HashMap::new();
         partials.insert("body".to_owned(), hn);
         partials.insert("hnitemsummary".to_owned(), hnitemsummary);
-        res.set_body(render_with_partials(
+        res.set_body(render_with_helpers(
             &layout,
             &JsonValue::Object(context),
             &partials,
+            &req.urls().helpers(),
         ));
+        Ok(())
     });
+    server.set_route_name("hn", "GET", "/hn");
 
-    server.get("/hn/:id", &|req, res, items_cache| {
-        let id = match req.params.get("id") {
-            Some(id) => id,
-            None => panic!("hn id not found"),
-        };
-        let id = match id.parse::<u64>() {
-            Ok(id) => id,
-            Err(_) => {
-                res.set_status_code(400);
-                res.set_body(String::from("Item id has to be a number"));
-                return;
-            }
-        };
-        let item = get_item(items_cache, id);
-
-        if item == JsonValue::Null {
-            res.set_status_code(500);
-            res.set_body(String::from("Error fetching item from HN API"));
-            return;
-        }
+    server.mount(hn_item);
 
-        let layout = read_file("./examples/templates/oldweb/layout.hbs");
-        let hnitem = read_file("./examples/templates/oldweb/hnitem.hbs");
-        let hncomment = read_file("./examples/templates/oldweb/partials/hncomment.hbs");
-        let hnitemsummary = read_file("./examples/templates/oldweb/partials/hnitemsummary.hbs");
-        let mut partials = HashMap::new();
-        partials.insert("body".to_owned(), hnitem);
-        partials.insert("hncomment".to_owned(), hncomment);
-        partials.insert("hnitemsummary".to_owned(), hnitemsummary);
-        res.set_body(render_with_partials(&layout, &item, &partials));
+    // without OLDWEB_ADMIN_TOKEN every token is rejected
+    let admin_token = env::var("OLDWEB_ADMIN_TOKEN").ok();
+    let auth = Auth::bearer("oldweb admin", move |credentials| match &admin_token {
+        Some(token) if credentials.is_bearer(token) => Some(()),
+        _ => None,
     });
-
-    server.get("/hn/cache-size", &|_, res, items_cache: ItemsCacheMutex| {
-        res.set_body(items_cache.lock().unwrap().len().to_string());
+    server.add_route(Route {
+        method: "GET".to_owned(),
+        uri: "/hn/cache-size".to_owned(),
+        middleware: Arc::new(vec![Box::new(auth.middleware())]),
+        handler: Arc::new(|_, res, items_cache: ItemsCacheMutex| {
+            lock(&items_cache)
+                .map(|items_cache| items_cache.len().to_string())
+                .into_response(res);
+        }),
     });
 }
 
 fn main() {
     let items_cache = HashMap::new();
     let mut server = HttpServer::new(items_cache);
-    watch_changed_items(&server);
+    let updates = EventBroadcaster::new(500);
+    watch_changed_items(&server, updates.clone());
     warmup(&server);
     watch_new_items(&server);
     oldweb(&mut server);
+    server.event_stream("/hn/updates", move |_, stream, _| stream.stream_from(&updates));
+    server.after(conditional_get(ETagStrength::Weak));
     server.start();
 }
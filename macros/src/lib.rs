@@ -0,0 +1,637 @@
+//! Attributes declaring the route of a handler, like `#[get("/hn/:id")]`, for the
+//! `webserver` crate. They turn the function into a unit struct of the same name
+//! implementing `Endpoint`, which `HttpServer::mount` registers. The arguments of the
+//! function are extractors, see `webserver::http::extract`.
+//!
+//! The route is named after the function for `url_for`, another name can be given with
+//! `#[get("/hn/:id", name = "item")]`.
+//!
+//! Route patterns are checked while compiling:
+//!
+//! ```compile_fail
+//! #[webserver_macros::get("/items/:id/edit")]
+//! fn edit() {}
+//! ```
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use std::iter::FromIterator;
+
+#[proc_macro_attribute]
+pub fn get(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(Some("GET"), args, item)
+}
+
+#[proc_macro_attribute]
+pub fn post(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(Some("POST"), args, item)
+}
+
+#[proc_macro_attribute]
+pub fn put(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(Some("PUT"), args, item)
+}
+
+#[proc_macro_attribute]
+pub fn patch(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(Some("PATCH"), args, item)
+}
+
+#[proc_macro_attribute]
+pub fn delete(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(Some("DELETE"), args, item)
+}
+
+/// Route with any method: `#[route("OPTIONS", "/items")]`.
+#[proc_macro_attribute]
+pub fn route(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(None, args, item)
+}
+
+struct Error {
+    message: String,
+    span: Span,
+}
+
+impl Error {
+    fn new(message: &str, span: Span) -> Self {
+        Error {
+            message: message.to_owned(),
+            span,
+        }
+    }
+
+    fn into_tokens(self) -> TokenStream {
+        let tokens: TokenStream = format!("compile_error!({:?});", self.message)
+            .parse()
+            .unwrap();
+        respan(tokens, self.span)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn respan(tokens: TokenStream, span: Span) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            if let TokenTree::Group(group) = &token {
+                token =
+                    TokenTree::Group(Group::new(group.delimiter(), respan(group.stream(), span)));
+            }
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
+
+fn expand(method: Option<&str>, args: TokenStream, item: TokenStream) -> TokenStream {
+    match try_expand(method, args, item.clone()) {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            let mut tokens = err.into_tokens();
+            tokens.extend(item);
+            tokens
+        }
+    }
+}
+
+fn try_expand(method: Option<&str>, args: TokenStream, item: TokenStream) -> Result<TokenStream> {
+    let mut args: Vec<TokenTree> = args.into_iter().collect();
+    let method = match method {
+        Some(method) => Literal::string(method),
+        None => route_method(&mut args)?,
+    };
+    let (uri, name) = match args.as_slice() {
+        [TokenTree::Literal(uri)] => (uri.clone(), None),
+        [TokenTree::Literal(uri), TokenTree::Punct(comma), TokenTree::Ident(key), TokenTree::Punct(equals), TokenTree::Literal(name)]
+            if comma.as_char() == ',' && key.to_string() == "name" && equals.as_char() == '=' =>
+        {
+            string_value(name)?;
+            (uri.clone(), Some(name.clone()))
+        }
+        _ => {
+            return Err(Error::new(
+                "Expected the route as a string, like \"/items/:id\", and optionally its \
+                 name, like name = \"item\"",
+                Span::call_site(),
+            ))
+        }
+    };
+    let pattern = string_value(&uri)?;
+    let parameter = check_route(&pattern).map_err(|message| Error::new(&message, uri.span()))?;
+    let function = Function::parse(item)?;
+    let name = name.unwrap_or_else(|| Literal::string(&function.name.to_string()));
+    if parameter.is_none() {
+        if let Some(arg) = function.args.iter().find(|arg| arg.is_path()) {
+            return Err(Error::new(
+                &format!(
+                    "`Path` needs a route with a parameter, like \"{}/:id\"",
+                    pattern
+                ),
+                arg.span,
+            ));
+        }
+    }
+    Ok(function.into_endpoint(method, uri, name))
+}
+
+/// Takes `"METHOD",` off the arguments of `#[route]`.
+fn route_method(args: &mut Vec<TokenTree>) -> Result<Literal> {
+    let method = match args.first() {
+        Some(TokenTree::Literal(method)) => method.clone(),
+        _ => {
+            return Err(Error::new(
+                "Expected a method and a route, like #[route(\"OPTIONS\", \"/items\")]",
+                Span::call_site(),
+            ))
+        }
+    };
+    let name = string_value(&method)?;
+    if name.is_empty() || !name.chars().all(|ch| ch.is_ascii_uppercase()) {
+        return Err(Error::new(
+            &format!("Invalid method `{}`, it has to be uppercase", name),
+            method.span(),
+        ));
+    }
+    match args.get(1) {
+        Some(TokenTree::Punct(punct)) if punct.as_char() == ',' => {}
+        _ => {
+            return Err(Error::new(
+                "Expected a route after the method",
+                method.span(),
+            ))
+        }
+    }
+    args.drain(..2);
+    Ok(method)
+}
+
+fn string_value(literal: &Literal) -> Result<String> {
+    let text = literal.to_string();
+    match text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+    {
+        Some(value) if !value.contains('\\') => Ok(value.to_owned()),
+        _ => Err(Error::new(
+            "Expected a plain string literal",
+            literal.span(),
+        )),
+    }
+}
+
+/// Checks a route the way the router reads it: an absolute path with at most one parameter,
+/// which has to be its last segment. Returns the name of the parameter.
+fn check_route(uri: &str) -> std::result::Result<Option<&str>, String> {
+    if !uri.starts_with('/') {
+        return Err(format!("Route `{}` has to start with `/`", uri));
+    }
+    if uri.contains(|ch: char| ch == '?' || ch == '#' || ch.is_whitespace()) {
+        return Err(format!(
+            "Route `{}` can't have a query, a fragment or spaces",
+            uri
+        ));
+    }
+    if uri.contains("//") {
+        return Err(format!("Route `{}` has an empty segment", uri));
+    }
+    let position = match uri.find(':') {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    let parameter = &uri[position + 1..];
+    if parameter.contains(':') {
+        return Err(format!(
+            "Route `{}` has more than one parameter, routes can only have one",
+            uri
+        ));
+    }
+    if !uri[..position].ends_with('/') {
+        return Err(format!(
+            "The parameter of route `{}` has to start a segment",
+            uri
+        ));
+    }
+    if parameter.contains('/') {
+        return Err(format!(
+            "The parameter of route `{}` has to be its last segment",
+            uri
+        ));
+    }
+    let (name, constraint) = match parameter.find('<') {
+        Some(start) if parameter.ends_with('>') => (
+            &parameter[..start],
+            Some(&parameter[start + 1..parameter.len() - 1]),
+        ),
+        Some(_) => {
+            return Err(format!(
+                "The constraint of route `{}` has to end with `>`",
+                uri
+            ))
+        }
+        None => (parameter, None),
+    };
+    if !is_identifier(name) {
+        return Err(format!(
+            "Invalid parameter name `{}` in route `{}`",
+            name, uri
+        ));
+    }
+    if let Some(constraint) = constraint {
+        if !is_constraint(constraint) {
+            return Err(format!(
+                "Invalid parameter constraint <{}> in route `{}`",
+                constraint, uri
+            ));
+        }
+    }
+    Ok(Some(name))
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// `int`, `uuid`, the name of a custom constraint or a character class like `[a-z0-9-]+`.
+/// The router checks the ranges of the class when the route is added.
+fn is_constraint(constraint: &str) -> bool {
+    let class = match constraint.strip_prefix('[') {
+        Some(class) => class,
+        None => return is_identifier(constraint),
+    };
+    let end = match class.rfind(']') {
+        Some(end) => end,
+        None => return false,
+    };
+    let body = &class[..end];
+    let quantifier = &class[end + 1..];
+    let counts = quantifier
+        .strip_prefix('{')
+        .and_then(|quantifier| quantifier.strip_suffix('}'));
+    let valid_quantifier = match counts {
+        Some(counts) => match counts.split_once(',') {
+            Some((min, max)) => {
+                min.parse::<usize>().is_ok() && (max.is_empty() || max.parse::<usize>().is_ok())
+            }
+            None => counts.parse::<usize>().is_ok(),
+        },
+        None => ["", "+", "*"].contains(&quantifier),
+    };
+    !body.strip_prefix('^').unwrap_or(body).is_empty() && valid_quantifier
+}
+
+struct Argument {
+    ty: Vec<TokenTree>,
+    span: Span,
+}
+
+impl Argument {
+    /// Whether the type is `Path<...>`, with or without a path before it.
+    fn is_path(&self) -> bool {
+        let name = self
+            .ty
+            .iter()
+            .take_while(|token| !matches!(token, TokenTree::Punct(punct) if punct.as_char() == '<'))
+            .filter_map(|token| match token {
+                TokenTree::Ident(ident) => Some(ident.to_string()),
+                _ => None,
+            })
+            .last();
+        name.as_deref() == Some("Path")
+    }
+}
+
+/// Handler function, split in the parts the endpoint is built from.
+struct Function {
+    docs: Vec<TokenTree>,
+    attributes: Vec<TokenTree>,
+    visibility: Vec<TokenTree>,
+    name: Ident,
+    // `fn` to the end of the body
+    item: Vec<TokenTree>,
+    args: Vec<Argument>,
+}
+
+impl Function {
+    fn parse(item: TokenStream) -> Result<Self> {
+        let mut tokens = item.into_iter().peekable();
+        let mut docs = Vec::new();
+        let mut attributes = Vec::new();
+        while let Some(TokenTree::Punct(punct)) = tokens.peek() {
+            if punct.as_char() != '#' {
+                break;
+            }
+            let pound = tokens.next().unwrap();
+            let attribute = match tokens.next() {
+                Some(attribute @ TokenTree::Group(_)) => attribute,
+                _ => return Err(Error::new("Expected an attribute", pound.span())),
+            };
+            let is_doc = match &attribute {
+                TokenTree::Group(group) => matches!(
+                    group.stream().into_iter().next(),
+                    Some(TokenTree::Ident(ident)) if ident.to_string() == "doc"
+                ),
+                _ => false,
+            };
+            let target = if is_doc { &mut docs } else { &mut attributes };
+            target.push(pound);
+            target.push(attribute);
+        }
+
+        let mut visibility = Vec::new();
+        if let Some(TokenTree::Ident(ident)) = tokens.peek() {
+            if ident.to_string() == "pub" {
+                visibility.push(tokens.next().unwrap());
+                if let Some(TokenTree::Group(group)) = tokens.peek() {
+                    if group.delimiter() == Delimiter::Parenthesis {
+                        visibility.push(tokens.next().unwrap());
+                    }
+                }
+            }
+        }
+
+        let mut item = Vec::new();
+        match tokens.next() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "fn" => {
+                item.push(TokenTree::Ident(ident))
+            }
+            Some(token) => {
+                return Err(Error::new(
+                    "Routes can only be declared on plain functions, without async, const, \
+                     unsafe or extern",
+                    token.span(),
+                ))
+            }
+            None => return Err(Error::new("Expected a function", Span::call_site())),
+        }
+        let name = match tokens.next() {
+            Some(TokenTree::Ident(name)) => name,
+            _ => {
+                return Err(Error::new(
+                    "Expected the name of the function",
+                    Span::call_site(),
+                ))
+            }
+        };
+        item.push(TokenTree::Ident(name.clone()));
+        let args = match tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                let args = parse_arguments(group.stream())?;
+                item.push(TokenTree::Group(group));
+                args
+            }
+            Some(token) => {
+                return Err(Error::new(
+                    "Routes can't be declared on generic functions",
+                    token.span(),
+                ))
+            }
+            None => return Err(Error::new("Expected the arguments", name.span())),
+        };
+        for token in tokens {
+            if let TokenTree::Ident(ident) = &token {
+                if ident.to_string() == "where" {
+                    return Err(Error::new(
+                        "Routes can't be declared on generic functions",
+                        ident.span(),
+                    ));
+                }
+            }
+            item.push(token);
+        }
+
+        Ok(Function {
+            docs,
+            attributes,
+            visibility,
+            name,
+            item,
+            args,
+        })
+    }
+
+    /// String literal of the first `#[doc = "..."]`, the first line of the doc comment.
+    fn summary(&self) -> Option<Literal> {
+        self.docs.iter().find_map(|token| match token {
+            TokenTree::Group(group) => match group.stream().into_iter().collect::<Vec<_>>()[..] {
+                [_, TokenTree::Punct(ref eq), TokenTree::Literal(ref literal)]
+                    if eq.as_char() == '=' && literal.to_string().starts_with('"') =>
+                {
+                    Some(literal.clone())
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    /// ```ignore
+    /// #[allow(non_camel_case_types)]
+    /// pub struct name;
+    ///
+    /// impl<__T: Send + 'static> ::webserver::http::Endpoint<__T> for name
+    /// where
+    ///     Argument: ::webserver::http::FromRequest<__T>,
+    /// {
+    ///     fn mount(self, router: &mut ::webserver::http::Router<__T>) {
+    ///         fn name(...) { ... }
+    ///         router.route(method, uri, ::webserver::http::extract(name));
+    ///         router.set_route_name(route_name, method, uri);
+    ///         router.set_route_summary(method, uri, "first doc line".trim());
+    ///     }
+    /// }
+    /// ```
+    fn into_endpoint(self, method: Literal, uri: Literal, route_name: Literal) -> TokenStream {
+        let summary = self.summary();
+        let mut tokens = TokenStream::from_iter(self.docs);
+        tokens.extend(parse("#[allow(non_camel_case_types)]"));
+        tokens.extend(self.visibility);
+        tokens.extend(parse("struct"));
+        tokens.extend(vec![
+            TokenTree::Ident(self.name.clone()),
+            TokenTree::Punct(Punct::new(';', Spacing::Alone)),
+        ]);
+
+        tokens.extend(parse(
+            "impl<__T: Send + 'static> ::webserver::http::Endpoint<__T> for",
+        ));
+        tokens.extend(Some(TokenTree::Ident(self.name.clone())));
+        if !self.args.is_empty() {
+            tokens.extend(parse("where"));
+            for arg in self.args {
+                tokens.extend(arg.ty);
+                tokens.extend(parse(": ::webserver::http::FromRequest<__T>,"));
+            }
+        }
+
+        let mut mount = TokenStream::from_iter(self.attributes);
+        mount.extend(self.item);
+        mount.extend(parse("router.route"));
+        let method_literal = method.clone();
+        let mut route = TokenStream::from_iter(vec![
+            TokenTree::Literal(method),
+            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+            TokenTree::Literal(uri.clone()),
+            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+        ]);
+        route.extend(parse("::webserver::http::extract"));
+        route.extend(Some(group(
+            Delimiter::Parenthesis,
+            TokenStream::from(TokenTree::Ident(self.name)),
+        )));
+        mount.extend(Some(group(Delimiter::Parenthesis, route)));
+        mount.extend(parse(";"));
+        mount.extend(parse("router.set_route_name"));
+        mount.extend(Some(group(
+            Delimiter::Parenthesis,
+            TokenStream::from_iter(vec![
+                TokenTree::Literal(route_name),
+                TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+                TokenTree::Literal(method_literal.clone()),
+                TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+                TokenTree::Literal(uri.clone()),
+            ]),
+        )));
+        mount.extend(parse(";"));
+        if let Some(summary) = summary {
+            mount.extend(parse("router.set_route_summary"));
+            let mut args = TokenStream::from_iter(vec![
+                TokenTree::Literal(method_literal),
+                TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+                TokenTree::Literal(uri),
+                TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+                TokenTree::Literal(summary),
+            ]);
+            args.extend(parse(".trim()"));
+            mount.extend(Some(group(Delimiter::Parenthesis, args)));
+            mount.extend(parse(";"));
+        }
+
+        let mut body = parse("fn mount(self, router: &mut ::webserver::http::Router<__T>)");
+        body.extend(Some(group(Delimiter::Brace, mount)));
+        tokens.extend(Some(group(Delimiter::Brace, body)));
+        tokens
+    }
+}
+
+fn parse(code: &str) -> TokenStream {
+    code.parse().unwrap()
+}
+
+fn group(delimiter: Delimiter, stream: TokenStream) -> TokenTree {
+    TokenTree::Group(Group::new(delimiter, stream))
+}
+
+/// Splits `pattern: Type, ...` at the commas and colons outside of generics.
+fn parse_arguments(stream: TokenStream) -> Result<Vec<Argument>> {
+    let mut args = Vec::new();
+    let mut tokens = stream.into_iter().peekable();
+    while tokens.peek().is_some() {
+        let mut pattern = Vec::new();
+        let mut ty = Vec::new();
+        let mut in_type = false;
+        let mut depth = 0;
+        let mut previous: Option<Punct> = None;
+        for token in tokens.by_ref() {
+            if let TokenTree::Punct(punct) = &token {
+                let after_joint = previous
+                    .as_ref()
+                    .is_some_and(|previous| previous.spacing() == Spacing::Joint);
+                match punct.as_char() {
+                    ',' if depth == 0 => break,
+                    '<' => depth += 1,
+                    // Not the one of `->`
+                    '>' if !(after_joint && previous.as_ref().unwrap().as_char() == '-') => {
+                        depth -= 1
+                    }
+                    ':' if !in_type && punct.spacing() == Spacing::Alone && !after_joint => {
+                        in_type = true;
+                        previous = None;
+                        continue;
+                    }
+                    _ => {}
+                }
+                previous = Some(punct.clone());
+            } else {
+                previous = None;
+            }
+            if in_type {
+                ty.push(token);
+            } else {
+                pattern.push(token);
+            }
+        }
+        let span = pattern
+            .first()
+            .map(|token| token.span())
+            .unwrap_or_else(Span::call_site);
+        if !in_type {
+            return Err(Error::new("Routes can't be declared on methods", span));
+        }
+        if let Some(TokenTree::Punct(punct)) = ty.first() {
+            if punct.as_char() == '&' {
+                return Err(Error::new(
+                    "Handler arguments are extractors like `Path<u64>`, `Json<T>` or \
+                     `HttpRequest`, they can't be references",
+                    punct.span(),
+                ));
+            }
+        }
+        let span = ty.first().map(|token| token.span()).unwrap_or(span);
+        args.push(Argument { ty, span });
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_routes() {
+        assert_eq!(check_route("/"), Ok(None));
+        assert_eq!(check_route("/hn/top"), Ok(None));
+        assert_eq!(check_route("/hn/:id"), Ok(Some("id")));
+        assert_eq!(check_route("/:item_id"), Ok(Some("item_id")));
+
+        assert!(check_route("hn")
+            .unwrap_err()
+            .contains("has to start with `/`"));
+        assert!(check_route("/hn?page=1").unwrap_err().contains("query"));
+        assert!(check_route("/hn//top")
+            .unwrap_err()
+            .contains("empty segment"));
+        assert!(check_route("/:a/:b").unwrap_err().contains("more than one"));
+        assert!(check_route("/hn:id")
+            .unwrap_err()
+            .contains("start a segment"));
+        assert!(check_route("/:id/edit")
+            .unwrap_err()
+            .contains("last segment"));
+        assert!(check_route("/hn/:")
+            .unwrap_err()
+            .contains("Invalid parameter"));
+        assert!(check_route("/hn/:id-x")
+            .unwrap_err()
+            .contains("Invalid parameter"));
+
+        assert_eq!(check_route("/hn/:id<int>"), Ok(Some("id")));
+        assert_eq!(check_route("/tags/:tag<[a-z0-9-]{2,}>"), Ok(Some("tag")));
+        assert_eq!(check_route("/users/:name<username>"), Ok(Some("name")));
+        assert!(check_route("/hn/:id<int")
+            .unwrap_err()
+            .contains("has to end with `>`"));
+        assert!(check_route("/hn/:id<>")
+            .unwrap_err()
+            .contains("Invalid parameter constraint"));
+        assert!(check_route("/tags/:tag<[a-z]!>")
+            .unwrap_err()
+            .contains("Invalid parameter constraint"));
+        assert!(check_route("/tags/:tag<[]+>")
+            .unwrap_err()
+            .contains("Invalid parameter constraint"));
+    }
+}
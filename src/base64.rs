@@ -0,0 +1,96 @@
+// https://tools.ietf.org/html/rfc4648#section-4
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode(input: &[u8]) -> String {
+    let mut result = String::with_capacity(input.len().div_ceil(3) * 4);
+
+    for chunk in input.chunks(3) {
+        let b0 = chunk[0] as u32;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as u32;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as u32;
+        let triple = (b0 << 16) | (b1 << 8) | b2;
+
+        result.push(ALPHABET[(triple >> 18) as usize & 0x3f] as char);
+        result.push(ALPHABET[(triple >> 12) as usize & 0x3f] as char);
+        if chunk.len() > 1 {
+            result.push(ALPHABET[(triple >> 6) as usize & 0x3f] as char);
+        } else {
+            result.push('=');
+        }
+        if chunk.len() > 2 {
+            result.push(ALPHABET[triple as usize & 0x3f] as char);
+        } else {
+            result.push('=');
+        }
+    }
+
+    result
+}
+
+fn decode_char(c: u8) -> Option<u32> {
+    ALPHABET
+        .iter()
+        .position(|&letter| letter == c)
+        .map(|value| value as u32)
+}
+
+/// Decodes padded base64. Returns `None` if the input has characters outside of the
+/// alphabet or a wrong length.
+pub fn decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    if input.len() % 4 != 0 {
+        return None;
+    }
+    let mut result = Vec::with_capacity(input.len() / 4 * 3);
+
+    for (i, chunk) in input.chunks(4).enumerate() {
+        let last = i == input.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut triple = 0;
+        for &c in &chunk[..4 - padding] {
+            triple = (triple << 6) | decode_char(c)?;
+        }
+        triple <<= 6 * padding;
+
+        result.push((triple >> 16) as u8);
+        if padding < 2 {
+            result.push((triple >> 8) as u8);
+        }
+        if padding < 1 {
+            result.push(triple as u8);
+        }
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_test_vectors() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foob"), "Zm9vYg==");
+        assert_eq!(encode(b"fooba"), "Zm9vYmE=");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn decode_test_vectors() {
+        assert_eq!(decode(""), Some(b"".to_vec()));
+        assert_eq!(decode("Zg=="), Some(b"f".to_vec()));
+        assert_eq!(decode("Zm8="), Some(b"fo".to_vec()));
+        assert_eq!(decode("Zm9vYmFy"), Some(b"foobar".to_vec()));
+        assert_eq!(decode("Zm9vYg"), None);
+        assert_eq!(decode("Zm=vYmFy"), None);
+        assert_eq!(decode("Zg==Zg=="), None);
+        assert_eq!(decode("Zm9*"), None);
+    }
+}
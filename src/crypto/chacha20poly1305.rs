@@ -0,0 +1,284 @@
+// https://tools.ietf.org/html/rfc8439
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn chacha20_block(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; NONCE_LEN]) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for i in 0..8 {
+        state[4 + i] = le32(&key[i * 4..]);
+    }
+    state[12] = counter;
+    for i in 0..3 {
+        state[13 + i] = le32(&nonce[i * 4..]);
+    }
+
+    let mut working = state;
+    for _ in 0..10 {
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    }
+
+    let mut block = [0u8; 64];
+    for i in 0..16 {
+        block[i * 4..i * 4 + 4].copy_from_slice(&working[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    block
+}
+
+fn chacha20_xor(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; NONCE_LEN], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (byte, key_byte) in chunk.iter_mut().zip(block.iter()) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+/// Poly1305 with 26 bit limbs, so products fit in 64 bits.
+struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+}
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Self {
+        Poly1305 {
+            r: [
+                le32(&key[0..]) & 0x3ffffff,
+                (le32(&key[3..]) >> 2) & 0x3ffff03,
+                (le32(&key[6..]) >> 4) & 0x3ffc0ff,
+                (le32(&key[9..]) >> 6) & 0x3f03fff,
+                (le32(&key[12..]) >> 8) & 0x00fffff,
+            ],
+            h: [0; 5],
+            pad: [
+                le32(&key[16..]),
+                le32(&key[20..]),
+                le32(&key[24..]),
+                le32(&key[28..]),
+            ],
+        }
+    }
+
+    fn block(&mut self, block: &[u8; 16], high_bit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r.map(u64::from);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+
+        let h0 = (self.h[0] + (le32(&block[0..]) & 0x3ffffff)) as u64;
+        let h1 = (self.h[1] + ((le32(&block[3..]) >> 2) & 0x3ffffff)) as u64;
+        let h2 = (self.h[2] + ((le32(&block[6..]) >> 4) & 0x3ffffff)) as u64;
+        let h3 = (self.h[3] + ((le32(&block[9..]) >> 6) & 0x3ffffff)) as u64;
+        let h4 = (self.h[4] + ((le32(&block[12..]) >> 8) | high_bit)) as u64;
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        d1 += d0 >> 26;
+        d2 += d1 >> 26;
+        d3 += d2 >> 26;
+        d4 += d3 >> 26;
+        let mut h0 = (d0 & 0x3ffffff) + (d4 >> 26) * 5;
+        let h1 = (d1 & 0x3ffffff) + (h0 >> 26);
+        h0 &= 0x3ffffff;
+        self.h = [
+            h0 as u32,
+            h1 as u32,
+            (d2 & 0x3ffffff) as u32,
+            (d3 & 0x3ffffff) as u32,
+            (d4 & 0x3ffffff) as u32,
+        ];
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(16) {
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            if chunk.len() == 16 {
+                self.block(&block, 1 << 24);
+            } else {
+                block[chunk.len()] = 1;
+                self.block(&block, 0);
+            }
+        }
+    }
+
+    fn finalize(self) -> [u8; TAG_LEN] {
+        let mut h = self.h;
+        let mut carry;
+        for i in 1..5 {
+            carry = h[i] >> 26;
+            h[i] &= 0x3ffffff;
+            if i < 4 {
+                h[i + 1] += carry;
+            } else {
+                h[0] += carry * 5;
+            }
+        }
+        carry = h[0] >> 26;
+        h[0] &= 0x3ffffff;
+        h[1] += carry;
+
+        // Compute h - p and keep it if it didn't underflow
+        let mut g = [0u32; 5];
+        carry = 5;
+        for i in 0..5 {
+            g[i] = h[i].wrapping_add(carry);
+            carry = g[i] >> 26;
+            g[i] &= 0x3ffffff;
+        }
+        g[4] = g[4].wrapping_add(carry << 26).wrapping_sub(1 << 26);
+        let mask = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !mask) | (g[i] & mask);
+        }
+
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0u8; TAG_LEN];
+        let mut sum = 0u64;
+        for i in 0..4 {
+            sum = (sum >> 32) + words[i] as u64 + self.pad[i] as u64;
+            tag[i * 4..i * 4 + 4].copy_from_slice(&(sum as u32).to_le_bytes());
+        }
+        tag
+    }
+}
+
+fn compute_tag(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> [u8; TAG_LEN] {
+    let block = chacha20_block(key, 0, nonce);
+    let mut one_time_key = [0u8; 32];
+    one_time_key.copy_from_slice(&block[..32]);
+
+    // The padding has to be part of the same input, otherwise it would be a short block
+    let mut mac_data = aad.to_vec();
+    mac_data.resize(aad.len().div_ceil(16) * 16, 0);
+    mac_data.extend_from_slice(ciphertext);
+    mac_data.resize(mac_data.len().div_ceil(16) * 16, 0);
+    mac_data.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+    mac_data.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+
+    let mut poly = Poly1305::new(&one_time_key);
+    poly.update(&mac_data);
+    poly.finalize()
+}
+
+/// Encrypts `plaintext` and returns the ciphertext followed by the tag.
+pub fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut output = plaintext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut output);
+    let tag = compute_tag(key, nonce, aad, &output);
+    output.extend_from_slice(&tag);
+    output
+}
+
+/// Checks the tag at the end of `sealed` and decrypts the rest. Returns `None` if the tag
+/// doesn't match.
+pub fn open(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    sealed: &[u8],
+) -> Option<Vec<u8>> {
+    if sealed.len() < TAG_LEN {
+        return None;
+    }
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    let expected = compute_tag(key, nonce, aad, ciphertext);
+    let difference = expected
+        .iter()
+        .zip(tag.iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+    if difference != 0 {
+        return None;
+    }
+    let mut plaintext = ciphertext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut plaintext);
+    Some(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn poly1305_test_vector() {
+        // RFC 8439 section 2.5.2
+        let key = [
+            0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5,
+            0x06, 0xa8, 0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf,
+            0x41, 0x49, 0xf5, 0x1b,
+        ];
+        let mut poly = Poly1305::new(&key);
+        poly.update(b"Cryptographic Forum Research Group");
+        assert_eq!(hex(&poly.finalize()), "a8061dc1305136c6c22b8baf0c0127a9");
+    }
+
+    #[test]
+    fn aead_test_vector() {
+        // RFC 8439 section 2.8.2
+        let mut key = [0u8; KEY_LEN];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = 0x80 + i as u8;
+        }
+        let nonce = [
+            0x07, 0, 0, 0, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
+        ];
+        let aad = [
+            0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+        ];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let sealed = seal(&key, &nonce, &aad, plaintext);
+        assert_eq!(hex(&sealed[..16]), "d31a8d34648e60db7b86afbc53ef7ec2");
+        assert_eq!(
+            hex(&sealed[sealed.len() - TAG_LEN..]),
+            "1ae10b594f09e26a7e902ecbd0600691"
+        );
+        assert_eq!(open(&key, &nonce, &aad, &sealed), Some(plaintext.to_vec()));
+
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert_eq!(open(&key, &nonce, &aad, &tampered), None);
+    }
+}
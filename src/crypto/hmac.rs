@@ -0,0 +1,78 @@
+// https://tools.ietf.org/html/rfc2104 and https://tools.ietf.org/html/rfc5869
+use super::Sha256;
+
+const BLOCK_SIZE: usize = 64;
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block_key = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block_key[..32].copy_from_slice(&super::sha256(key));
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(&block_key.map(|byte| byte ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(&block_key.map(|byte| byte ^ 0x5c));
+    outer.update(&inner.finalize());
+    outer.finalize()
+}
+
+pub fn hkdf_extract(salt: &[u8], input_key_material: &[u8]) -> [u8; 32] {
+    hmac_sha256(salt, input_key_material)
+}
+
+pub fn hkdf_expand(pseudorandom_key: &[u8], info: &[u8], length: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(length);
+    let mut previous: Vec<u8> = Vec::new();
+    let mut counter = 1u8;
+    while output.len() < length {
+        let mut data = previous.clone();
+        data.extend_from_slice(info);
+        data.push(counter);
+        previous = hmac_sha256(pseudorandom_key, &data).to_vec();
+        output.extend_from_slice(&previous);
+        counter += 1;
+    }
+    output.truncate(length);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn hmac_test_vector() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn hkdf_test_vector() {
+        // RFC 5869 test case 1
+        let prk = hkdf_extract(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12], &[0x0b; 22]);
+        assert_eq!(
+            hex(&prk),
+            "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"
+        );
+        let okm = hkdf_expand(
+            &prk,
+            &[0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9],
+            42,
+        );
+        assert_eq!(
+            hex(&okm),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+    }
+}
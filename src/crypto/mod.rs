@@ -0,0 +1,21 @@
+pub mod chacha20poly1305;
+mod hmac;
+mod rsa;
+mod sha1;
+mod sha256;
+mod x25519;
+pub use hmac::{hkdf_expand, hkdf_extract, hmac_sha256};
+pub use rsa::{RsaPrivateKey, RsaPublicKey};
+pub use sha1::{sha1, Sha1};
+pub use sha256::{sha256, Sha256};
+pub use x25519::{x25519, x25519_base};
+
+use std::fs::File;
+use std::io::{self, Read};
+
+/// Reads `len` bytes from the operating system's random number generator.
+pub fn random_bytes(len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0u8; len];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,711 @@
+// https://tools.ietf.org/html/rfc8017
+use super::{random_bytes, sha256};
+use std::cmp::Ordering;
+use std::io;
+
+/// Unsigned big integer with 32 bit limbs, least significant first.
+#[derive(Debug, Clone, PartialEq)]
+struct BigUint(Vec<u32>);
+
+impl BigUint {
+    fn from_limbs(mut limbs: Vec<u32>) -> Self {
+        while limbs.len() > 1 && limbs[limbs.len() - 1] == 0 {
+            limbs.pop();
+        }
+        if limbs.is_empty() {
+            limbs.push(0);
+        }
+        BigUint(limbs)
+    }
+
+    fn from_bytes_be(bytes: &[u8]) -> Self {
+        BigUint::from_limbs(
+            bytes
+                .rchunks(4)
+                .map(|chunk| {
+                    chunk
+                        .iter()
+                        .fold(0u32, |limb, &byte| (limb << 8) | byte as u32)
+                })
+                .collect(),
+        )
+    }
+
+    fn to_bytes_be(&self, len: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self
+            .0
+            .iter()
+            .rev()
+            .flat_map(|limb| limb.to_be_bytes())
+            .skip_while(|&byte| byte == 0)
+            .collect();
+        while bytes.len() < len {
+            bytes.insert(0, 0);
+        }
+        bytes
+    }
+
+    fn bits(&self) -> usize {
+        let top = self.0.len() - 1;
+        top * 32 + (32 - self.0[top].leading_zeros() as usize)
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        self.0
+            .get(i / 32)
+            .map(|limb| (limb >> (i % 32)) & 1 == 1)
+            .unwrap_or(false)
+    }
+
+    fn padded(&self, len: usize) -> Vec<u32> {
+        let mut limbs = self.0.clone();
+        limbs.resize(len.max(limbs.len()), 0);
+        limbs
+    }
+}
+
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    for i in (0..a.len().max(b.len())).rev() {
+        let x = a.get(i).copied().unwrap_or(0);
+        let y = b.get(i).copied().unwrap_or(0);
+        if x != y {
+            return x.cmp(&y);
+        }
+    }
+    Ordering::Equal
+}
+
+// a -= b, a has to be bigger
+fn subtract(a: &mut [u32], b: &[u32]) {
+    let mut borrow = 0i64;
+    for (i, limb) in a.iter_mut().enumerate() {
+        let value = *limb as i64 - b.get(i).copied().unwrap_or(0) as i64 - borrow;
+        borrow = (value < 0) as i64;
+        *limb = value as u32;
+    }
+}
+
+// a += b, a has to have room for the carry
+fn add_in_place(a: &mut [u32], b: &[u32]) {
+    let mut carry = 0u64;
+    for (i, limb) in a.iter_mut().enumerate() {
+        let value = *limb as u64 + b.get(i).copied().unwrap_or(0) as u64 + carry;
+        *limb = value as u32;
+        carry = value >> 32;
+    }
+}
+
+fn halve(a: &mut [u32]) {
+    let mut carry = 0;
+    for limb in a.iter_mut().rev() {
+        let next = *limb << 31;
+        *limb = (*limb >> 1) | carry;
+        carry = next;
+    }
+}
+
+// Swaps a and b when swap is 1, with the same memory accesses when it's 0
+fn conditional_swap(a: &mut [u32], b: &mut [u32], swap: u32) {
+    let mask = swap.wrapping_neg();
+    for (x, y) in a.iter_mut().zip(b.iter_mut()) {
+        let difference = (*x ^ *y) & mask;
+        *x ^= difference;
+        *y ^= difference;
+    }
+}
+
+/// Montgomery arithmetic modulo an odd number.
+#[derive(Clone)]
+struct Montgomery {
+    modulus: Vec<u32>,
+    // -modulus^-1 mod 2^32
+    inverse: u32,
+    // 2^(64 * len) mod modulus
+    r_squared: Vec<u32>,
+}
+
+impl Montgomery {
+    fn new(modulus: &BigUint) -> Self {
+        let modulus = modulus.0.clone();
+        let mut inverse = 1u32;
+        for _ in 0..5 {
+            inverse = inverse.wrapping_mul(2u32.wrapping_sub(modulus[0].wrapping_mul(inverse)));
+        }
+        let inverse = inverse.wrapping_neg();
+
+        // Double 1 until it's 2^(64 * len), reducing as it goes
+        let len = modulus.len();
+        let mut r_squared = vec![0u32; len + 1];
+        r_squared[0] = 1;
+        for _ in 0..64 * len {
+            let mut carry = 0;
+            for limb in r_squared.iter_mut() {
+                let next = *limb >> 31;
+                *limb = (*limb << 1) | carry;
+                carry = next;
+            }
+            if compare(&r_squared, &modulus) != Ordering::Less {
+                subtract(&mut r_squared, &modulus);
+            }
+        }
+        r_squared.truncate(len);
+
+        Montgomery {
+            modulus,
+            inverse,
+            r_squared,
+        }
+    }
+
+    // a * b / 2^(32 * len) mod modulus
+    fn multiply(&self, a: &[u32], b: &[u32]) -> Vec<u32> {
+        let len = self.modulus.len();
+        let mut t = vec![0u32; len + 2];
+        for &b_limb in b.iter().take(len) {
+            let mut carry = 0u64;
+            for j in 0..len {
+                let value = t[j] as u64 + a[j] as u64 * b_limb as u64 + carry;
+                t[j] = value as u32;
+                carry = value >> 32;
+            }
+            let value = t[len] as u64 + carry;
+            t[len] = value as u32;
+            t[len + 1] = (value >> 32) as u32;
+
+            let m = t[0].wrapping_mul(self.inverse) as u64;
+            let mut carry = (t[0] as u64 + m * self.modulus[0] as u64) >> 32;
+            for j in 1..len {
+                let value = t[j] as u64 + m * self.modulus[j] as u64 + carry;
+                t[j - 1] = value as u32;
+                carry = value >> 32;
+            }
+            let value = t[len] as u64 + carry;
+            t[len - 1] = value as u32;
+            t[len] = t[len + 1] + (value >> 32) as u32;
+        }
+        // t is below 2 * modulus, subtract the modulus without branching on whether it's needed
+        let mut reduced = vec![0u32; len];
+        let mut borrow = 0u64;
+        for j in 0..len {
+            let value = (t[j] as u64)
+                .wrapping_sub(self.modulus[j] as u64)
+                .wrapping_sub(borrow);
+            reduced[j] = value as u32;
+            borrow = value >> 63;
+        }
+        // all ones when the subtraction didn't go below zero
+        let keep_reduced = (((t[len] as u64).wrapping_sub(borrow) >> 63) as u32).wrapping_sub(1);
+        t.truncate(len);
+        for (limb, reduced) in t.iter_mut().zip(reduced) {
+            *limb = (reduced & keep_reduced) | (*limb & !keep_reduced);
+        }
+        t
+    }
+
+    /// Montgomery ladder, doing the same multiplications whatever the bits of the exponent
+    /// are, so the time it takes doesn't leak private exponents.
+    fn pow(&self, base: &BigUint, exponent: &BigUint) -> BigUint {
+        let len = self.modulus.len();
+        let mut one = vec![0u32; len];
+        one[0] = 1;
+        let mut result = self.multiply(&one, &self.r_squared);
+        // result * base, all along
+        let mut power = self.multiply(&base.padded(len), &self.r_squared);
+        for i in (0..32 * len.max(exponent.0.len())).rev() {
+            let bit = exponent.bit(i) as u32;
+            conditional_swap(&mut result, &mut power, bit);
+            power = self.multiply(&result, &power);
+            result = self.multiply(&result, &result);
+            conditional_swap(&mut result, &mut power, bit);
+        }
+        BigUint::from_limbs(self.multiply(&result, &one))
+    }
+
+    /// Square and multiply, faster than `pow` but only for public exponents, as it only
+    /// multiplies for the bits that are set.
+    fn pow_public(&self, base: &BigUint, exponent: &BigUint) -> BigUint {
+        let len = self.modulus.len();
+        let base = self.multiply(&base.padded(len), &self.r_squared);
+        let mut one = vec![0u32; len];
+        one[0] = 1;
+        let mut result = self.multiply(&one, &self.r_squared);
+        for i in (0..exponent.bits()).rev() {
+            result = self.multiply(&result, &result);
+            if exponent.bit(i) {
+                result = self.multiply(&result, &base);
+            }
+        }
+        BigUint::from_limbs(self.multiply(&result, &one))
+    }
+}
+
+fn add(a: &BigUint, b: &BigUint) -> BigUint {
+    let len = a.0.len().max(b.0.len()) + 1;
+    let (a, b) = (a.padded(len), b.padded(len));
+    let mut result = vec![0u32; len];
+    let mut carry = 0u64;
+    for i in 0..len {
+        let value = a[i] as u64 + b[i] as u64 + carry;
+        result[i] = value as u32;
+        carry = value >> 32;
+    }
+    BigUint::from_limbs(result)
+}
+
+fn multiply(a: &BigUint, b: &BigUint) -> BigUint {
+    let mut result = vec![0u32; a.0.len() + b.0.len()];
+    for (i, &a_limb) in a.0.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &b_limb) in b.0.iter().enumerate() {
+            let value = result[i + j] as u64 + a_limb as u64 * b_limb as u64 + carry;
+            result[i + j] = value as u32;
+            carry = value >> 32;
+        }
+        result[i + b.0.len()] = carry as u32;
+    }
+    BigUint::from_limbs(result)
+}
+
+// Bit by bit long division, only used a few times per signature
+fn remainder(a: &BigUint, modulus: &BigUint) -> BigUint {
+    let mut result = vec![0u32; modulus.0.len() + 1];
+    for i in (0..a.bits()).rev() {
+        let mut carry = a.bit(i) as u32;
+        for limb in result.iter_mut() {
+            let next = *limb >> 31;
+            *limb = (*limb << 1) | carry;
+            carry = next;
+        }
+        if compare(&result, &modulus.0) != Ordering::Less {
+            subtract(&mut result, &modulus.0);
+        }
+    }
+    BigUint::from_limbs(result)
+}
+
+// a^-1 mod an odd modulus with the binary extended Euclidean algorithm, None if they aren't
+// coprime. Its timing depends on a, it's only used on random blinding factors.
+fn inverse(a: &BigUint, modulus: &BigUint) -> Option<BigUint> {
+    let len = modulus.0.len() + 1;
+    let modulus = modulus.padded(len);
+    let mut one = vec![0u32; len];
+    one[0] = 1;
+    let (mut u, mut v) = (a.padded(len), modulus.clone());
+    // u = x1 * a and v = x2 * a, mod modulus
+    let (mut x1, mut x2) = (one.clone(), vec![0u32; len]);
+    let halve_modulo = |x: &mut Vec<u32>| {
+        if x[0] & 1 == 1 {
+            add_in_place(x, &modulus);
+        }
+        halve(x);
+    };
+    let subtract_modulo = |x: &mut Vec<u32>, y: &[u32]| {
+        if compare(x, y) == Ordering::Less {
+            add_in_place(x, &modulus);
+        }
+        subtract(x, y);
+    };
+    while u != one && v != one {
+        // a common factor ends up alone on one side, and zero on the other
+        if u.iter().all(|&limb| limb == 0) || v.iter().all(|&limb| limb == 0) {
+            return None;
+        }
+        while u[0] & 1 == 0 {
+            halve(&mut u);
+            halve_modulo(&mut x1);
+        }
+        while v[0] & 1 == 0 {
+            halve(&mut v);
+            halve_modulo(&mut x2);
+        }
+        if compare(&u, &v) != Ordering::Less {
+            subtract(&mut u, &v);
+            subtract_modulo(&mut x1, &x2);
+        } else {
+            subtract(&mut v, &u);
+            subtract_modulo(&mut x2, &x1);
+        }
+    }
+    Some(BigUint::from_limbs(if u == one { x1 } else { x2 }))
+}
+
+/// RSA public key, checking signatures.
+#[derive(Clone)]
+pub struct RsaPublicKey {
+    modulus: Montgomery,
+    public_exponent: BigUint,
+    size: usize,
+}
+
+impl RsaPublicKey {
+    /// Builds a key from the big endian modulus and public exponent.
+    pub fn new(modulus: &[u8], public_exponent: &[u8]) -> Option<Self> {
+        let modulus = BigUint::from_bytes_be(modulus);
+        if modulus.bits() < 1024 || modulus.0[0] & 1 == 0 {
+            return None;
+        }
+        Some(RsaPublicKey {
+            size: modulus.bits().div_ceil(8),
+            modulus: Montgomery::new(&modulus),
+            public_exponent: BigUint::from_bytes_be(public_exponent),
+        })
+    }
+
+    fn modulus(&self) -> BigUint {
+        BigUint(self.modulus.modulus.clone())
+    }
+
+    /// Whether `signature` is an RSASSA-PSS signature of `message` with SHA-256, MGF1 and a
+    /// 32 byte salt.
+    pub fn verify_pss_sha256(&self, message: &[u8], signature: &[u8]) -> bool {
+        if signature.len() != self.size {
+            return false;
+        }
+        let signature = BigUint::from_bytes_be(signature);
+        if compare(&signature.0, &self.modulus.modulus) != Ordering::Less {
+            return false;
+        }
+        let encoded_bits = self.modulus().bits() - 1;
+        let encoded = self
+            .modulus
+            .pow_public(&signature, &self.public_exponent)
+            .to_bytes_be(encoded_bits.div_ceil(8));
+        emsa_pss_verify(&sha256(message), &encoded, encoded_bits)
+    }
+}
+
+/// RSA private key with the CRT parameters, which make signing about four times faster.
+#[derive(Clone)]
+pub struct RsaPrivateKey {
+    public_key: RsaPublicKey,
+    prime1: Montgomery,
+    prime2: Montgomery,
+    exponent1: BigUint,
+    exponent2: BigUint,
+    coefficient: BigUint,
+}
+
+impl RsaPrivateKey {
+    /// Builds a key from the big endian fields of a PKCS #1 `RSAPrivateKey`.
+    pub fn new(
+        modulus: &[u8],
+        public_exponent: &[u8],
+        prime1: &[u8],
+        prime2: &[u8],
+        exponent1: &[u8],
+        exponent2: &[u8],
+        coefficient: &[u8],
+    ) -> Option<Self> {
+        let modulus = BigUint::from_bytes_be(modulus);
+        let prime1 = BigUint::from_bytes_be(prime1);
+        let prime2 = BigUint::from_bytes_be(prime2);
+        if modulus.bits() < 1024 || multiply(&prime1, &prime2) != modulus {
+            return None;
+        }
+        if prime1.0[0] & 1 == 0 || prime2.0[0] & 1 == 0 {
+            return None;
+        }
+        Some(RsaPrivateKey {
+            public_key: RsaPublicKey {
+                size: modulus.bits().div_ceil(8),
+                modulus: Montgomery::new(&modulus),
+                public_exponent: BigUint::from_bytes_be(public_exponent),
+            },
+            prime1: Montgomery::new(&prime1),
+            prime2: Montgomery::new(&prime2),
+            exponent1: BigUint::from_bytes_be(exponent1),
+            exponent2: BigUint::from_bytes_be(exponent2),
+            coefficient: BigUint::from_bytes_be(coefficient),
+        })
+    }
+
+    /// Size of the modulus and the signatures in bytes.
+    pub fn size(&self) -> usize {
+        self.public_key.size
+    }
+
+    pub fn public_key(&self) -> &RsaPublicKey {
+        &self.public_key
+    }
+
+    // The message is blinded with r^e for a random r, and the result unblinded with r^-1, so
+    // the exponentiations never see a message an attacker chose
+    fn private_operation(&self, message: &BigUint) -> io::Result<BigUint> {
+        let public_key = &self.public_key;
+        let modulus = public_key.modulus();
+        let (blinding, unblinding) = loop {
+            let r = BigUint::from_bytes_be(&random_bytes(public_key.size)?);
+            let r = remainder(&r, &modulus);
+            if let Some(unblinding) = inverse(&r, &modulus) {
+                let blinding = public_key
+                    .modulus
+                    .pow_public(&r, &public_key.public_exponent);
+                break (blinding, unblinding);
+            }
+        };
+        let message = remainder(&multiply(message, &blinding), &modulus);
+
+        let prime1 = BigUint(self.prime1.modulus.clone());
+        let prime2 = BigUint(self.prime2.modulus.clone());
+        let m1 = self
+            .prime1
+            .pow(&remainder(&message, &prime1), &self.exponent1);
+        let m2 = self
+            .prime2
+            .pow(&remainder(&message, &prime2), &self.exponent2);
+        // h = coefficient * (m1 - m2) mod prime1, adding prime1 so it can't go negative
+        let mut difference = add(&m1, &prime1).padded(prime1.0.len() + 1);
+        subtract(&mut difference, &remainder(&m2, &prime1).0);
+        let h = remainder(
+            &multiply(&self.coefficient, &BigUint::from_limbs(difference)),
+            &prime1,
+        );
+        let blinded = add(&m2, &multiply(&h, &prime2));
+        Ok(remainder(&multiply(&blinded, &unblinding), &modulus))
+    }
+
+    /// RSASSA-PSS signature with SHA-256, MGF1 and a 32 byte salt.
+    pub fn sign_pss_sha256(&self, message: &[u8]) -> io::Result<Vec<u8>> {
+        self.sign_pss_sha256_with_salt(message, &random_bytes(32)?)
+    }
+
+    fn sign_pss_sha256_with_salt(&self, message: &[u8], salt: &[u8]) -> io::Result<Vec<u8>> {
+        let modulus_bits = self.public_key.modulus().bits();
+        let encoded = emsa_pss_encode(&sha256(message), salt, modulus_bits - 1);
+        let signature = self
+            .private_operation(&BigUint::from_bytes_be(&encoded))?
+            .to_bytes_be(self.size());
+
+        // Check the signature, so a broken key doesn't leak bad signatures
+        if !self.public_key.verify_pss_sha256(message, &signature) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "RSA signature verification failed",
+            ));
+        }
+        Ok(signature)
+    }
+}
+
+fn mgf1_sha256(seed: &[u8], len: usize) -> Vec<u8> {
+    let mut mask = Vec::with_capacity(len + 32);
+    let mut counter = 0u32;
+    while mask.len() < len {
+        let mut data = seed.to_vec();
+        data.extend_from_slice(&counter.to_be_bytes());
+        mask.extend_from_slice(&sha256(&data));
+        counter += 1;
+    }
+    mask.truncate(len);
+    mask
+}
+
+fn emsa_pss_encode(message_hash: &[u8; 32], salt: &[u8], encoded_bits: usize) -> Vec<u8> {
+    let encoded_len = encoded_bits.div_ceil(8);
+    let mut prefixed = vec![0u8; 8];
+    prefixed.extend_from_slice(message_hash);
+    prefixed.extend_from_slice(salt);
+    let hash = sha256(&prefixed);
+
+    let mut db = vec![0u8; encoded_len - salt.len() - 32 - 2];
+    db.push(1);
+    db.extend_from_slice(salt);
+    let mask = mgf1_sha256(&hash, db.len());
+    for (byte, mask_byte) in db.iter_mut().zip(mask.iter()) {
+        *byte ^= mask_byte;
+    }
+    db[0] &= 0xff >> (8 * encoded_len - encoded_bits);
+
+    db.extend_from_slice(&hash);
+    db.push(0xbc);
+    db
+}
+
+fn emsa_pss_verify(message_hash: &[u8; 32], encoded: &[u8], encoded_bits: usize) -> bool {
+    let encoded_len = encoded_bits.div_ceil(8);
+    if encoded.len() != encoded_len || encoded_len < 32 + 32 + 2 || encoded[encoded_len - 1] != 0xbc
+    {
+        return false;
+    }
+    let (masked_db, hash) = encoded[..encoded_len - 1].split_at(encoded_len - 33);
+    let top_bits = 0xff >> (8 * encoded_len - encoded_bits);
+    if masked_db[0] & !top_bits != 0 {
+        return false;
+    }
+    let mut db: Vec<u8> = masked_db
+        .iter()
+        .zip(mgf1_sha256(hash, masked_db.len()))
+        .map(|(byte, mask_byte)| byte ^ mask_byte)
+        .collect();
+    db[0] &= top_bits;
+    let padding_len = db.len() - 32 - 1;
+    if db[..padding_len].iter().any(|&byte| byte != 0) || db[padding_len] != 1 {
+        return false;
+    }
+
+    let mut prefixed = vec![0u8; 8];
+    prefixed.extend_from_slice(message_hash);
+    prefixed.extend_from_slice(&db[padding_len + 1..]);
+    sha256(&prefixed) == hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modular_exponentiation() {
+        // 4^13 mod 497 = 445 with a single limb
+        let montgomery = Montgomery::new(&BigUint(vec![497]));
+        assert_eq!(
+            montgomery.pow(&BigUint(vec![4]), &BigUint(vec![13])),
+            BigUint(vec![445])
+        );
+
+        // (2^64 + 3)^65537 mod (2^89 - 1), a Mersenne prime
+        let modulus = BigUint(vec![0xffffffff, 0xffffffff, 0x1ffffff]);
+        let montgomery = Montgomery::new(&modulus);
+        let result = montgomery.pow(&BigUint(vec![3, 0, 1]), &BigUint(vec![65537]));
+        // Fermat: a^(p - 1) = 1 mod p
+        let p_minus_1 = BigUint(vec![0xfffffffe, 0xffffffff, 0x1ffffff]);
+        assert_eq!(montgomery.pow(&result, &p_minus_1), BigUint(vec![1]));
+        assert_eq!(
+            montgomery.pow_public(&BigUint(vec![3, 0, 1]), &BigUint(vec![65537])),
+            result
+        );
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let number = BigUint::from_bytes_be(&[0, 0, 1, 2, 3, 4, 5]);
+        assert_eq!(number, BigUint(vec![0x02030405, 0x01]));
+        assert_eq!(number.to_bytes_be(0), vec![1, 2, 3, 4, 5]);
+        assert_eq!(number.to_bytes_be(8), vec![0, 0, 0, 1, 2, 3, 4, 5]);
+        assert_eq!(number.bits(), 33);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = BigUint(vec![0xffffffff, 0xffffffff]);
+        let b = BigUint(vec![2]);
+        assert_eq!(add(&a, &b), BigUint(vec![1, 0, 1]));
+        assert_eq!(multiply(&a, &b), BigUint(vec![0xfffffffe, 0xffffffff, 1]));
+        assert_eq!(remainder(&a, &BigUint(vec![1000])), BigUint(vec![615]));
+    }
+
+    #[test]
+    fn crt_signature() {
+        // Textbook key with p = 61, q = 53, e = 17 and d = 2753
+        let modulus = BigUint(vec![3233]);
+        let prime1 = BigUint(vec![61]);
+        let prime2 = BigUint(vec![53]);
+        let key = RsaPrivateKey {
+            public_key: RsaPublicKey {
+                size: 2,
+                modulus: Montgomery::new(&modulus),
+                public_exponent: BigUint(vec![17]),
+            },
+            prime1: Montgomery::new(&prime1),
+            prime2: Montgomery::new(&prime2),
+            exponent1: BigUint(vec![53]),
+            exponent2: BigUint(vec![49]),
+            coefficient: BigUint(vec![38]),
+        };
+        // blinded with a different r each time, the results don't change
+        for _ in 0..8 {
+            assert_eq!(
+                key.private_operation(&BigUint(vec![2790])).unwrap(),
+                BigUint(vec![65])
+            );
+            assert_eq!(
+                key.private_operation(&BigUint(vec![65])).unwrap(),
+                BigUint(vec![588])
+            );
+        }
+    }
+
+    #[test]
+    fn modular_inverse() {
+        let modulus = BigUint(vec![3233]);
+        assert_eq!(
+            inverse(&BigUint(vec![17]), &modulus),
+            Some(BigUint(vec![2092]))
+        );
+        assert_eq!(inverse(&BigUint(vec![1]), &modulus), Some(BigUint(vec![1])));
+        assert_eq!(inverse(&BigUint(vec![0]), &modulus), None);
+        // 61 divides 3233
+        assert_eq!(inverse(&BigUint(vec![122]), &modulus), None);
+
+        let modulus = BigUint(vec![0xffffffff, 0xffffffff, 0x1ffffff]);
+        let a = BigUint(vec![3, 0, 1]);
+        let product = remainder(&multiply(&a, &inverse(&a, &modulus).unwrap()), &modulus);
+        assert_eq!(product, BigUint(vec![1]));
+    }
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // The 1024 bit key of the TLS tests
+    fn test_key() -> RsaPrivateKey {
+        RsaPrivateKey::new(
+            &from_hex(concat!(
+                "cd5ba1653b7b314d2f6e6a37001670d30de88ee739a9ea891191d75cefbe2ded",
+                "17ffa1892badb0cbe0dda6c0f289d0aab81a1e5363cad6925eada7d06f478ebc",
+                "f5d91fea41db2d16d320b0af126ff022cb237dec8123b0163c9f469df5d6af47",
+                "90f9b290189d50ee83d541e8633790755d380e0df09b2af3f7fa7b3973052f7f",
+            )),
+            &from_hex("010001"),
+            &from_hex(concat!(
+                "f9b7597a6695aa4052c768106524a244f879882b27ae29f550faab37f8c12d23",
+                "c50fce0ac215a18bd891f2bd22e91f58cae8f5f77fdff2444f11abf6c49c1f97",
+            )),
+            &from_hex(concat!(
+                "d28687587904e07b76c1e05d23829bdfe88889fb0c94edcdb541faba20a4208a",
+                "f0c626d8d8048b7e84af0466c2a82e551807a2969cd2080281b5d5695539ec59",
+            )),
+            &from_hex(concat!(
+                "e604546061c3f489c1763a4df7cc71c8141466305221a4367a7c1b60b83ac068",
+                "95694482365a946e6b73ac63399800aeda6111c803baf5bea41284130ef13c4b",
+            )),
+            &from_hex(concat!(
+                "977ef9bcbff1b37d055bc5122130a6ecfd0fec833d6d9aa7326d9579056e3680",
+                "e57d228d5efdfe48004155e080e9b7d5c262142d6012807315567d89bc2bdf99",
+            )),
+            &from_hex(concat!(
+                "9739fffa611bbe54e9d11579f2018a02c9b14beae5362e6874d1d6e2d91f87b8",
+                "06ca43561a97669ed4f65434eab83c4d877399d76f9861e7a91e068319a9c233",
+            )),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn pss_signature() {
+        let key = test_key();
+        let signature = key.sign_pss_sha256_with_salt(b"moon", &[0x42; 32]).unwrap();
+        // PSS is deterministic with a fixed salt, checked against another implementation
+        assert_eq!(
+            signature,
+            from_hex(concat!(
+                "31e8936d07cbc1870728bc0f22de8d6334d54f1c82e3784bb8eafe620d34e894",
+                "20b0399f0be4567980d3b124da222155a03e42ce738d55d54e6130d0babb6a14",
+                "8455bc98f15555ca1362f138bb21dc164c5e81df6eb0bfe19f5a66600d692785",
+                "3adc8740e5147343143035db120029f4153f2535d8667d9c38209d72e463e4e2",
+            ))
+        );
+        let public_key = key.public_key();
+        assert!(public_key.verify_pss_sha256(b"moon", &signature));
+        assert!(!public_key.verify_pss_sha256(b"mood", &signature));
+        let mut tampered = signature.clone();
+        tampered[64] ^= 1;
+        assert!(!public_key.verify_pss_sha256(b"moon", &tampered));
+        assert!(!public_key.verify_pss_sha256(b"moon", &signature[1..]));
+
+        let signature = key.sign_pss_sha256(b"moon").unwrap();
+        assert!(public_key.verify_pss_sha256(b"moon", &signature));
+    }
+}
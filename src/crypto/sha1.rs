@@ -0,0 +1,126 @@
+// https://tools.ietf.org/html/rfc3174
+
+pub struct Sha1 {
+    state: [u32; 5],
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+impl Sha1 {
+    pub fn new() -> Self {
+        Sha1 {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0],
+            buffer: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+
+    fn process_block(&mut self, block: &[u8]) {
+        let mut words = [0u32; 80];
+        for (i, chunk) in block.chunks(4).enumerate() {
+            words[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e].iter()) {
+            *state = state.wrapping_add(*value);
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.length += data.len() as u64;
+        self.buffer.extend_from_slice(data);
+        let full_blocks = self.buffer.len() / 64 * 64;
+        let buffer = std::mem::take(&mut self.buffer);
+        for block in buffer[..full_blocks].chunks(64) {
+            self.process_block(block);
+        }
+        self.buffer = buffer[full_blocks..].to_vec();
+    }
+
+    pub fn finalize(mut self) -> [u8; 20] {
+        let bit_length = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        while (self.buffer.len() + padding.len()) % 64 != 56 {
+            padding.push(0);
+        }
+        padding.extend_from_slice(&bit_length.to_be_bytes());
+        let length = self.length;
+        self.update(&padding);
+        self.length = length;
+
+        let mut digest = [0u8; 20];
+        for (chunk, word) in digest.chunks_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn sha1_test_vectors() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(&sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn sha1_incremental_update() {
+        let mut hasher = Sha1::new();
+        for _ in 0..1000 {
+            hasher.update(b"a");
+        }
+        assert_eq!(hasher.finalize(), sha1(&[b'a'; 1000]));
+    }
+}
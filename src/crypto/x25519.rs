@@ -0,0 +1,246 @@
+// https://tools.ietf.org/html/rfc7748
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// Element of the field modulo 2^255 - 19, in five 51 bit limbs.
+#[derive(Clone, Copy)]
+struct FieldElement([u64; 5]);
+
+impl FieldElement {
+    const ZERO: FieldElement = FieldElement([0; 5]);
+    const ONE: FieldElement = FieldElement([1, 0, 0, 0, 0]);
+
+    fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let load = |offset: usize| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&bytes[offset..offset + 8]);
+            u64::from_le_bytes(word)
+        };
+        FieldElement([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    fn carry(mut self) -> Self {
+        for i in 0..4 {
+            self.0[i + 1] += self.0[i] >> 51;
+            self.0[i] &= MASK;
+        }
+        self.0[0] += (self.0[4] >> 51) * 19;
+        self.0[4] &= MASK;
+        self.0[1] += self.0[0] >> 51;
+        self.0[0] &= MASK;
+        self
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut limbs = self.carry().carry().0;
+        // Subtract p if the value is still bigger than it
+        let mut q = (limbs[0] + 19) >> 51;
+        for limb in &limbs[1..] {
+            q = (limb + q) >> 51;
+        }
+        limbs[0] += 19 * q;
+        for i in 0..4 {
+            limbs[i + 1] += limbs[i] >> 51;
+            limbs[i] &= MASK;
+        }
+        limbs[4] &= MASK;
+
+        let mut bytes = [0u8; 32];
+        let mut accumulator: u128 = 0;
+        let mut bits = 0;
+        let mut position = 0;
+        for limb in limbs.iter() {
+            accumulator |= (*limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 && position < 32 {
+                bytes[position] = accumulator as u8;
+                accumulator >>= 8;
+                bits -= 8;
+                position += 1;
+            }
+        }
+        if position < 32 {
+            bytes[position] = accumulator as u8;
+        }
+        bytes
+    }
+
+    fn add(self, other: Self) -> Self {
+        let mut result = self.0;
+        for (limb, other) in result.iter_mut().zip(other.0.iter()) {
+            *limb += other;
+        }
+        FieldElement(result).carry()
+    }
+
+    fn sub(self, other: Self) -> Self {
+        // Add 2p first so the limbs don't underflow
+        let two_p = [
+            0xfffffffffffda,
+            0xffffffffffffe,
+            0xffffffffffffe,
+            0xffffffffffffe,
+            0xffffffffffffe,
+        ];
+        let mut result = self.0;
+        for i in 0..5 {
+            result[i] = result[i] + two_p[i] - other.0[i];
+        }
+        FieldElement(result).carry()
+    }
+
+    fn mul(self, other: Self) -> Self {
+        let a = self.0.map(u128::from);
+        let b = other.0.map(u128::from);
+        let b19 = [b[0], b[1] * 19, b[2] * 19, b[3] * 19, b[4] * 19];
+
+        let mut result = [0u128; 5];
+        for i in 0..5 {
+            for j in 0..5 {
+                if i + j < 5 {
+                    result[i + j] += a[i] * b[j];
+                } else {
+                    result[i + j - 5] += a[i] * b19[j];
+                }
+            }
+        }
+
+        let mut limbs = [0u64; 5];
+        let mut carry = 0u128;
+        for i in 0..5 {
+            let value = result[i] + carry;
+            limbs[i] = (value as u64) & MASK;
+            carry = value >> 51;
+        }
+        limbs[0] += (carry as u64) * 19;
+        FieldElement(limbs).carry()
+    }
+
+    fn square(self) -> Self {
+        self.mul(self)
+    }
+
+    fn mul_small(self, scalar: u64) -> Self {
+        self.mul(FieldElement([scalar, 0, 0, 0, 0]))
+    }
+
+    fn invert(self) -> Self {
+        // a^(p - 2), p - 2 = 2^255 - 21
+        let mut result = FieldElement::ONE;
+        for bit in (0..255).rev() {
+            result = result.square();
+            let exponent_bit = match bit {
+                1 | 3 => true,
+                2 => false,
+                0 => true,
+                _ => bit >= 5,
+            };
+            if exponent_bit {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    fn conditional_swap(a: &mut Self, b: &mut Self, swap: u64) {
+        let mask = 0u64.wrapping_sub(swap);
+        for i in 0..5 {
+            let t = mask & (a.0[i] ^ b.0[i]);
+            a.0[i] ^= t;
+            b.0[i] ^= t;
+        }
+    }
+}
+
+/// Scalar multiplication on Curve25519, returning the u-coordinate of `scalar * u`.
+pub fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = FieldElement::from_bytes(u);
+    let mut x2 = FieldElement::ONE;
+    let mut z2 = FieldElement::ZERO;
+    let mut x3 = x1;
+    let mut z3 = FieldElement::ONE;
+    let mut swap = 0;
+
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= bit;
+        FieldElement::conditional_swap(&mut x2, &mut x3, swap);
+        FieldElement::conditional_swap(&mut z2, &mut z3, swap);
+        swap = bit;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(e.mul_small(121665)));
+    }
+    FieldElement::conditional_swap(&mut x2, &mut x3, swap);
+    FieldElement::conditional_swap(&mut z2, &mut z3, swap);
+
+    x2.mul(z2.invert()).to_bytes()
+}
+
+/// Public key for a private key, the scalar multiplication of the base point 9.
+pub fn x25519_base(scalar: &[u8; 32]) -> [u8; 32] {
+    let mut base = [0u8; 32];
+    base[0] = 9;
+    x25519(scalar, &base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn x25519_test_vector() {
+        // RFC 7748 section 5.2
+        let scalar = from_hex("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        let u = from_hex("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+        assert_eq!(
+            x25519(&scalar, &u),
+            from_hex("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552")
+        );
+    }
+
+    #[test]
+    fn diffie_hellman() {
+        // RFC 7748 section 6.1
+        let alice = from_hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = from_hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        assert_eq!(
+            x25519_base(&alice),
+            from_hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+        );
+        assert_eq!(
+            x25519(&alice, &x25519_base(&bob)),
+            from_hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742")
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use super::date::DateTime;
+use super::server::State;
+use super::{HttpRequest, HttpResponse};
+use crate::json::JsonValue;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET / HTTP/1.1" 200 2326 0.004`
+    Common,
+    /// Common format with the `Referer` and `User-Agent` headers before the duration.
+    Combined,
+    /// A JSON object per line.
+    Json,
+}
+
+enum Sink {
+    Stderr,
+    File(LogFile),
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    // max size, old files kept
+    rotation: Option<(u64, usize)>,
+}
+
+impl LogFile {
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if let Some((max_size, max_files)) = self.rotation {
+            if self.size > 0 && self.size + line.len() as u64 > max_size {
+                self.rotate(max_files)?;
+            }
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    // access.log becomes access.log.1, access.log.1 becomes access.log.2 and so on
+    fn rotate(&mut self, max_files: usize) -> io::Result<()> {
+        let old_file = |index: usize| PathBuf::from(format!("{}.{}", self.path.display(), index));
+        if max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(old_file(max_files));
+            for index in (1..max_files).rev() {
+                if old_file(index).exists() {
+                    fs::rename(old_file(index), old_file(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, old_file(1))?;
+        }
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Writes a line per request, to stderr or a file. Register its `middleware` with
+/// `HttpServer::after`, after the middleware that change the response like `byte_ranges`, so
+/// it logs the body that is sent. Upgraded responses (WebSockets, event streams and streamed
+/// proxy bodies) send their body later on, their size is logged as unknown.
+#[derive(Clone)]
+pub struct AccessLog {
+    format: LogFormat,
+    sink: Arc<Mutex<Sink>>,
+}
+
+impl AccessLog {
+    pub fn stderr(format: LogFormat) -> Self {
+        AccessLog {
+            format,
+            sink: Arc::new(Mutex::new(Sink::Stderr)),
+        }
+    }
+
+    /// Appends to the file at `path`, creating it if needed.
+    pub fn file<P: AsRef<Path>>(path: P, format: LogFormat) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(AccessLog {
+            format,
+            sink: Arc::new(Mutex::new(Sink::File(LogFile {
+                path,
+                file,
+                size,
+                rotation: None,
+            }))),
+        })
+    }
+
+    /// Starts a new file when the log would grow past `max_size` bytes, keeping `max_files`
+    /// old ones as `access.log.1` (the newest) to `access.log.<max_files>`. Only files rotate.
+    pub fn set_rotation(&mut self, max_size: u64, max_files: usize) {
+        if let Sink::File(file) = &mut *self.sink.lock().unwrap() {
+            file.rotation = Some((max_size, max_files));
+        }
+    }
+
+    pub fn log(&self, request: &HttpRequest, response: &HttpResponse) {
+        let line = format_entry(
+            self.format,
+            request,
+            response,
+            SystemTime::now(),
+            request.received_at.elapsed(),
+        );
+        let result = match &mut *self.sink.lock().unwrap() {
+            Sink::Stderr => io::stderr().write_all(line.as_bytes()),
+            Sink::File(file) => file.write_line(&line),
+        };
+        if let Err(err) = result {
+            eprintln!("Error writing the access log: {}", err);
+        }
+    }
+
+    pub fn middleware<T>(
+        &self,
+    ) -> impl Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync {
+        let log = self.clone();
+        move |request, response, _| log.log(request, response)
+    }
+}
+
+fn format_entry(
+    format: LogFormat,
+    request: &HttpRequest,
+    response: &HttpResponse,
+    time: SystemTime,
+    duration: Duration,
+) -> String {
+    let client = request
+        .client_ip
+        .or(request.peer_addr.map(|address| address.ip()));
+    let date = DateTime::from_system_time(time);
+    // upgraded responses write their body to the connection once the response is sent
+    let bytes = match response.upgrade {
+        Some(_) => None,
+        None => Some(response.body().len()),
+    };
+    let duration = duration.as_secs_f64();
+    if format == LogFormat::Json {
+        let mut entry = HashMap::new();
+        let timestamp = format!(
+            "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            date.year, date.month, date.day, date.hour, date.minute, date.second
+        );
+        entry.insert("time".to_owned(), JsonValue::from(timestamp));
+        let client = client.map_or(JsonValue::Null, |client| client.to_string().into());
+        entry.insert("client".to_owned(), client);
+        entry.insert("method".to_owned(), JsonValue::from(&request.method));
+        entry.insert("uri".to_owned(), JsonValue::from(&request.uri));
+        entry.insert("version".to_owned(), JsonValue::from(&request.version));
+        let status = response.status_code() as f64;
+        entry.insert("status".to_owned(), JsonValue::from(status));
+        let bytes = bytes.map_or(JsonValue::Null, |bytes| JsonValue::from(bytes as f64));
+        entry.insert("bytes".to_owned(), bytes);
+        entry.insert("duration".to_owned(), JsonValue::from(duration));
+        for (key, header) in [("referer", "Referer"), ("user_agent", "User-Agent")] {
+            let value = request
+                .header(header)
+                .map_or(JsonValue::Null, JsonValue::from);
+            entry.insert(key.to_owned(), value);
+        }
+        return format!("{}\n", JsonValue::Object(entry).stringify());
+    }
+
+    let mut line = format!(
+        "{} - - [{:02}/{}/{}:{:02}:{:02}:{:02} +0000] \"{}\" {} {}",
+        client.map_or("-".to_owned(), |client| client.to_string()),
+        date.day,
+        date.month_name(),
+        date.year,
+        date.hour,
+        date.minute,
+        date.second,
+        escape(&format!(
+            "{} {} HTTP/{}",
+            request.method, request.uri, request.version
+        )),
+        response.status_code(),
+        match bytes {
+            Some(bytes) if bytes > 0 => bytes.to_string(),
+            _ => "-".to_owned(),
+        },
+    );
+    if format == LogFormat::Combined {
+        for header in ["Referer", "User-Agent"] {
+            line.push_str(&format!(
+                " \"{}\"",
+                escape(request.header(header).unwrap_or("-"))
+            ));
+        }
+    }
+    line.push_str(&format!(" {:.3}\n", duration));
+    line
+}
+
+// Quotes and control characters would let clients forge entries
+fn escape(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    for ch in field.chars() {
+        match ch {
+            '"' | '\\' => {
+                result.push('\\');
+                result.push(ch);
+            }
+            ch if ch.is_control() => result.push_str(&format!("\\x{:02x}", ch as u32)),
+            ch => result.push(ch),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::byte_ranges;
+    use super::*;
+    use crate::json::JsonParser;
+    use std::env;
+    use std::time::UNIX_EPOCH;
+
+    fn request() -> HttpRequest {
+        let mut request = HttpRequest::new_with_uri("/hn?q=\"moon\"".to_owned());
+        request.peer_addr = Some("192.0.2.1:4000".parse().unwrap());
+        request.headers = vec![("User-Agent".to_owned(), "curl/8.0".to_owned())];
+        request
+    }
+
+    fn response_entry(format: LogFormat, request: &HttpRequest, response: &HttpResponse) -> String {
+        let time = UNIX_EPOCH + Duration::from_secs(971_186_136);
+        format_entry(format, request, response, time, Duration::from_millis(4))
+    }
+
+    fn entry(format: LogFormat) -> String {
+        let mut response = HttpResponse::new();
+        response.set_body("moon".to_owned());
+        response_entry(format, &request(), &response)
+    }
+
+    #[test]
+    fn log_formats() {
+        assert_eq!(
+            entry(LogFormat::Common),
+            "192.0.2.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /hn?q=\\\"moon\\\" HTTP/1.1\" 200 4 0.004\n"
+        );
+        assert_eq!(
+            entry(LogFormat::Combined),
+            "192.0.2.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /hn?q=\\\"moon\\\" HTTP/1.1\" 200 4 \"-\" \"curl/8.0\" 0.004\n"
+        );
+        let json = JsonParser::new(&entry(LogFormat::Json)).parse();
+        assert_eq!(
+            json.field::<String>("time"),
+            Ok("2000-10-10T13:55:36Z".to_owned())
+        );
+        assert_eq!(json.field::<String>("uri"), Ok("/hn?q=\"moon\"".to_owned()));
+        assert_eq!(json.field::<f64>("status"), Ok(200.0));
+        assert_eq!(json.field::<f64>("bytes"), Ok(4.0));
+        assert_eq!(json.field::<f64>("duration"), Ok(0.004));
+        assert_eq!(json.field::<Option<String>>("referer"), Ok(None));
+    }
+
+    #[test]
+    fn logged_bytes() {
+        let mut response = HttpResponse::new();
+        response.set_upgrade(|_| {});
+        assert!(response_entry(LogFormat::Common, &request(), &response).contains(" 200 - "));
+        let json = JsonParser::new(&response_entry(LogFormat::Json, &request(), &response)).parse();
+        assert_eq!(json.field::<Option<f64>>("bytes"), Ok(None));
+
+        // logged after byte_ranges, the entry has the size of the range
+        let mut request = request();
+        request
+            .headers
+            .push(("Range".to_owned(), "bytes=0-1".to_owned()));
+        let mut response = HttpResponse::new();
+        response.set_body("moon".to_owned());
+        byte_ranges()(&request, &mut response, Arc::new(Mutex::new(())));
+        assert!(response_entry(LogFormat::Common, &request, &response).contains(" 206 2 "));
+    }
+
+    #[test]
+    fn rotate_files() {
+        let dir = env::temp_dir().join(format!("moon-access-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        let mut log = AccessLog::file(&path, LogFormat::Common).unwrap();
+        let line_length = entry(LogFormat::Common).len() as u64;
+        log.set_rotation(line_length * 2, 2);
+        let request = HttpRequest::new_with_uri("/hn?q=\"moon\"".to_owned());
+        let mut response = HttpResponse::new();
+        response.set_body("moon".to_owned());
+        for _ in 0..7 {
+            log.log(&request, &response);
+        }
+        let lines = |path: &Path| fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!(lines(&path), 1);
+        assert_eq!(lines(&dir.join("access.log.1")), 2);
+        assert_eq!(lines(&dir.join("access.log.2")), 2);
+        assert!(!dir.join("access.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+use super::cors::is_preflight;
+use super::error::HttpError;
+use super::server::State;
+use super::{HttpRequest, HttpResponse};
+use crate::base64;
+use std::any::Any;
+use std::sync::Arc;
+
+/// Credentials of the `Authorization` header of a request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Credentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Credentials {
+    /// `None` if there is no `Authorization` header, or it can't be parsed.
+    pub fn from_request(request: &HttpRequest) -> Option<Self> {
+        let (scheme, value) = request.header("Authorization")?.trim().split_once(' ')?;
+        let value = value.trim();
+        if scheme.eq_ignore_ascii_case("Basic") {
+            let decoded = String::from_utf8(base64::decode(value)?).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+            Some(Credentials::Basic {
+                username: username.to_owned(),
+                password: password.to_owned(),
+            })
+        } else if scheme.eq_ignore_ascii_case("Bearer") && !value.is_empty() {
+            Some(Credentials::Bearer(value.to_owned()))
+        } else {
+            None
+        }
+    }
+
+    /// Whether these are Basic credentials for `username` and `password`. Compares in
+    /// constant time, so the time it takes doesn't tell how much of them matched.
+    pub fn is_basic(&self, username: &str, password: &str) -> bool {
+        match self {
+            Credentials::Basic {
+                username: given_username,
+                password: given_password,
+            } => {
+                // both are compared so a wrong username takes as long as a wrong password
+                let username_matches = constant_time_eq(given_username, username);
+                constant_time_eq(given_password, password) && username_matches
+            }
+            Credentials::Bearer(_) => false,
+        }
+    }
+
+    /// Whether this is the bearer `token`, compared in constant time.
+    pub fn is_bearer(&self, token: &str) -> bool {
+        match self {
+            Credentials::Bearer(given) => constant_time_eq(given, token),
+            Credentials::Basic { .. } => false,
+        }
+    }
+}
+
+// Goes through all of `given` whatever its length, so neither the time nor an early return
+// tells the length of `secret`
+fn constant_time_eq(given: &str, secret: &str) -> bool {
+    let (given, secret) = (given.as_bytes(), secret.as_bytes());
+    let mut difference = given.len() ^ secret.len();
+    for (index, byte) in given.iter().enumerate() {
+        let expected = match secret.len() {
+            0 => 0,
+            len => secret[index % len],
+        };
+        difference |= (byte ^ expected) as usize;
+    }
+    difference == 0
+}
+
+type Verifier<P> = dyn Fn(&Credentials) -> Option<P> + Send + Sync;
+
+/// Authentication with the `Authorization` header. The verifier turns valid credentials into
+/// a principal, like a user, that handlers find in the request extensions. Requests without
+/// valid credentials get a 401 with a `WWW-Authenticate` challenge.
+pub struct Auth<P> {
+    realm: String,
+    basic: bool,
+    bearer: bool,
+    verifier: Arc<Verifier<P>>,
+}
+
+impl<P> Clone for Auth<P> {
+    fn clone(&self) -> Self {
+        Auth {
+            realm: self.realm.clone(),
+            basic: self.basic,
+            bearer: self.bearer,
+            verifier: Arc::clone(&self.verifier),
+        }
+    }
+}
+
+impl<P: Any + Send + Sync> Auth<P> {
+    fn with_schemes<F>(realm: &str, basic: bool, bearer: bool, verifier: F) -> Self
+    where
+        F: Fn(&Credentials) -> Option<P> + Send + Sync + 'static,
+    {
+        Auth {
+            realm: realm.to_owned(),
+            basic,
+            bearer,
+            verifier: Arc::new(verifier),
+        }
+    }
+
+    /// Accepts Basic and Bearer credentials.
+    pub fn new<F>(realm: &str, verifier: F) -> Self
+    where
+        F: Fn(&Credentials) -> Option<P> + Send + Sync + 'static,
+    {
+        Self::with_schemes(realm, true, true, verifier)
+    }
+
+    pub fn basic<F>(realm: &str, verifier: F) -> Self
+    where
+        F: Fn(&Credentials) -> Option<P> + Send + Sync + 'static,
+    {
+        Self::with_schemes(realm, true, false, verifier)
+    }
+
+    pub fn bearer<F>(realm: &str, verifier: F) -> Self
+    where
+        F: Fn(&Credentials) -> Option<P> + Send + Sync + 'static,
+    {
+        Self::with_schemes(realm, false, true, verifier)
+    }
+
+    /// Principal of the request, or `None` if its credentials are missing or rejected.
+    pub fn authenticate(&self, request: &HttpRequest) -> Option<P> {
+        let credentials = Credentials::from_request(request)?;
+        let accepted = match credentials {
+            Credentials::Basic { .. } => self.basic,
+            Credentials::Bearer(_) => self.bearer,
+        };
+        if !accepted {
+            return None;
+        }
+        (self.verifier)(&credentials)
+    }
+
+    fn challenge(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+        if self.basic {
+            response.add_header(
+                "WWW-Authenticate".to_owned(),
+                format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm),
+            );
+        }
+        if self.bearer {
+            // RFC 6750: tell a client that sent a token that it was rejected
+            let sent_token = matches!(
+                Credentials::from_request(request),
+                Some(Credentials::Bearer(_))
+            );
+            let challenge = if sent_token {
+                format!("Bearer realm=\"{}\", error=\"invalid_token\"", realm)
+            } else {
+                format!("Bearer realm=\"{}\"", realm)
+            };
+            response.add_header("WWW-Authenticate".to_owned(), challenge);
+        }
+        response.set_error(HttpError::new(401, "Authentication required"));
+    }
+
+    /// Middleware storing the principal in `request.extensions`, for a route or for every
+    /// request with `HttpServer::before`. CORS preflights go through without a principal, as
+    /// browsers never send credentials with them.
+    pub fn middleware<T>(
+        &self,
+    ) -> impl Fn(&mut HttpRequest, &mut HttpResponse, State<T>) -> bool + Send + Sync {
+        let auth = self.clone();
+        move |request, response, _| match auth.authenticate(request) {
+            None if is_preflight(request) => true,
+            Some(principal) => {
+                request.extensions.insert(principal);
+                true
+            }
+            None => {
+                auth.challenge(request, response);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{extract, Cors, Extension, HttpServer, TestClient};
+    use super::*;
+
+    fn request(authorization: &str) -> HttpRequest {
+        let mut request = HttpRequest::new_with_uri("/admin".to_owned());
+        request
+            .headers
+            .push(("Authorization".to_owned(), authorization.to_owned()));
+        request
+    }
+
+    #[test]
+    fn parse_credentials() {
+        // moon:web:server, the password keeps its colons
+        let basic = Credentials::from_request(&request("Basic bW9vbjp3ZWI6c2VydmVy"));
+        assert_eq!(
+            basic,
+            Some(Credentials::Basic {
+                username: "moon".to_owned(),
+                password: "web:server".to_owned(),
+            })
+        );
+        assert!(basic.unwrap().is_basic("moon", "web:server"));
+        let bearer = Credentials::from_request(&request("bearer  abc.def ")).unwrap();
+        assert!(bearer.is_bearer("abc.def"));
+        assert!(!bearer.is_bearer("abc.de"));
+        assert_eq!(Credentials::from_request(&request("Basic !!!")), None);
+        assert_eq!(Credentials::from_request(&request("Basic bW9vbg==")), None);
+        assert_eq!(Credentials::from_request(&request("Digest abc")), None);
+        assert_eq!(
+            Credentials::from_request(&HttpRequest::new_with_uri("/".to_owned())),
+            None
+        );
+    }
+
+    #[test]
+    fn schemes() {
+        let verifier = |credentials: &Credentials| {
+            if credentials.is_basic("moon", "web") || credentials.is_bearer("token") {
+                Some("admin".to_owned())
+            } else {
+                None
+            }
+        };
+        let basic = Auth::basic("admin", verifier);
+        let bearer = Auth::bearer("admin", verifier);
+        assert_eq!(
+            basic.authenticate(&request("Basic bW9vbjp3ZWI=")),
+            Some("admin".to_owned())
+        );
+        assert_eq!(basic.authenticate(&request("Bearer token")), None);
+        assert_eq!(
+            bearer.authenticate(&request("Bearer token")),
+            Some("admin".to_owned())
+        );
+        assert_eq!(bearer.authenticate(&request("Bearer other")), None);
+    }
+
+    #[test]
+    fn compare_secrets() {
+        assert!(constant_time_eq("token", "token"));
+        assert!(!constant_time_eq("token", "tokentoken"));
+        assert!(!constant_time_eq("tokentoken", "token"));
+        assert!(!constant_time_eq("", "token"));
+        assert!(!constant_time_eq("token", ""));
+        assert!(constant_time_eq("", ""));
+    }
+
+    #[test]
+    fn preflights_skip_authentication() {
+        let mut server = HttpServer::new(());
+        server.before(Auth::bearer("admin", |_| Some(())).middleware());
+        let mut cors = Cors::new();
+        cors.allow_origin("https://moon.example");
+        cors.set_allowed_headers(&["Authorization"]);
+        server.set_cors(cors);
+        server.get("/admin", &|_, response, _| {
+            response.set_body("admin".to_owned())
+        });
+        let mut client = TestClient::new(&server);
+
+        let mut preflight = client.request("OPTIONS", "/admin");
+        preflight.headers = vec![
+            ("Origin".to_owned(), "https://moon.example".to_owned()),
+            ("Access-Control-Request-Method".to_owned(), "GET".to_owned()),
+            (
+                "Access-Control-Request-Headers".to_owned(),
+                "authorization".to_owned(),
+            ),
+        ];
+        let response = client.send(preflight);
+        assert_eq!(response.status_code(), 204);
+        assert_eq!(
+            response.header("Access-Control-Allow-Origin"),
+            Some("https://moon.example")
+        );
+
+        let response = client.get("/admin");
+        assert_eq!(response.status_code(), 401);
+    }
+
+    #[test]
+    fn authentication() {
+        #[derive(Clone)]
+        struct User(String);
+
+        let mut server = HttpServer::new(());
+        let auth = Auth::new("moon", |credentials: &Credentials| match credentials {
+            Credentials::Basic { username, .. } if credentials.is_basic("moon", "secret") => {
+                Some(User(username.clone()))
+            }
+            _ if credentials.is_bearer("token") => Some(User("bot".to_owned())),
+            _ => None,
+        });
+        server.before(auth.middleware());
+        server.route(
+            "GET",
+            "/admin",
+            extract(|Extension(User(name)): Extension<User>| format!("hi {}", name)),
+        );
+        let mut client = TestClient::new(&server);
+        let send = |client: &mut TestClient<()>, authorization: &str| {
+            let mut request = client.request("GET", "/admin");
+            request
+                .headers
+                .push(("Authorization".to_owned(), authorization.to_owned()));
+            client.send(request)
+        };
+
+        // bW9vbjpzZWNyZXQ= is moon:secret
+        assert_eq!(
+            send(&mut client, "Basic bW9vbjpzZWNyZXQ=").body(),
+            b"hi moon"
+        );
+        assert_eq!(send(&mut client, "Bearer token").body(), b"hi bot");
+
+        let response = client.get("/admin");
+        assert_eq!(response.status_code(), 401);
+        let challenges: Vec<&str> = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| name == "WWW-Authenticate")
+            .map(|(_, value)| value.as_str())
+            .collect();
+        assert_eq!(
+            challenges,
+            vec![
+                "Basic realm=\"moon\", charset=\"UTF-8\"",
+                "Bearer realm=\"moon\""
+            ]
+        );
+        let response = send(&mut client, "Bearer wrong");
+        assert_eq!(response.status_code(), 401);
+        assert!(response
+            .headers()
+            .iter()
+            .any(|(_, value)| value.contains("error=\"invalid_token\"")));
+        // bW9vbjp3cm9uZw== is moon:wrong
+        assert_eq!(
+            send(&mut client, "Basic bW9vbjp3cm9uZw==").status_code(),
+            401
+        );
+    }
+}
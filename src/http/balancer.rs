@@ -0,0 +1,417 @@
+use super::proxy::{read_head, ProxyError, Upstream};
+use super::server::State;
+use super::{HttpRequest, HttpResponse};
+use crate::json::JsonValue;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How a pool picks the upstream of the next request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Balance {
+    RoundRobin,
+    LeastConnections,
+    /// Requests with the same value of the header go to the same upstream while it's
+    /// available, requests without it are spread round-robin.
+    HeaderHash(String),
+}
+
+#[derive(Debug)]
+struct UpstreamState {
+    upstream: Upstream,
+    active: AtomicUsize,
+    requests: AtomicU64,
+    failures: AtomicU64,
+    consecutive_failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+    healthy: AtomicBool,
+}
+
+impl UpstreamState {
+    fn is_ejected(&self) -> bool {
+        self.ejected_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn is_available(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed) && !self.is_ejected()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct HealthCheck {
+    path: String,
+    interval: Duration,
+    timeout: Duration,
+}
+
+/// Snapshot of an upstream of a pool, for debugging.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamStatus {
+    pub upstream: Upstream,
+    /// Result of the last health check, `true` until the first one.
+    pub healthy: bool,
+    /// Taken out of the pool for a while after failing too many requests in a row.
+    pub ejected: bool,
+    pub active_connections: usize,
+    pub requests: u64,
+    pub failures: u64,
+}
+
+impl From<&UpstreamStatus> for JsonValue {
+    fn from(status: &UpstreamStatus) -> JsonValue {
+        let upstream = &status.upstream;
+        let mut object = HashMap::new();
+        object.insert(
+            "upstream".to_owned(),
+            JsonValue::from(format!(
+                "http://{}:{}{}",
+                upstream.host, upstream.port, upstream.path
+            )),
+        );
+        object.insert("healthy".to_owned(), JsonValue::from(status.healthy));
+        object.insert("ejected".to_owned(), JsonValue::from(status.ejected));
+        object.insert(
+            "active_connections".to_owned(),
+            JsonValue::from(status.active_connections as f64),
+        );
+        object.insert(
+            "requests".to_owned(),
+            JsonValue::from(status.requests as f64),
+        );
+        object.insert(
+            "failures".to_owned(),
+            JsonValue::from(status.failures as f64),
+        );
+        JsonValue::from(object)
+    }
+}
+
+/// Upstreams sharing the requests of a proxied route.
+#[derive(Debug)]
+pub struct UpstreamPool {
+    upstreams: Vec<UpstreamState>,
+    balance: Balance,
+    next: AtomicUsize,
+    max_failures: u32,
+    ejection_time: Duration,
+    health_check: Option<HealthCheck>,
+}
+
+impl UpstreamPool {
+    /// Panics if one of the upstreams isn't a plain `http://host:port/path` URL.
+    pub fn new(upstreams: &[&str], balance: Balance) -> Self {
+        assert!(!upstreams.is_empty(), "Upstream pool without upstreams");
+        let upstreams = upstreams
+            .iter()
+            .map(|upstream| UpstreamState {
+                upstream: Upstream::parse(upstream)
+                    .unwrap_or_else(|| panic!("Invalid upstream {:?}", upstream)),
+                active: AtomicUsize::new(0),
+                requests: AtomicU64::new(0),
+                failures: AtomicU64::new(0),
+                consecutive_failures: AtomicU32::new(0),
+                ejected_until: Mutex::new(None),
+                healthy: AtomicBool::new(true),
+            })
+            .collect();
+        UpstreamPool {
+            upstreams,
+            balance,
+            next: AtomicUsize::new(0),
+            max_failures: 3,
+            ejection_time: Duration::from_secs(30),
+            health_check: None,
+        }
+    }
+
+    /// Takes an upstream out of the pool for `ejection_time` after `max_failures` failed
+    /// requests in a row, 0 turns ejection off. Pools of one upstream never eject it.
+    pub fn set_passive_ejection(&mut self, max_failures: u32, ejection_time: Duration) {
+        self.max_failures = max_failures;
+        self.ejection_time = ejection_time;
+    }
+
+    /// Sends `GET path` to every upstream each `interval` once the proxy handler is built.
+    /// Upstreams answering with anything but 2xx or 3xx get no requests until they recover.
+    pub fn set_health_check(&mut self, path: &str, interval: Duration) {
+        self.health_check = Some(HealthCheck {
+            path: path.to_owned(),
+            interval,
+            timeout: interval.min(Duration::from_secs(5)),
+        });
+    }
+
+    pub fn status(&self) -> Vec<UpstreamStatus> {
+        self.upstreams
+            .iter()
+            .map(|state| UpstreamStatus {
+                upstream: state.upstream.clone(),
+                healthy: state.healthy.load(Ordering::Relaxed),
+                ejected: state.is_ejected(),
+                active_connections: state.active.load(Ordering::Relaxed),
+                requests: state.requests.load(Ordering::Relaxed),
+                failures: state.failures.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    pub(super) fn upstream(&self, index: usize) -> &Upstream {
+        &self.upstreams[index].upstream
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.upstreams.len()
+    }
+
+    /// Index of the upstream for `request`, skipping unavailable upstreams and the ones
+    /// in `tried`.
+    pub(super) fn choose(&self, request: &HttpRequest, tried: &[usize]) -> Option<usize> {
+        let len = self.upstreams.len();
+        let usable =
+            |index: &usize| !tried.contains(index) && self.upstreams[*index].is_available();
+        let hash = match &self.balance {
+            Balance::HeaderHash(name) => request.header(name).map(fnv1a),
+            _ => None,
+        };
+        let start = match hash {
+            Some(hash) => (hash % len as u64) as usize,
+            None => self.next.fetch_add(1, Ordering::Relaxed) % len,
+        };
+        let mut candidates = (start..len).chain(0..start).filter(usable);
+        if self.balance == Balance::LeastConnections {
+            candidates.min_by_key(|index| self.upstreams[*index].active.load(Ordering::Relaxed))
+        } else {
+            candidates.next()
+        }
+    }
+
+    /// Counts a request to the upstream at `index` until the returned guard is dropped.
+    pub(super) fn start_request(self: &Arc<Self>, index: usize) -> ActiveRequest {
+        let state = &self.upstreams[index];
+        state.requests.fetch_add(1, Ordering::Relaxed);
+        state.active.fetch_add(1, Ordering::Relaxed);
+        ActiveRequest {
+            pool: Arc::clone(self),
+            index,
+        }
+    }
+
+    pub(super) fn report_success(&self, index: usize) {
+        self.upstreams[index]
+            .consecutive_failures
+            .store(0, Ordering::Relaxed);
+    }
+
+    pub(super) fn report_failure(&self, index: usize) {
+        let state = &self.upstreams[index];
+        state.failures.fetch_add(1, Ordering::Relaxed);
+        let failures = state.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.max_failures > 0 && self.upstreams.len() > 1 && failures >= self.max_failures {
+            *state.ejected_until.lock().unwrap() = Some(Instant::now() + self.ejection_time);
+            state.consecutive_failures.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Probes every upstream once. An upstream passing the check is back in the pool
+    /// even if it was ejected.
+    pub fn check_health(&self) {
+        let check = match &self.health_check {
+            Some(check) => check,
+            None => return,
+        };
+        for state in &self.upstreams {
+            let healthy =
+                probe(&state.upstream, check).is_ok_and(|code| (200..400).contains(&code));
+            state.healthy.store(healthy, Ordering::Relaxed);
+            if healthy && state.is_ejected() {
+                *state.ejected_until.lock().unwrap() = None;
+            }
+        }
+    }
+
+    /// Runs the health checks in the background until the pool is dropped.
+    pub(super) fn start_health_checks(self: &Arc<Self>) {
+        let interval = match &self.health_check {
+            Some(check) => check.interval,
+            None => return,
+        };
+        let pool: Weak<Self> = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            match pool.upgrade() {
+                Some(pool) => pool.check_health(),
+                None => return,
+            }
+            thread::sleep(interval);
+        });
+    }
+
+    /// Route handler answering with the status of every upstream as JSON.
+    pub fn status_handler<T>(
+        self: &Arc<Self>,
+    ) -> impl Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync {
+        let pool = Arc::clone(self);
+        move |_, response, _| {
+            let status: JsonValue = pool.status().iter().map(JsonValue::from).collect();
+            response.add_header("Content-Type".to_owned(), "application/json".to_owned());
+            response.set_body(status.stringify());
+        }
+    }
+}
+
+/// A request in flight to an upstream of a pool, for least-connections balancing.
+pub(super) struct ActiveRequest {
+    pool: Arc<UpstreamPool>,
+    index: usize,
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        self.pool.upstreams[self.index]
+            .active
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn probe(upstream: &Upstream, check: &HealthCheck) -> Result<u16, ProxyError> {
+    let mut stream = upstream.connect(check.timeout, check.timeout)?;
+    write!(
+        stream,
+        "GET {}{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        upstream.path,
+        check.path,
+        upstream.authority()
+    )?;
+    let (head, _) = read_head(&mut stream)?;
+    Ok(head.status_code)
+}
+
+// https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn request(user: Option<&str>) -> HttpRequest {
+        let mut request = HttpRequest::new_with_uri("/".to_owned());
+        if let Some(user) = user {
+            request.headers = vec![("X-User".to_owned(), user.to_owned())];
+        }
+        request
+    }
+
+    fn pool(balance: Balance) -> Arc<UpstreamPool> {
+        Arc::new(UpstreamPool::new(&["a:1", "b:2", "c:3"], balance))
+    }
+
+    #[test]
+    fn round_robin() {
+        let pool = pool(Balance::RoundRobin);
+        let chosen: Vec<_> = (0..4)
+            .map(|_| pool.choose(&request(None), &[]).unwrap())
+            .collect();
+        assert_eq!(chosen, vec![0, 1, 2, 0]);
+        assert_eq!(pool.choose(&request(None), &[1, 2]), Some(0));
+        assert_eq!(pool.choose(&request(None), &[0, 1, 2]), None);
+    }
+
+    #[test]
+    fn least_connections() {
+        let pool = pool(Balance::LeastConnections);
+        let first = pool.start_request(0);
+        let _second = pool.start_request(1);
+        assert_eq!(pool.choose(&request(None), &[]), Some(2));
+        let _third = pool.start_request(2);
+        drop(first);
+        assert_eq!(pool.choose(&request(None), &[]), Some(0));
+        assert_eq!(pool.status()[1].active_connections, 1);
+        assert_eq!(pool.status()[0].requests, 1);
+    }
+
+    #[test]
+    fn header_hash() {
+        let pool = pool(Balance::HeaderHash("X-User".to_owned()));
+        let alice = pool.choose(&request(Some("alice")), &[]).unwrap();
+        for _ in 0..5 {
+            assert_eq!(pool.choose(&request(Some("alice")), &[]), Some(alice));
+        }
+        // Another upstream takes over while it's gone
+        for _ in 0..3 {
+            pool.report_failure(alice);
+        }
+        assert_ne!(pool.choose(&request(Some("alice")), &[]), Some(alice));
+    }
+
+    #[test]
+    fn passive_ejection() {
+        let mut pool = UpstreamPool::new(&["a:1", "b:2"], Balance::RoundRobin);
+        pool.set_passive_ejection(2, Duration::from_millis(50));
+        pool.report_failure(0);
+        pool.report_success(0);
+        pool.report_failure(0);
+        assert!(!pool.status()[0].ejected);
+        pool.report_failure(0);
+        assert!(pool.status()[0].ejected);
+        assert_eq!(pool.status()[0].failures, 3);
+        assert_eq!(pool.choose(&request(None), &[]), Some(1));
+        assert_eq!(pool.choose(&request(None), &[]), Some(1));
+        thread::sleep(Duration::from_millis(60));
+        assert!(!pool.status()[0].ejected);
+
+        let single = UpstreamPool::new(&["a:1"], Balance::RoundRobin);
+        for _ in 0..5 {
+            single.report_failure(0);
+        }
+        assert_eq!(single.choose(&request(None), &[]), Some(0));
+    }
+
+    #[test]
+    fn active_health_checks() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let healthy = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // Reads the whole request, closing before that can reset the connection
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match std::io::Read::read(&mut stream, &mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&buffer[..read]),
+                }
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .unwrap();
+        });
+        // Nothing listens on the port once the listener is dropped
+        let down = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let mut pool = UpstreamPool::new(&[&healthy, &down], Balance::RoundRobin);
+        pool.set_health_check("/health", Duration::from_secs(1));
+        pool.check_health();
+        let status = pool.status();
+        assert!(status[0].healthy);
+        assert!(!status[1].healthy);
+        for _ in 0..3 {
+            assert_eq!(pool.choose(&request(None), &[]), Some(0));
+        }
+
+        let json = JsonValue::from(&status[1]).stringify();
+        assert!(json.contains("\"healthy\":false"));
+    }
+}
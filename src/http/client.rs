@@ -4,7 +4,7 @@ use super::response::HttpResponse;
 use super::url::{URLParser, URL};
 use super::HttpHeaders;
 use super::HttpParserError;
-use std::{collections::HashMap, str};
+use std::str;
 use std::io::prelude::*;
 use std::net::TcpStream;
 use std::fmt;
@@ -70,15 +70,13 @@ fn send_http_request_impl(url: &str, mut headers: HttpHeaders) -> Result<HttpRes
     let host = format!("{}:{}", url.host, url.port);
     let mut req_headers = vec![(String::from("Host"), String::from(&host))];
     req_headers.append(&mut headers);
-    let request = HttpRequest {
-        method: String::from("GET"),
-        body: String::new(),
-        uri: get_uri(&url),
-        version: String::from("1.1"),
-        headers: req_headers,
-        query: HashMap::new(),
-        params: HashMap::new(),
-    };
+    let request = HttpRequest::new(
+        String::from("GET"),
+        get_uri(&url),
+        String::from("1.1"),
+        req_headers,
+        String::new(),
+    );
     let mut stream = match TcpStream::connect(&host) {
         Ok(stream) => stream,
         Err(err) => return Err(HttpClientError::ConnectionError(ConnectionError { tcp_error: err, host: host.to_owned() }))
@@ -101,3 +99,44 @@ fn send_http_request_impl(url: &str, mut headers: HttpHeaders) -> Result<HttpRes
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::server::HttpServer;
+    use super::super::test_server::TestServer;
+    use super::*;
+
+    fn server() -> TestServer {
+        let mut server = HttpServer::new(());
+        server.get("/headers", &|request, response, _| {
+            if let Some(user_agent) = request.header("User-Agent") {
+                response.add_header("X-User-Agent".to_owned(), user_agent.to_owned());
+            }
+            response.set_body(request.uri.clone());
+        });
+        TestServer::start(server).unwrap()
+    }
+
+    #[test]
+    fn send_request() {
+        let server = server();
+        let response = send_http_request(&server.url("/headers")).unwrap();
+        assert_eq!(response.status_code(), 200);
+        assert!(String::from_utf8_lossy(response.body()).contains("/headers"));
+
+        let headers = vec![(String::from("User-Agent"), String::from("moon"))];
+        let response = send_http_request_with_headers(&server.url("/headers"), headers).unwrap();
+        assert_eq!(response.header("X-User-Agent"), Some("moon"));
+
+        let response = send_http_request(&server.url("/missing")).unwrap();
+        assert_eq!(response.status_code(), 404);
+    }
+
+    #[test]
+    fn connection_error() {
+        // the .invalid top-level domain is reserved and never resolves (RFC 6761)
+        let err = send_http_request("http://server.invalid/").unwrap_err();
+        assert!(err.to_string().starts_with("Error connecting to \"server.invalid:80\""));
+        assert!(matches!(err, HttpClientError::ConnectionError(_)));
+    }
+}
@@ -0,0 +1,383 @@
+// https://tools.ietf.org/html/rfc7232
+use super::date::parse_http_date;
+use super::server::State;
+use super::{HttpHeaders, HttpRequest, HttpResponse};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ETagStrength {
+    Strong,
+    Weak,
+}
+
+#[derive(Debug, PartialEq)]
+struct EntityTag {
+    weak: bool,
+    opaque: String,
+}
+
+impl EntityTag {
+    fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.opaque == other.opaque
+    }
+
+    fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.opaque == other.opaque
+    }
+}
+
+// 64-bit FNV-1a, stable between runs unlike std's DefaultHasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Builds an entity tag for a response body, e.g. `"af63bd4c8601b7be"` or
+/// `W/"af63bd4c8601b7be"`.
+pub fn etag(body: &[u8], strength: ETagStrength) -> String {
+    let opaque = format!("\"{:016x}\"", fnv1a(body));
+    match strength {
+        ETagStrength::Strong => opaque,
+        ETagStrength::Weak => format!("W/{}", opaque),
+    }
+}
+
+fn parse_entity_tag(input: &str) -> Option<EntityTag> {
+    let input = input.trim();
+    let (weak, quoted) = match input.strip_prefix("W/") {
+        Some(rest) => (true, rest),
+        None => (false, input),
+    };
+    if quoted.len() < 2 || !quoted.starts_with('"') || !quoted.ends_with('"') {
+        return None;
+    }
+    Some(EntityTag {
+        weak,
+        opaque: quoted[1..quoted.len() - 1].to_owned(),
+    })
+}
+
+// None means the header value is "*"
+fn parse_entity_tag_list(input: &str) -> Option<Vec<EntityTag>> {
+    if input.trim() == "*" {
+        return None;
+    }
+    let mut tags = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (i, ch) in input.char_indices() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                tags.extend(parse_entity_tag(&input[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    tags.extend(parse_entity_tag(&input[start..]));
+    Some(tags)
+}
+
+/// Strong comparison of two entity tags, as `If-Range` requires.
+pub fn strong_etag_match(a: &str, b: &str) -> bool {
+    match (parse_entity_tag(a), parse_entity_tag(b)) {
+        (Some(a), Some(b)) => a.strong_eq(&b),
+        _ => false,
+    }
+}
+
+fn is_get_or_head(request: &HttpRequest) -> bool {
+    request.method == "GET" || request.method == "HEAD"
+}
+
+/// Evaluates `If-Match`, `If-Unmodified-Since`, `If-None-Match` and `If-Modified-Since`
+/// against the `ETag` and `Last-Modified` headers of a response, in the order of RFC 7232
+/// section 6. Returns the status code the response should be replaced with, if any.
+pub fn evaluate_preconditions(request: &HttpRequest, response: &HttpResponse) -> Option<u16> {
+    let current_etag = response.header("ETag").and_then(parse_entity_tag);
+
+    if let Some(if_match) = request.header("If-Match") {
+        let matches = match parse_entity_tag_list(if_match) {
+            None => true,
+            Some(tags) => match &current_etag {
+                None => false,
+                Some(current) => tags.iter().any(|tag| tag.strong_eq(current)),
+            },
+        };
+        if !matches {
+            return Some(412);
+        }
+    } else if let Some(if_unmodified_since) = request
+        .header("If-Unmodified-Since")
+        .and_then(parse_http_date)
+    {
+        // without a Last-Modified the resource can't be shown unmodified
+        let modified = match response.header("Last-Modified").and_then(parse_http_date) {
+            Some(last_modified) => last_modified > if_unmodified_since,
+            None => true,
+        };
+        if modified {
+            return Some(412);
+        }
+    }
+
+    if let Some(if_none_match) = request.header("If-None-Match") {
+        let matches = match parse_entity_tag_list(if_none_match) {
+            None => true,
+            Some(tags) => match &current_etag {
+                None => false,
+                Some(current) => tags.iter().any(|tag| tag.weak_eq(current)),
+            },
+        };
+        if !matches {
+            return None;
+        }
+        return if is_get_or_head(request) {
+            Some(304)
+        } else {
+            Some(412)
+        };
+    }
+
+    if !is_get_or_head(request) {
+        return None;
+    }
+    let if_modified_since = request
+        .header("If-Modified-Since")
+        .and_then(parse_http_date)?;
+    let last_modified = response.header("Last-Modified").and_then(parse_http_date)?;
+    if last_modified <= if_modified_since {
+        return Some(304);
+    }
+    None
+}
+
+/// Runs `evaluate_preconditions` and replaces the response with a 304 or a 412 when they
+/// fail. Returns whether the response was replaced.
+pub fn apply_preconditions(request: &HttpRequest, response: &mut HttpResponse) -> bool {
+    match evaluate_preconditions(request, response) {
+        Some(304) => not_modified(response),
+        Some(412) => precondition_failed(response),
+        _ => return false,
+    }
+    true
+}
+
+fn not_modified(response: &mut HttpResponse) {
+    response.set_status_code(304);
+    response.set_body(String::new());
+    response
+        .headers_mut()
+        .retain(|(key, _)| !key.to_ascii_lowercase().starts_with("content-"));
+}
+
+fn precondition_failed(response: &mut HttpResponse) {
+    response.set_status_code(412);
+    response.set_body(String::from("Precondition failed"));
+    response
+        .headers_mut()
+        .retain(|(key, _)| !key.to_ascii_lowercase().starts_with("content-"));
+}
+
+/// After middleware for conditional `GET` and `HEAD` requests. Successful responses get an
+/// `ETag` computed from their body unless the handler already set one, then the request
+/// preconditions are checked and the response is turned into a 304 or a 412 if needed.
+/// Other methods are left alone, their handler already ran, see `check_preconditions`.
+pub fn conditional_get<T>(
+    strength: ETagStrength,
+) -> impl Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync {
+    move |request, response, _| {
+        if !is_get_or_head(request)
+            || response.status_code() < 200
+            || response.status_code() > 299
+            || response.upgrade.is_some()
+        {
+            return;
+        }
+        if response.status_code() == 200 && response.header("ETag").is_none() {
+            let etag = etag(response.body(), strength);
+            response.add_header("ETag".to_owned(), etag);
+        }
+        apply_preconditions(request, response);
+    }
+}
+
+/// Middleware checking the preconditions of requests with other methods than `GET` and
+/// `HEAD`, like `If-Match` on a `PUT`, before their handler changes anything. `current`
+/// returns the `ETag` and `Last-Modified` headers the resource has now, empty if it doesn't
+/// exist. Requests failing them get a 412 without calling the handler.
+pub fn check_preconditions<T, F>(
+    current: F,
+) -> impl Fn(&mut HttpRequest, &mut HttpResponse, State<T>) -> bool + Send + Sync
+where
+    F: Fn(&HttpRequest, State<T>) -> HttpHeaders + Send + Sync,
+{
+    move |request, response, state| {
+        let conditional = ["If-Match", "If-Unmodified-Since", "If-None-Match"]
+            .iter()
+            .any(|header| request.header(header).is_some());
+        if is_get_or_head(request) || !conditional {
+            return true;
+        }
+        let mut validators = HttpResponse::new();
+        *validators.headers_mut() = current(request, state);
+        if evaluate_preconditions(request, &validators) == Some(412) {
+            precondition_failed(response);
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn request_with_header(method: &str, key: &str, value: &str) -> HttpRequest {
+        let mut request = HttpRequest::new_with_uri("/".to_owned());
+        request.method = method.to_owned();
+        request.headers.push((key.to_owned(), value.to_owned()));
+        request
+    }
+
+    fn response_with_body(body: &str) -> HttpResponse {
+        let mut response = HttpResponse::new();
+        response.set_body(body.to_owned());
+        response
+    }
+
+    #[test]
+    fn etag_strength() {
+        assert_eq!(etag(b"test", ETagStrength::Strong), "\"f9e6e6ef197c2b25\"");
+        assert_eq!(etag(b"test", ETagStrength::Weak), "W/\"f9e6e6ef197c2b25\"");
+    }
+
+    #[test]
+    fn parse_entity_tags() {
+        let tags = parse_entity_tag_list("W/\"a,b\", \"c\"").unwrap();
+        assert_eq!(
+            tags,
+            vec![
+                EntityTag {
+                    weak: true,
+                    opaque: "a,b".to_owned()
+                },
+                EntityTag {
+                    weak: false,
+                    opaque: "c".to_owned()
+                }
+            ]
+        );
+        assert_eq!(parse_entity_tag_list(" * "), None);
+    }
+
+    #[test]
+    fn if_none_match_returns_not_modified() {
+        let middleware = conditional_get::<()>(ETagStrength::Weak);
+        let request = request_with_header("GET", "If-None-Match", "\"f9e6e6ef197c2b25\"");
+        let mut response = response_with_body("test");
+        response.add_header("Content-Type".to_owned(), "text/plain".to_owned());
+        middleware(&request, &mut response, Arc::new(Mutex::new(())));
+        assert_eq!(response.status_code(), 304);
+        assert_eq!(response.body(), b"");
+        assert_eq!(response.header("Content-Type"), None);
+        assert_eq!(response.header("ETag"), Some("W/\"f9e6e6ef197c2b25\""));
+    }
+
+    #[test]
+    fn if_none_match_with_changed_body() {
+        let middleware = conditional_get::<()>(ETagStrength::Strong);
+        let request = request_with_header("GET", "If-None-Match", "\"f9e6e6ef197c2b25\"");
+        let mut response = response_with_body("changed");
+        middleware(&request, &mut response, Arc::new(Mutex::new(())));
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), b"changed");
+    }
+
+    #[test]
+    fn if_match_uses_strong_comparison() {
+        let mut response = response_with_body("test");
+        response.add_header("ETag".to_owned(), "W/\"v1\"".to_owned());
+        let request = request_with_header("PUT", "If-Match", "W/\"v1\"");
+        assert_eq!(evaluate_preconditions(&request, &response), Some(412));
+        let request = request_with_header("PUT", "If-Match", "*");
+        assert_eq!(evaluate_preconditions(&request, &response), None);
+    }
+
+    #[test]
+    fn if_none_match_on_unsafe_method_fails() {
+        let mut response = response_with_body("test");
+        response.add_header("ETag".to_owned(), "\"v1\"".to_owned());
+        let request = request_with_header("POST", "If-None-Match", "*");
+        assert_eq!(evaluate_preconditions(&request, &response), Some(412));
+    }
+
+    #[test]
+    fn unsafe_methods_checked_before_the_handler() {
+        let middleware = check_preconditions::<(), _>(|_, _| {
+            vec![
+                ("ETag".to_owned(), "\"v2\"".to_owned()),
+                (
+                    "Last-Modified".to_owned(),
+                    "Sun, 06 Nov 1994 08:49:37 GMT".to_owned(),
+                ),
+            ]
+        });
+        let check = |request: &mut HttpRequest| {
+            let mut response = HttpResponse::new();
+            let proceed = middleware(request, &mut response, Arc::new(Mutex::new(())));
+            (proceed, response.status_code())
+        };
+        assert_eq!(
+            check(&mut request_with_header("PUT", "If-Match", "\"v1\"")),
+            (false, 412)
+        );
+        assert_eq!(
+            check(&mut request_with_header("PUT", "If-Match", "\"v2\"")),
+            (true, 200)
+        );
+        assert_eq!(
+            check(&mut request_with_header(
+                "DELETE",
+                "If-Unmodified-Since",
+                "Sat, 05 Nov 1994 08:49:37 GMT"
+            )),
+            (false, 412)
+        );
+        assert_eq!(
+            check(&mut request_with_header("PUT", "If-None-Match", "*")),
+            (false, 412)
+        );
+        assert_eq!(
+            check(&mut request_with_header("GET", "If-Match", "\"v1\"")),
+            (true, 200)
+        );
+
+        // the after middleware leaves responses to unsafe methods alone
+        let after = conditional_get::<()>(ETagStrength::Strong);
+        let request = request_with_header("PUT", "If-Match", "\"v1\"");
+        let mut response = response_with_body("saved");
+        after(&request, &mut response, Arc::new(Mutex::new(())));
+        assert_eq!(response.status_code(), 200);
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let mut response = response_with_body("test");
+        response.add_header(
+            "Last-Modified".to_owned(),
+            "Sun, 06 Nov 1994 08:49:37 GMT".to_owned(),
+        );
+        let request =
+            request_with_header("GET", "If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(evaluate_preconditions(&request, &response), Some(304));
+        let request =
+            request_with_header("GET", "If-Modified-Since", "Sat, 05 Nov 1994 08:49:37 GMT");
+        assert_eq!(evaluate_preconditions(&request, &response), None);
+    }
+}
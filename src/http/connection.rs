@@ -0,0 +1,99 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// TLS session of a connection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsInfo {
+    pub version: &'static str,
+    pub cipher_suite: &'static str,
+    /// Host name the client sent with SNI.
+    pub server_name: Option<String>,
+    /// Protocol negotiated with ALPN.
+    pub alpn_protocol: Option<String>,
+}
+
+/// Byte stream a request came in on. Upgraded responses (WebSockets, event streams) take it
+/// over once the response head has been sent.
+pub trait Connection: Read + Write + Send {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Address of the other end, `None` for connections that aren't over IP.
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Address of this end, the one the server listens on.
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn is_secure(&self) -> bool {
+        false
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        None
+    }
+}
+
+impl Connection for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        TcpStream::peer_addr(self).ok()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        TcpStream::local_addr(self).ok()
+    }
+}
+
+/// In memory connection for tests. Reads come from `input` and writes go to the shared
+/// output buffer returned by `new`.
+#[cfg(test)]
+pub struct MockConnection {
+    input: io::Cursor<Vec<u8>>,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl MockConnection {
+    pub fn new(input: Vec<u8>) -> (Self, Arc<Mutex<Vec<u8>>>) {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let connection = MockConnection {
+            input: io::Cursor::new(input),
+            output: Arc::clone(&output),
+        };
+        (connection, output)
+    }
+}
+
+#[cfg(test)]
+impl Read for MockConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl Write for MockConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Connection for MockConnection {
+    fn set_read_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+/// Custom constraint for route parameters, see `Router::set_param_constraint`.
+pub type ParamPredicate = dyn Fn(&str) -> bool + Send + Sync;
+
+/// Constraint on the parameter of a route, written after its name: `/hn/:id<int>`,
+/// `/items/:id<uuid>`, `/tags/:tag<[a-z0-9-]+>` or `/users/:name<username>` for a custom one.
+/// `int` takes the digits of a `u64`, without sign.
+#[derive(Debug, PartialEq)]
+pub(super) enum Constraint {
+    Int,
+    Uuid,
+    Class(CharClass),
+    Custom(String),
+}
+
+impl Constraint {
+    pub(super) fn parse(constraint: &str) -> Result<Self, String> {
+        match constraint {
+            "int" => Ok(Constraint::Int),
+            "uuid" => Ok(Constraint::Uuid),
+            _ if constraint.starts_with('[') => CharClass::parse(constraint).map(Constraint::Class),
+            _ if !constraint.is_empty()
+                && constraint
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '_') =>
+            {
+                Ok(Constraint::Custom(constraint.to_owned()))
+            }
+            _ => Err(format!("Invalid parameter constraint <{}>", constraint)),
+        }
+    }
+
+    /// Whether `value` satisfies the constraint. Unknown custom constraints never do, routes
+    /// using them are rejected when they're added.
+    pub(super) fn matches(
+        &self,
+        value: &str,
+        custom: &HashMap<String, Box<ParamPredicate>>,
+    ) -> bool {
+        match self {
+            Constraint::Int => {
+                value.bytes().all(|byte| byte.is_ascii_digit()) && value.parse::<u64>().is_ok()
+            }
+            Constraint::Uuid => is_uuid(value),
+            Constraint::Class(class) => class.matches(value),
+            Constraint::Custom(name) => custom.get(name).is_some_and(|predicate| predicate(value)),
+        }
+    }
+}
+
+/// Splits a route parameter like `id<int>` in its name and constraint.
+pub(super) fn split_parameter(parameter: &str) -> (&str, Option<&str>) {
+    match parameter.find('<') {
+        Some(start) if parameter.ends_with('>') => (
+            &parameter[..start],
+            Some(&parameter[start + 1..parameter.len() - 1]),
+        ),
+        _ => (parameter, None),
+    }
+}
+
+fn is_uuid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip(&[8, 4, 4, 4, 12]).all(|(group, length)| {
+            group.len() == *length && group.bytes().all(|byte| byte.is_ascii_hexdigit())
+        })
+}
+
+/// Regex-like character class with an optional quantifier: `[a-z0-9_-]+`, `[^.]*` or
+/// `[0-9]{4}`. Without quantifier it matches a single character.
+#[derive(Debug, PartialEq)]
+pub(super) struct CharClass {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+    min: usize,
+    max: Option<usize>,
+}
+
+impl CharClass {
+    fn parse(input: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid character class <{}>", input);
+        let body_end = input.rfind(']').ok_or_else(invalid)?;
+        let mut body: Vec<char> = input[1..body_end].chars().collect();
+        let negated = body.first() == Some(&'^');
+        if negated {
+            body.remove(0);
+        }
+        let mut ranges = Vec::new();
+        let mut index = 0;
+        while index < body.len() {
+            let mut start = body[index];
+            if start == '\\' {
+                index += 1;
+                start = *body.get(index).ok_or_else(invalid)?;
+            }
+            // `-` at the start or the end of the class is a literal
+            if body.get(index + 1) == Some(&'-') && index + 2 < body.len() {
+                let mut end = body[index + 2];
+                index += 2;
+                if end == '\\' {
+                    index += 1;
+                    end = *body.get(index).ok_or_else(invalid)?;
+                }
+                if end < start {
+                    return Err(invalid());
+                }
+                ranges.push((start, end));
+            } else {
+                ranges.push((start, start));
+            }
+            index += 1;
+        }
+        if ranges.is_empty() {
+            return Err(invalid());
+        }
+        let (min, max) = match &input[body_end + 1..] {
+            "" => (1, Some(1)),
+            "+" => (1, None),
+            "*" => (0, None),
+            quantifier => {
+                let counts = quantifier
+                    .strip_prefix('{')
+                    .and_then(|counts| counts.strip_suffix('}'))
+                    .ok_or_else(invalid)?;
+                let (min, max) = match counts.split_once(',') {
+                    Some((min, "")) => (min.parse().map_err(|_| invalid())?, None),
+                    Some((min, max)) => (
+                        min.parse().map_err(|_| invalid())?,
+                        Some(max.parse().map_err(|_| invalid())?),
+                    ),
+                    None => {
+                        let count = counts.parse().map_err(|_| invalid())?;
+                        (count, Some(count))
+                    }
+                };
+                if max.is_some_and(|max| max < min) {
+                    return Err(invalid());
+                }
+                (min, max)
+            }
+        };
+        Ok(CharClass {
+            negated,
+            ranges,
+            min,
+            max,
+        })
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        let count = value.chars().count();
+        count >= self.min
+            && match self.max {
+                Some(max) => count <= max,
+                None => true,
+            }
+            && value.chars().all(|ch| {
+                let in_class = self
+                    .ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&ch));
+                in_class != self.negated
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{HttpServer, TestClient};
+    use super::*;
+
+    fn matches(constraint: &str, value: &str) -> bool {
+        let mut custom: HashMap<String, Box<ParamPredicate>> = HashMap::new();
+        custom.insert("even".to_owned(), Box::new(|value| value.len() % 2 == 0));
+        Constraint::parse(constraint)
+            .unwrap()
+            .matches(value, &custom)
+    }
+
+    #[test]
+    fn builtin_constraints() {
+        assert!(matches("int", "42"));
+        assert!(matches("int", "18446744073709551615"));
+        assert!(!matches("int", "18446744073709551616"));
+        assert!(!matches("int", "-7"));
+        assert!(!matches("int", "+7"));
+        assert!(!matches("int", "4x"));
+        assert!(!matches("int", ""));
+        assert!(matches("uuid", "123e4567-e89b-12d3-A456-426614174000"));
+        assert!(!matches("uuid", "123e4567-e89b-12d3-a456-42661417400"));
+        assert!(!matches("uuid", "123e4567e89b12d3a456426614174000"));
+        assert!(matches("even", "ab"));
+        assert!(!matches("even", "abc"));
+        assert!(!matches("unknown", "abc"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(matches("[a-z0-9_-]+", "moon-web_2"));
+        assert!(!matches("[a-z0-9_-]+", "Moon"));
+        assert!(!matches("[a-z]+", ""));
+        assert!(matches("[a-z]*", ""));
+        assert!(matches("[a-z]", "a"));
+        assert!(!matches("[a-z]", "ab"));
+        assert!(matches("[0-9]{4}", "2024"));
+        assert!(!matches("[0-9]{4}", "202"));
+        assert!(matches("[0-9]{2,}", "202"));
+        assert!(matches("[^.]+", "file"));
+        assert!(!matches("[^.]+", "file.txt"));
+        assert!(matches("[\\]a]+", "]a"));
+
+        assert!(Constraint::parse("[z-a]").is_err());
+        assert!(Constraint::parse("[]").is_err());
+        assert!(Constraint::parse("[a-z]?").is_err());
+        assert!(Constraint::parse("[0-9]{3,1}").is_err());
+        assert!(Constraint::parse("in t").is_err());
+    }
+
+    #[test]
+    fn split_parameters() {
+        assert_eq!(split_parameter("id"), ("id", None));
+        assert_eq!(split_parameter("id<int>"), ("id", Some("int")));
+        assert_eq!(split_parameter("tag<[a-z]+>"), ("tag", Some("[a-z]+")));
+    }
+
+    #[test]
+    fn param_constraints() {
+        let mut server = HttpServer::new(());
+        server.route("GET", "/hn/:id<int>", |request, _, _| {
+            format!("item {}", request.params["id"])
+        });
+        server.route("GET", "/hn/:slug<[a-z-]+>", |request, _, _| {
+            format!("list {}", request.params["slug"])
+        });
+        server.set_param_constraint("username", |name| name.len() >= 3);
+        server.route("GET", "/users/:name<username>", |_, _, _| "user");
+        let mut client = TestClient::new(&server);
+        assert_eq!(client.get("/hn/42").body(), b"item 42");
+        assert_eq!(client.get("/hn/top-stories").body(), b"list top-stories");
+        assert_eq!(client.get("/hn/Top").status_code(), 404);
+        assert_eq!(client.get("/hn/-7").status_code(), 404);
+        assert_eq!(client.get("/users/moon").body(), b"user");
+        assert_eq!(client.get("/users/mo").status_code(), 404);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid route /users/:name<username>: Unknown parameter \
+                               constraint <username>")]
+    fn unknown_param_constraint() {
+        HttpServer::new(()).route("GET", "/users/:name<username>", |_, _, _| "");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid route /hn/:id<[z-a]+>: Invalid character class")]
+    fn invalid_param_constraint() {
+        HttpServer::new(()).route("GET", "/hn/:id<[z-a]+>", |_, _, _| "");
+    }
+}
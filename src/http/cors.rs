@@ -0,0 +1,382 @@
+use super::server::State;
+use super::{HttpRequest, HttpResponse};
+use std::sync::Arc;
+use std::time::Duration;
+
+type OriginPredicate = dyn Fn(&str) -> bool + Send + Sync;
+
+#[derive(Clone)]
+enum AllowedOrigin {
+    Any,
+    Exact(String),
+    // `https://*.example.com`, split around the `*`
+    Wildcard(String, String),
+    Predicate(Arc<OriginPredicate>),
+}
+
+impl AllowedOrigin {
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Exact(allowed) => allowed.eq_ignore_ascii_case(origin),
+            Self::Wildcard(prefix, suffix) => {
+                let origin = origin.to_ascii_lowercase();
+                // the `*` only stands for subdomains, not for anything with a `/` or a `:`
+                origin
+                    .strip_prefix(prefix.as_str())
+                    .and_then(|origin| origin.strip_suffix(suffix.as_str()))
+                    .is_some_and(|subdomain| {
+                        !subdomain.is_empty()
+                            && subdomain
+                                .chars()
+                                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '.')
+                    })
+            }
+            Self::Predicate(predicate) => predicate(origin),
+        }
+    }
+}
+
+/// Cross-origin resource sharing rules. No origin is allowed until `allow_origin` or
+/// `allow_origin_fn` is called. Register them with `HttpServer::set_cors`.
+#[derive(Clone)]
+pub struct Cors {
+    origins: Vec<AllowedOrigin>,
+    methods: Vec<String>,
+    headers: Vec<String>,
+    exposed_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cors {
+    pub fn new() -> Self {
+        Cors {
+            origins: Vec::new(),
+            methods: vec!["GET".to_owned(), "HEAD".to_owned(), "POST".to_owned()],
+            headers: Vec::new(),
+            exposed_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allows an origin like `https://app.example.com`, every subdomain with
+    /// `https://*.example.com`, or any origin with `*`.
+    pub fn allow_origin(&mut self, origin: &str) {
+        let origin = origin.to_ascii_lowercase();
+        let allowed = match origin.split_once('*') {
+            _ if origin == "*" => AllowedOrigin::Any,
+            Some((prefix, suffix)) => AllowedOrigin::Wildcard(prefix.to_owned(), suffix.to_owned()),
+            None => AllowedOrigin::Exact(origin),
+        };
+        self.origins.push(allowed);
+    }
+
+    pub fn allow_origin_fn<F>(&mut self, predicate: F)
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.origins
+            .push(AllowedOrigin::Predicate(Arc::new(predicate)));
+    }
+
+    /// Methods preflights can ask for, `GET`, `HEAD` and `POST` by default.
+    pub fn set_allowed_methods(&mut self, methods: &[&str]) {
+        self.methods = methods.iter().map(|method| method.to_uppercase()).collect();
+    }
+
+    /// Request headers preflights can ask for, `*` allows any. None by default.
+    pub fn set_allowed_headers(&mut self, headers: &[&str]) {
+        self.headers = headers.iter().map(|header| header.to_lowercase()).collect();
+    }
+
+    /// Response headers scripts of the other origin can read.
+    pub fn set_exposed_headers(&mut self, headers: &[&str]) {
+        self.exposed_headers = headers.iter().map(|header| header.to_string()).collect();
+    }
+
+    /// Lets requests include cookies and `Authorization`. Origins allowed with `*` get
+    /// their own origin back, browsers reject a wildcard with credentials.
+    pub fn set_allow_credentials(&mut self, credentials: bool) {
+        self.credentials = credentials;
+    }
+
+    /// How long browsers can cache a preflight answer.
+    pub fn set_max_age(&mut self, max_age: Duration) {
+        self.max_age = Some(max_age);
+    }
+
+    fn allowed_origin(&self, origin: &str) -> Option<String> {
+        let allowed = self
+            .origins
+            .iter()
+            .find(|allowed| allowed.matches(origin))?;
+        match allowed {
+            AllowedOrigin::Any if !self.credentials => Some("*".to_owned()),
+            _ => Some(origin.to_owned()),
+        }
+    }
+
+    // The answer only stays the same for every origin if any origin gets `*`
+    fn varies_by_origin(&self) -> bool {
+        self.credentials
+            || !self
+                .origins
+                .iter()
+                .any(|allowed| matches!(allowed, AllowedOrigin::Any))
+    }
+
+    fn set_origin_headers(&self, origin: String, response: &mut HttpResponse) {
+        response.set_header("Access-Control-Allow-Origin", origin);
+        if self.credentials {
+            response.set_header("Access-Control-Allow-Credentials", "true".to_owned());
+        }
+    }
+
+    fn answer_preflight(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        add_vary(response, "Origin");
+        add_vary(response, "Access-Control-Request-Method");
+        add_vary(response, "Access-Control-Request-Headers");
+        let origin = request
+            .header("Origin")
+            .and_then(|origin| self.allowed_origin(origin));
+        let method = request
+            .header("Access-Control-Request-Method")
+            .unwrap_or("")
+            .trim()
+            .to_uppercase();
+        let requested_headers: Vec<String> = request
+            .header("Access-Control-Request-Headers")
+            .unwrap_or("")
+            .split(',')
+            .map(|header| header.trim().to_lowercase())
+            .filter(|header| !header.is_empty())
+            .collect();
+        let any_header = self.headers.iter().any(|header| header == "*");
+        let headers_allowed = any_header
+            || requested_headers
+                .iter()
+                .all(|header| self.headers.contains(header));
+        let origin = match origin {
+            Some(origin) if self.methods.contains(&method) && headers_allowed => origin,
+            _ => {
+                response.set_status_code(403);
+                return;
+            }
+        };
+
+        response.set_status_code(204);
+        self.set_origin_headers(origin, response);
+        response.set_header("Access-Control-Allow-Methods", self.methods.join(", "));
+        let allowed_headers = if any_header {
+            requested_headers.join(", ")
+        } else {
+            self.headers.join(", ")
+        };
+        if !allowed_headers.is_empty() {
+            response.set_header("Access-Control-Allow-Headers", allowed_headers);
+        }
+        if let Some(max_age) = self.max_age {
+            response.set_header("Access-Control-Max-Age", max_age.as_secs().to_string());
+        }
+    }
+
+    /// Before middleware answering preflight requests without calling the routes.
+    pub fn preflight<T>(
+        &self,
+    ) -> impl Fn(&mut HttpRequest, &mut HttpResponse, State<T>) -> bool + Send + Sync {
+        let cors = self.clone();
+        move |request, response, _| {
+            if !is_preflight(request) {
+                return true;
+            }
+            cors.answer_preflight(request, response);
+            false
+        }
+    }
+
+    /// After middleware adding the CORS headers to the other responses.
+    pub fn middleware<T>(
+        &self,
+    ) -> impl Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync {
+        let cors = self.clone();
+        move |request, response, _| {
+            if is_preflight(request) {
+                return;
+            }
+            if cors.varies_by_origin() {
+                add_vary(response, "Origin");
+            }
+            let origin = match request
+                .header("Origin")
+                .and_then(|origin| cors.allowed_origin(origin))
+            {
+                Some(origin) => origin,
+                None => return,
+            };
+            cors.set_origin_headers(origin, response);
+            if !cors.exposed_headers.is_empty() {
+                response.set_header(
+                    "Access-Control-Expose-Headers",
+                    cors.exposed_headers.join(", "),
+                );
+            }
+        }
+    }
+}
+
+pub(super) fn is_preflight(request: &HttpRequest) -> bool {
+    request.method == "OPTIONS"
+        && request.header("Origin").is_some()
+        && request.header("Access-Control-Request-Method").is_some()
+}
+
+/// Adds `name` to the `Vary` header of the response, unless it's already there.
+fn add_vary(response: &mut HttpResponse, name: &str) {
+    let vary = response.header("Vary").unwrap_or("").to_owned();
+    let present = vary
+        .split(',')
+        .any(|value| value.trim() == "*" || value.trim().eq_ignore_ascii_case(name));
+    if present {
+        return;
+    }
+    let vary = if vary.trim().is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}, {}", vary, name)
+    };
+    response.set_header("Vary", vary);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{HttpServer, TestClient};
+    use super::*;
+
+    #[test]
+    fn allowed_origins() {
+        let mut cors = Cors::new();
+        assert_eq!(cors.allowed_origin("https://app.example.com"), None);
+        cors.allow_origin("https://App.example.com");
+        cors.allow_origin("https://*.moon.dev");
+        cors.allow_origin_fn(|origin| origin.ends_with(":3000"));
+        assert_eq!(
+            cors.allowed_origin("https://app.example.com").as_deref(),
+            Some("https://app.example.com")
+        );
+        assert!(cors.allowed_origin("https://api.moon.dev").is_some());
+        assert!(cors.allowed_origin("https://.moon.dev").is_none());
+        assert!(cors.allowed_origin("https://evil.dev/.moon.dev").is_none());
+        assert!(cors.allowed_origin("https://a.moon.dev.evil.dev").is_none());
+        assert!(cors.allowed_origin("http://localhost:3000").is_some());
+        assert!(cors.varies_by_origin());
+
+        let mut any = Cors::new();
+        any.allow_origin("*");
+        assert_eq!(any.allowed_origin("https://a.dev").as_deref(), Some("*"));
+        assert!(!any.varies_by_origin());
+        any.set_allow_credentials(true);
+        assert_eq!(
+            any.allowed_origin("https://a.dev").as_deref(),
+            Some("https://a.dev")
+        );
+    }
+
+    #[test]
+    fn vary_header() {
+        let mut response = HttpResponse::new();
+        add_vary(&mut response, "Origin");
+        add_vary(&mut response, "Accept-Encoding");
+        add_vary(&mut response, "origin");
+        assert_eq!(response.header("Vary"), Some("Origin, Accept-Encoding"));
+    }
+
+    #[test]
+    fn cors_requests() {
+        let mut server = HttpServer::new(());
+        let mut cors = Cors::new();
+        cors.allow_origin("https://app.moon.dev");
+        cors.set_allowed_methods(&["GET", "PUT"]);
+        cors.set_allowed_headers(&["Content-Type"]);
+        cors.set_exposed_headers(&["X-Total"]);
+        cors.set_allow_credentials(true);
+        cors.set_max_age(Duration::from_secs(600));
+        server.set_cors(cors);
+        server.route("PUT", "/items", |_, _, _| "saved");
+        let mut client = TestClient::new(&server);
+
+        let preflight = |client: &TestClient<()>, origin: &str, method: &str, headers: &str| {
+            let mut request = client.request("OPTIONS", "/items");
+            request
+                .headers
+                .push(("Origin".to_owned(), origin.to_owned()));
+            request.headers.push((
+                "Access-Control-Request-Method".to_owned(),
+                method.to_owned(),
+            ));
+            request.headers.push((
+                "Access-Control-Request-Headers".to_owned(),
+                headers.to_owned(),
+            ));
+            request
+        };
+        let request = preflight(&client, "https://app.moon.dev", "PUT", "content-type");
+        let response = client.send(request);
+        assert_eq!(response.status_code(), 204);
+        assert_eq!(
+            response.header("Access-Control-Allow-Origin"),
+            Some("https://app.moon.dev")
+        );
+        assert_eq!(
+            response.header("Access-Control-Allow-Methods"),
+            Some("GET, PUT")
+        );
+        assert_eq!(
+            response.header("Access-Control-Allow-Headers"),
+            Some("content-type")
+        );
+        assert_eq!(
+            response.header("Access-Control-Allow-Credentials"),
+            Some("true")
+        );
+        assert_eq!(response.header("Access-Control-Max-Age"), Some("600"));
+        assert_eq!(
+            response.header("Vary"),
+            Some("Origin, Access-Control-Request-Method, Access-Control-Request-Headers")
+        );
+        let request = preflight(&client, "https://app.moon.dev", "DELETE", "");
+        assert_eq!(client.send(request).status_code(), 403);
+        let request = preflight(&client, "https://app.moon.dev", "PUT", "x-secret");
+        assert_eq!(client.send(request).status_code(), 403);
+        let request = preflight(&client, "https://evil.dev", "PUT", "");
+        let response = client.send(request);
+        assert_eq!(response.status_code(), 403);
+        assert_eq!(response.header("Access-Control-Allow-Origin"), None);
+
+        let mut request = client.request("PUT", "/items");
+        request
+            .headers
+            .push(("Origin".to_owned(), "https://app.moon.dev".to_owned()));
+        let response = client.send(request);
+        assert_eq!(response.body(), b"saved");
+        assert_eq!(
+            response.header("Access-Control-Allow-Origin"),
+            Some("https://app.moon.dev")
+        );
+        assert_eq!(
+            response.header("Access-Control-Expose-Headers"),
+            Some("X-Total")
+        );
+        assert_eq!(response.header("Vary"), Some("Origin"));
+        let response = client.send(client.request("PUT", "/items"));
+        assert_eq!(response.header("Access-Control-Allow-Origin"), None);
+        assert_eq!(response.header("Vary"), Some("Origin"));
+    }
+}
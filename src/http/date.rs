@@ -0,0 +1,179 @@
+// https://howardhinnant.github.io/date_algorithms.html
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = (year - era * 400) as u64;
+    let day_of_year = (153 * ((month as u64 + 9) % 12) + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era as i64 - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = (days - era * 146097) as u64;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era as i64 + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Date and time in UTC, split in the fields the HTTP and log formats need.
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+    pub weekday: usize,
+}
+
+impl DateTime {
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let days = (seconds / 86400) as i64;
+        let (year, month, day) = civil_from_days(days);
+        let seconds_of_day = seconds % 86400;
+        DateTime {
+            year,
+            month,
+            day,
+            hour: seconds_of_day / 3600,
+            minute: seconds_of_day % 3600 / 60,
+            second: seconds_of_day % 60,
+            weekday: ((days + 4) % 7) as usize,
+        }
+    }
+
+    pub fn month_name(&self) -> &'static str {
+        MONTH_NAMES[self.month as usize - 1]
+    }
+
+    pub fn day_name(&self) -> &'static str {
+        DAY_NAMES[self.weekday]
+    }
+}
+
+/// Formats a time as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn format_http_date(time: SystemTime) -> String {
+    let date = DateTime::from_system_time(time);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        date.day_name(),
+        date.day,
+        date.month_name(),
+        date.year,
+        date.hour,
+        date.minute,
+        date.second
+    )
+}
+
+fn parse_time_of_day(token: &str) -> Option<u64> {
+    let parts: Vec<&str> = token.split(':').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let hour = parts[0].parse::<u64>().ok()?;
+    let minute = parts[1].parse::<u64>().ok()?;
+    let second = parts[2].parse::<u64>().ok()?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some(hour * 3600 + minute * 60 + second)
+}
+
+/// Parses the three date formats HTTP/1.1 recipients have to accept: IMF-fixdate,
+/// the obsolete RFC 850 format and ANSI C's asctime() format.
+pub fn parse_http_date(input: &str) -> Option<SystemTime> {
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    let mut seconds_of_day = None;
+
+    let tokens = input
+        .split([' ', ',', '-'])
+        .filter(|token| !token.is_empty());
+    for token in tokens {
+        if token.contains(':') {
+            seconds_of_day = Some(parse_time_of_day(token)?);
+            continue;
+        }
+        if let Some(index) = MONTH_NAMES.iter().position(|name| *name == token) {
+            month = Some(index as u32 + 1);
+            continue;
+        }
+        if token.chars().all(|ch| ch.is_ascii_digit()) {
+            let number = token.parse::<i64>().ok()?;
+            if day.is_none() && token.len() <= 2 {
+                day = Some(number as u32);
+            } else if year.is_none() {
+                // RFC 850 uses two digit years
+                year = Some(match token.len() {
+                    2 if number < 70 => number + 2000,
+                    2 => number + 1900,
+                    _ => number,
+                });
+            } else {
+                return None;
+            }
+        }
+    }
+
+    let (day, month, year) = (day?, month?, year?);
+    if day == 0 || day > 31 || year < 1970 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day) as u64;
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + seconds_of_day?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
+    #[test]
+    fn parse_all_date_formats() {
+        let expected = Some(UNIX_EPOCH + Duration::from_secs(784111777));
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), expected);
+    }
+
+    #[test]
+    fn parse_invalid_date() {
+        assert_eq!(parse_http_date("yesterday"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 25:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn format_and_parse_roundtrip() {
+        let time = UNIX_EPOCH + Duration::from_secs(1616592635);
+        assert_eq!(parse_http_date(&format_http_date(time)), Some(time));
+    }
+}
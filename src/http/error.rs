@@ -0,0 +1,505 @@
+use super::response::reason_phrase;
+use super::server::State;
+use super::{HttpRequest, HttpResponse};
+use crate::json::JsonValue;
+use crate::templating::render;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::num::{ParseFloatError, ParseIntError};
+use std::panic;
+use std::sync::Once;
+
+const ERROR_PAGE: &str = "<!DOCTYPE html>
+<html>
+<head><title>{{status}} {{reason}}</title></head>
+<body>
+<h1>{{status}} {{reason}}</h1>
+<p>{{message}}</p>
+</body>
+</html>
+";
+
+const DETAILS_PAGE: &str = "<!DOCTYPE html>
+<html>
+<head><title>{{status}} {{message}}</title></head>
+<body>
+<h1>{{status}} {{message}}</h1>
+<p>{{method}} {{path}}</p>
+{{#causes}}<p>Caused by: {{cause}}</p>{{/causes}}
+{{#backtrace}}<h2>Backtrace</h2>
+<pre>{{backtrace}}</pre>{{/backtrace}}
+</body>
+</html>
+";
+
+/// Error a handler can return instead of building the error response itself. The message is
+/// shown to the client, the source never is. Server errors (5xx) are logged with their source,
+/// client errors aren't logged at all.
+#[derive(Debug)]
+pub struct HttpError {
+    status_code: u16,
+    message: String,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl HttpError {
+    pub fn new(status_code: u16, message: &str) -> Self {
+        HttpError {
+            status_code,
+            message: message.to_owned(),
+            source: None,
+        }
+    }
+
+    pub fn bad_request(message: &str) -> Self {
+        Self::new(400, message)
+    }
+
+    pub fn not_found(message: &str) -> Self {
+        Self::new(404, message)
+    }
+
+    pub fn internal(message: &str) -> Self {
+        Self::new(500, message)
+    }
+
+    pub fn with_source<E: Into<Box<dyn Error + Send + Sync>>>(mut self, source: E) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    pub fn status_code(&self) -> u16 {
+        self.status_code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// 500 error for a handler that panicked with `payload`. The backtrace is there once
+    /// `capture_backtraces` installed its panic hook.
+    pub(super) fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().map_or_else(
+                || "Box<dyn Any>".to_owned(),
+                |message| (*message).to_owned(),
+            ),
+        };
+        let backtrace = BACKTRACE.with(|backtrace| backtrace.borrow_mut().take());
+        HttpError::internal("Internal server error").with_source(Panic { message, backtrace })
+    }
+
+    /// Fills `response` with the error as JSON if the client prefers it according to
+    /// `Accept`, as an HTML page otherwise.
+    pub fn render(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        response.set_status_code(self.status_code);
+        response.remove_header("Content-Length");
+        if prefers_json(request.header("Accept")) {
+            let mut error = HashMap::new();
+            error.insert(
+                "status".to_owned(),
+                JsonValue::from(self.status_code as f64),
+            );
+            error.insert("message".to_owned(), JsonValue::from(&self.message));
+            response.set_header("Content-Type", "application/json".to_owned());
+            response.set_body(JsonValue::from(error).stringify());
+        } else {
+            let mut context = HashMap::new();
+            context.insert(
+                "status".to_owned(),
+                JsonValue::from(self.status_code.to_string()),
+            );
+            context.insert(
+                "reason".to_owned(),
+                JsonValue::from(reason_phrase(self.status_code).unwrap_or("Error")),
+            );
+            context.insert("message".to_owned(), JsonValue::from(&self.message));
+            response.set_header("Content-Type", "text/html; charset=utf-8".to_owned());
+            response.set_body(render(ERROR_PAGE, &JsonValue::from(context)));
+        }
+    }
+
+    /// Like `render`, with the sources of the error and the backtrace of panics. Only for
+    /// development, it shows the internals of the server to anyone.
+    pub fn render_details(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        let mut causes = Vec::new();
+        let mut backtrace = None;
+        let mut source = self.source();
+        while let Some(err) = source {
+            let mut cause = HashMap::new();
+            cause.insert("cause".to_owned(), JsonValue::from(err.to_string()));
+            causes.push(JsonValue::from(cause));
+            if let Some(panic) = err.downcast_ref::<Panic>() {
+                backtrace = panic.backtrace.clone();
+            }
+            source = err.source();
+        }
+
+        let mut context = HashMap::new();
+        context.insert(
+            "status".to_owned(),
+            JsonValue::from(self.status_code as f64),
+        );
+        context.insert("message".to_owned(), JsonValue::from(&self.message));
+        context.insert("method".to_owned(), JsonValue::from(&request.method));
+        context.insert("path".to_owned(), JsonValue::from(&request.uri));
+        context.insert("causes".to_owned(), JsonValue::from(causes));
+        context.insert(
+            "backtrace".to_owned(),
+            backtrace.map_or(JsonValue::Null, JsonValue::from),
+        );
+        let context = JsonValue::from(context);
+
+        response.set_status_code(self.status_code);
+        response.remove_header("Content-Length");
+        if prefers_json(request.header("Accept")) {
+            response.set_header("Content-Type", "application/json".to_owned());
+            response.set_body(context.stringify());
+        } else {
+            response.set_header("Content-Type", "text/html; charset=utf-8".to_owned());
+            response.set_body(render(DETAILS_PAGE, &context));
+        }
+    }
+}
+
+/// Panic of a route handler, the source of the 500 error sent instead.
+#[derive(Debug)]
+pub struct Panic {
+    pub message: String,
+    pub backtrace: Option<String>,
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handler panicked: {}", self.message)
+    }
+}
+
+impl Error for Panic {}
+
+thread_local! {
+    static BACKTRACE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Keeps the backtrace of every panic for the error page of development mode. The panic
+/// hook that was there before still runs.
+pub(super) fn capture_backtraces() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let backtrace = Backtrace::force_capture().to_string();
+            BACKTRACE.with(|cell| *cell.borrow_mut() = Some(backtrace));
+            previous(info);
+        }));
+    });
+}
+
+/// Error handler rendering `template` with the `status`, `reason`, `message` and `path` of
+/// the error, for custom 404 or 500 pages.
+pub fn error_page<T>(
+    template: &str,
+) -> impl Fn(&HttpRequest, &HttpError, &mut HttpResponse, State<T>) + Send + Sync {
+    let template = template.to_owned();
+    move |request, error, response, _| {
+        let mut context = HashMap::new();
+        context.insert(
+            "status".to_owned(),
+            JsonValue::from(error.status_code.to_string()),
+        );
+        context.insert(
+            "reason".to_owned(),
+            JsonValue::from(reason_phrase(error.status_code).unwrap_or("Error")),
+        );
+        context.insert("message".to_owned(), JsonValue::from(&error.message));
+        context.insert("path".to_owned(), JsonValue::from(&request.uri));
+        response.set_header("Content-Type", "text/html; charset=utf-8".to_owned());
+        response.set_body(render(&template, &JsonValue::from(context)));
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.status_code, self.message)?;
+        if let Some(source) = &self.source {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for HttpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source
+            .as_ref()
+            .map(|source| source.as_ref() as &(dyn Error + 'static))
+    }
+}
+
+impl From<io::Error> for HttpError {
+    fn from(err: io::Error) -> Self {
+        let error = match err.kind() {
+            io::ErrorKind::NotFound => HttpError::not_found("Not found"),
+            io::ErrorKind::PermissionDenied => HttpError::new(403, "Forbidden"),
+            _ => HttpError::internal("Internal server error"),
+        };
+        error.with_source(err)
+    }
+}
+
+impl From<ParseIntError> for HttpError {
+    fn from(err: ParseIntError) -> Self {
+        HttpError::bad_request("Invalid number").with_source(err)
+    }
+}
+
+impl From<ParseFloatError> for HttpError {
+    fn from(err: ParseFloatError) -> Self {
+        HttpError::bad_request("Invalid number").with_source(err)
+    }
+}
+
+// Quality the client gives to `media_type`, through the exact type or a wildcard
+fn quality(accept: &str, media_type: &str) -> f32 {
+    let (kind, _) = media_type.split_once('/').unwrap_or((media_type, ""));
+    let mut best: Option<(u8, f32)> = None;
+    for range in accept.split(',') {
+        let mut params = range.split(';');
+        let range = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        let specificity = if range == media_type {
+            2
+        } else if range == format!("{}/*", kind) {
+            1
+        } else if range == "*/*" {
+            0
+        } else {
+            continue;
+        };
+        let q = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse().ok())
+            .unwrap_or(1.0);
+        let more_specific = match best {
+            Some((best_specificity, _)) => specificity > best_specificity,
+            None => true,
+        };
+        if more_specific {
+            best = Some((specificity, q));
+        }
+    }
+    best.map_or(0.0, |(_, q)| q)
+}
+
+fn prefers_json(accept: Option<&str>) -> bool {
+    accept.is_some_and(|accept| quality(accept, "application/json") > quality(accept, "text/html"))
+}
+
+/// What a route handler can return. Errors end up in the response and are rendered by the
+/// router once the handler is done.
+pub trait IntoResponse {
+    fn into_response(self, response: &mut HttpResponse);
+}
+
+impl IntoResponse for () {
+    fn into_response(self, _: &mut HttpResponse) {}
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self, response: &mut HttpResponse) {
+        response.set_error(self);
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self, response: &mut HttpResponse) {
+        response.set_body(self);
+    }
+}
+
+impl IntoResponse for &'static str {
+    fn into_response(self, response: &mut HttpResponse) {
+        response.set_body(self.to_owned());
+    }
+}
+
+impl IntoResponse for JsonValue {
+    fn into_response(self, response: &mut HttpResponse) {
+        response.set_header("Content-Type", "application/json".to_owned());
+        response.set_body(self.stringify());
+    }
+}
+
+/// Response with a status code other than 200.
+impl<R: IntoResponse> IntoResponse for (u16, R) {
+    fn into_response(self, response: &mut HttpResponse) {
+        response.set_status_code(self.0);
+        self.1.into_response(response);
+    }
+}
+
+impl<R: IntoResponse, E: Into<HttpError>> IntoResponse for Result<R, E> {
+    fn into_response(self, response: &mut HttpResponse) {
+        match self {
+            Ok(value) => value.into_response(response),
+            Err(err) => err.into().into_response(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{error_page, HttpServer, TestClient};
+    use super::*;
+
+    fn request(accept: Option<&str>) -> HttpRequest {
+        let mut request = HttpRequest::new_with_uri("/items/x".to_owned());
+        if let Some(accept) = accept {
+            request.headers = vec![("Accept".to_owned(), accept.to_owned())];
+        }
+        request
+    }
+
+    #[test]
+    fn negotiate_error_format() {
+        assert!(prefers_json(Some("application/json")));
+        assert!(prefers_json(Some("text/html;q=0.5, application/*")));
+        assert!(!prefers_json(Some("text/html,application/json;q=0.9")));
+        assert!(!prefers_json(Some("*/*")));
+        assert!(!prefers_json(None));
+    }
+
+    #[test]
+    fn render_errors() {
+        let error = HttpError::bad_request("Item <id> has to be a number");
+        let mut response = HttpResponse::new();
+        error.render(&request(Some("application/json")), &mut response);
+        assert_eq!(response.status_code(), 400);
+        assert_eq!(response.header("Content-Type"), Some("application/json"));
+        let json = response.json();
+        let json = json.as_object().unwrap();
+        assert_eq!(json["status"], JsonValue::from(400.0));
+        assert_eq!(
+            json["message"],
+            JsonValue::from("Item <id> has to be a number")
+        );
+
+        let mut response = HttpResponse::new();
+        error.render(&request(None), &mut response);
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert!(body.contains("<h1>400 Bad request</h1>"));
+        assert!(body.contains("Item &lt;id&gt; has to be a number"));
+    }
+
+    #[test]
+    fn results_into_responses() {
+        let parse = |id: &str| -> Result<String, HttpError> { Ok(id.parse::<u64>()?.to_string()) };
+        let mut response = HttpResponse::new();
+        parse("42").into_response(&mut response);
+        assert_eq!(response.body(), b"42");
+        assert!(response.take_error().is_none());
+
+        let mut response = HttpResponse::new();
+        parse("x").into_response(&mut response);
+        let error = response.take_error().unwrap();
+        assert_eq!(error.status_code(), 400);
+        assert!(error.source().is_some());
+        assert_eq!(
+            error.to_string(),
+            "400 Invalid number: invalid digit found in string"
+        );
+
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "missing.txt");
+        assert_eq!(HttpError::from(not_found).status_code(), 404);
+    }
+
+    #[test]
+    fn render_handler_errors() {
+        let mut server = HttpServer::new(());
+        server.route(
+            "GET",
+            "/items/:id",
+            |request, _, _| -> Result<String, HttpError> {
+                let id: u64 = request.params["id"].parse()?;
+                if id == 0 {
+                    return Err(HttpError::not_found("No item 0"));
+                }
+                Ok(format!("item {}", id))
+            },
+        );
+        server.after(|_, response, _| response.set_header("X-After", "1".to_owned()));
+        let mut client = TestClient::new(&server);
+        assert_eq!(client.get("/items/7").body(), b"item 7");
+
+        let response = client.get("/items/seven");
+        assert_eq!(response.status_code(), 400);
+        assert_eq!(response.header("X-After"), Some("1"));
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+
+        let mut request = client.request("GET", "/items/0");
+        request
+            .headers
+            .push(("Accept".to_owned(), "application/json".to_owned()));
+        let response = client.send(request);
+        assert_eq!(response.status_code(), 404);
+        assert_eq!(
+            response.json().as_object().unwrap()["message"],
+            JsonValue::from("No item 0")
+        );
+    }
+
+    #[test]
+    fn custom_error_pages() {
+        let mut server = HttpServer::new(());
+        server.route("GET", "/teapot", |_, _, _| {
+            HttpError::new(418, "Short and stout")
+        });
+        server.get("/panic", &|_, _, _| panic!("Handler bug"));
+        server.set_error_handler(404, error_page("<h1>{{status}} {{path}}</h1>"));
+        server.set_default_error_handler(|_, error, response, _| {
+            response.set_body(format!("Oops: {}", error.message()));
+        });
+        let mut client = TestClient::new(&server);
+
+        let response = client.get("/missing?x=<b>");
+        assert_eq!(response.status_code(), 404);
+        assert_eq!(response.body(), b"<h1>404 &#x2F;missing?x=&lt;b&gt;</h1>");
+        let response = client.get("/teapot");
+        assert_eq!(response.status_code(), 418);
+        assert_eq!(response.body(), b"Oops: Short and stout");
+        let response = client.get("/panic");
+        assert_eq!(response.status_code(), 500);
+        assert_eq!(response.body(), b"Oops: Internal server error");
+
+        drop(client);
+        server.set_fallback(|request: &HttpRequest, _: &mut HttpResponse, _| {
+            format!("fallback for {}", request.uri)
+        });
+        let mut client = TestClient::new(&server);
+        let response = client.get("/missing");
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), b"fallback for /missing");
+    }
+
+    #[test]
+    fn dev_mode_panic_page() {
+        let mut server = HttpServer::new(());
+        server.get("/panic", &|_, _, _| panic!("Handler bug"));
+        server.set_dev_mode(true);
+        let mut client = TestClient::new(&server);
+        let response = client.get("/panic");
+        assert_eq!(response.status_code(), 500);
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert!(body.contains("<p>GET &#x2F;panic</p>"));
+        assert!(body.contains("Caused by: Handler panicked: Handler bug"));
+        assert!(body.contains("<h2>Backtrace</h2>"));
+    }
+}
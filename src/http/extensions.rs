@@ -0,0 +1,157 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Values attached to a request by middleware, like the authenticated user or a request
+/// ID, one per type. Handlers read them with `get` or the `Extension` extractor.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `value`, returning the previous value of its type if it isn't shared with a
+    /// clone of the request.
+    pub fn insert<V: Any + Send + Sync>(&mut self, value: V) -> Option<V> {
+        self.values
+            .insert(TypeId::of::<V>(), Arc::new(value))
+            .and_then(|previous| previous.downcast().ok())
+            .and_then(|previous: Arc<V>| Arc::try_unwrap(previous).ok())
+    }
+
+    pub fn get<V: Any + Send + Sync>(&self) -> Option<&V> {
+        self.values
+            .get(&TypeId::of::<V>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// `None` if there is no value of type `V`, or if it is shared with a clone of the
+    /// request, like the ones given to WebSocket handlers.
+    pub fn get_mut<V: Any + Send + Sync>(&mut self) -> Option<&mut V> {
+        self.values
+            .get_mut(&TypeId::of::<V>())
+            .and_then(Arc::get_mut)
+            .and_then(|value| value.downcast_mut())
+    }
+
+    pub fn contains<V: Any + Send + Sync>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<V>())
+    }
+
+    pub fn remove<V: Any + Send + Sync>(&mut self) -> Option<V> {
+        self.values
+            .remove(&TypeId::of::<V>())
+            .and_then(|value| value.downcast().ok())
+            .and_then(|value: Arc<V>| Arc::try_unwrap(value).ok())
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::server::Route;
+    use super::super::{extract, Extension, HttpServer, TestClient};
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct User(String);
+
+    #[test]
+    fn values_by_type() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.is_empty());
+        assert_eq!(extensions.insert(User("moon".to_owned())), None);
+        assert_eq!(extensions.insert(7u64), None);
+        assert_eq!(extensions.get::<User>(), Some(&User("moon".to_owned())));
+        assert_eq!(extensions.get::<u32>(), None);
+
+        *extensions.get_mut::<u64>().unwrap() += 1;
+        assert_eq!(extensions.insert(1u64), Some(8));
+        assert!(extensions.contains::<u64>());
+        assert_eq!(extensions.remove::<u64>(), Some(1));
+        assert!(!extensions.contains::<u64>());
+        assert_eq!(extensions.len(), 1);
+    }
+
+    #[test]
+    fn clones_share_values() {
+        let mut extensions = Extensions::new();
+        extensions.insert(User("moon".to_owned()));
+        let clone = extensions.clone();
+        assert_eq!(clone.get::<User>(), Some(&User("moon".to_owned())));
+        assert_eq!(extensions.get_mut::<User>(), None);
+        drop(clone);
+        assert!(extensions.get_mut::<User>().is_some());
+    }
+
+    #[derive(Clone)]
+    struct RequestId(u64);
+
+    #[test]
+    fn middleware_extensions() {
+        let mut server = HttpServer::new(());
+        server.before(|request, response, _| {
+            if request.uri == "/blocked" {
+                response.set_status_code(403);
+                return false;
+            }
+            request.extensions.insert(RequestId(7));
+            true
+        });
+        server.route(
+            "GET",
+            "/id",
+            extract(|Extension(RequestId(id)): Extension<RequestId>| id.to_string()),
+        );
+        server.route(
+            "GET",
+            "/user",
+            extract(|user: Option<Extension<String>>| format!("{:?}", user.map(|user| user.0))),
+        );
+        server.add_route(Route {
+            method: "GET".to_owned(),
+            uri: "/me".to_owned(),
+            middleware: Arc::new(vec![Box::new(|request, _, _| {
+                request.extensions.insert("moon".to_owned());
+                true
+            })]),
+            handler: Arc::new(|request, response, _| {
+                response.set_body(request.extensions.get::<String>().unwrap().clone());
+            }),
+        });
+        server.after(|request, response, _| {
+            let id = request.extensions.get::<RequestId>().map_or(0, |id| id.0);
+            response.set_header("X-Request-Id", id.to_string());
+        });
+
+        let mut client = TestClient::new(&server);
+        let response = client.get("/id");
+        assert_eq!(response.body(), b"7");
+        assert_eq!(response.header("X-Request-Id"), Some("7"));
+        assert_eq!(client.get("/user").body(), b"None");
+        assert_eq!(client.get("/me").body(), b"moon");
+        let response = client.get("/blocked");
+        assert_eq!(response.status_code(), 403);
+        assert_eq!(response.header("X-Request-Id"), Some("0"));
+    }
+}
@@ -0,0 +1,484 @@
+use super::error::{HttpError, IntoResponse};
+use super::route_urls::RouteUrls;
+use super::server::State;
+use super::url::{parse_urlencoded, percent_decode};
+use super::{HttpHeaders, HttpRequest, HttpResponse};
+use crate::json::{FromJson, JsonParser};
+use std::any::{type_name, Any};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Something a handler can take as an argument, built from the request or the server
+/// state. Failing extractors answer for the handler, usually with a 400.
+pub trait FromRequest<T>: Sized {
+    fn from_request(request: &HttpRequest, state: &State<T>) -> Result<Self, HttpError>;
+}
+
+/// The parameter of the route, like the `:id` of `/items/:id`, percent-decoded and parsed
+/// with `FromStr`.
+#[derive(Debug, PartialEq)]
+pub struct Path<P>(pub P);
+
+impl<T, P> FromRequest<T> for Path<P>
+where
+    P: FromStr,
+    P::Err: Display,
+{
+    fn from_request(request: &HttpRequest, _: &State<T>) -> Result<Self, HttpError> {
+        let (name, value) = request
+            .params
+            .iter()
+            .next()
+            .ok_or_else(|| HttpError::internal("Route without a parameter for Path"))?;
+        let value = percent_decode(value, false).ok_or_else(|| {
+            HttpError::bad_request(&format!("Invalid path parameter {}: bad encoding", name))
+        })?;
+        value.parse().map(Path).map_err(|err| {
+            HttpError::bad_request(&format!("Invalid path parameter {}: {}", name, err))
+        })
+    }
+}
+
+/// Query string or form fields, percent-decoded.
+pub struct Fields {
+    fields: HashMap<String, String>,
+    kind: &'static str,
+}
+
+impl Fields {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|value| value.as_str())
+    }
+
+    pub fn required<V>(&self, name: &str) -> Result<V, String>
+    where
+        V: FromStr,
+        V::Err: Display,
+    {
+        match self.optional(name)? {
+            Some(value) => Ok(value),
+            None => Err(format!("Missing {} {}", self.kind, name)),
+        }
+    }
+
+    pub fn optional<V>(&self, name: &str) -> Result<Option<V>, String>
+    where
+        V: FromStr,
+        V::Err: Display,
+    {
+        self.get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|err| format!("Invalid {} {}: {}", self.kind, name, err))
+            })
+            .transpose()
+    }
+}
+
+/// Conversion from query string or form fields, usually with `Fields::required` and
+/// `Fields::optional`.
+pub trait FromFields: Sized {
+    fn from_fields(fields: &Fields) -> Result<Self, String>;
+}
+
+impl FromFields for HashMap<String, String> {
+    fn from_fields(fields: &Fields) -> Result<Self, String> {
+        Ok(fields.fields.clone())
+    }
+}
+
+fn fields<F: FromFields>(data: &str, kind: &'static str) -> Result<F, HttpError> {
+    let fields = parse_urlencoded(data)
+        .ok_or_else(|| HttpError::bad_request(&format!("Invalid URL encoding in {}", kind)))?;
+    F::from_fields(&Fields { fields, kind }).map_err(|err| HttpError::bad_request(&err))
+}
+
+fn has_content_type(request: &HttpRequest, expected: &str) -> bool {
+    request.header("Content-Type").is_some_and(|content_type| {
+        content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .eq_ignore_ascii_case(expected)
+    })
+}
+
+/// The query string of the request.
+#[derive(Debug, PartialEq)]
+pub struct Query<Q>(pub Q);
+
+impl<T, Q: FromFields> FromRequest<T> for Query<Q> {
+    fn from_request(request: &HttpRequest, _: &State<T>) -> Result<Self, HttpError> {
+        let uri = request.uri.split('#').next().unwrap_or("");
+        let query = uri.split_once('?').map_or("", |(_, query)| query);
+        fields(query, "query parameter").map(Query)
+    }
+}
+
+/// A `application/x-www-form-urlencoded` body.
+#[derive(Debug, PartialEq)]
+pub struct Form<F>(pub F);
+
+impl<T, F: FromFields> FromRequest<T> for Form<F> {
+    fn from_request(request: &HttpRequest, _: &State<T>) -> Result<Self, HttpError> {
+        if !has_content_type(request, "application/x-www-form-urlencoded") {
+            return Err(HttpError::new(415, "Expected a form body"));
+        }
+        fields(&request.body, "form field").map(Form)
+    }
+}
+
+/// A JSON body, converted with `FromJson`.
+#[derive(Debug, PartialEq)]
+pub struct Json<J>(pub J);
+
+impl<T, J: FromJson> FromRequest<T> for Json<J> {
+    fn from_request(request: &HttpRequest, _: &State<T>) -> Result<Self, HttpError> {
+        if !has_content_type(request, "application/json") {
+            return Err(HttpError::new(415, "Expected a JSON body"));
+        }
+        let value = JsonParser::new(&request.body)
+            .try_parse()
+            .map_err(|err| HttpError::bad_request("Invalid JSON body").with_source(err))?;
+        J::from_json(&value)
+            .map(Json)
+            .map_err(|err| HttpError::bad_request(&format!("Invalid JSON body: {}", err)))
+    }
+}
+
+/// The headers of the request.
+#[derive(Debug, PartialEq)]
+pub struct Headers(pub HttpHeaders);
+
+impl Headers {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl<T> FromRequest<T> for Headers {
+    fn from_request(request: &HttpRequest, _: &State<T>) -> Result<Self, HttpError> {
+        Ok(Headers(request.headers.clone()))
+    }
+}
+
+/// A value middleware stored in the extensions of the request, cloned. A missing one is a
+/// 500, since the middleware that provides it isn't on the route.
+#[derive(Debug, PartialEq)]
+pub struct Extension<V>(pub V);
+
+impl<T, V: Any + Clone + Send + Sync> FromRequest<T> for Extension<V> {
+    fn from_request(request: &HttpRequest, _: &State<T>) -> Result<Self, HttpError> {
+        request
+            .extensions
+            .get::<V>()
+            .cloned()
+            .map(Extension)
+            .ok_or_else(|| {
+                HttpError::internal(&format!("Missing request extension {}", type_name::<V>()))
+            })
+    }
+}
+
+impl<T> FromRequest<T> for State<T> {
+    fn from_request(_: &HttpRequest, state: &State<T>) -> Result<Self, HttpError> {
+        Ok(Arc::clone(state))
+    }
+}
+
+impl<T> FromRequest<T> for HttpRequest {
+    fn from_request(request: &HttpRequest, _: &State<T>) -> Result<Self, HttpError> {
+        Ok(request.clone())
+    }
+}
+
+impl<T> FromRequest<T> for RouteUrls {
+    fn from_request(request: &HttpRequest, _: &State<T>) -> Result<Self, HttpError> {
+        Ok(request.urls())
+    }
+}
+
+/// `None` instead of an error when the extractor fails.
+impl<T, E: FromRequest<T>> FromRequest<T> for Option<E> {
+    fn from_request(request: &HttpRequest, state: &State<T>) -> Result<Self, HttpError> {
+        Ok(E::from_request(request, state).ok())
+    }
+}
+
+/// Function taking extractors as arguments, up to 5. See `extract`.
+pub trait Handler<T, Args>: Send + Sync + 'static {
+    type Output: IntoResponse;
+
+    fn call(&self, request: &HttpRequest, state: &State<T>) -> Result<Self::Output, HttpError>;
+}
+
+macro_rules! impl_handler {
+    ($($arg:ident),*) => {
+        impl<T, F, R, $($arg,)*> Handler<T, ($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoResponse,
+            $($arg: FromRequest<T>,)*
+        {
+            type Output = R;
+
+            #[allow(non_snake_case, unused_variables)]
+            fn call(&self, request: &HttpRequest, state: &State<T>) -> Result<R, HttpError> {
+                $(let $arg = $arg::from_request(request, state)?;)*
+                Ok(self($($arg),*))
+            }
+        }
+    };
+}
+
+impl_handler!();
+impl_handler!(A);
+impl_handler!(A, B);
+impl_handler!(A, B, C);
+impl_handler!(A, B, C, D);
+impl_handler!(A, B, C, D, E);
+
+/// Route handler running `handler` with its arguments extracted from the request, for
+/// `Router::route`:
+///
+/// ```ignore
+/// server.route("GET", "/items/:id", extract(|Path(id): Path<u64>| format!("item {}", id)));
+/// ```
+pub fn extract<T, Args, H>(
+    handler: H,
+) -> impl Fn(&HttpRequest, &mut HttpResponse, State<T>) -> Result<H::Output, HttpError> + Send + Sync
+where
+    H: Handler<T, Args>,
+{
+    move |request, _, state| handler.call(request, &state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{get, post, route, HttpServer, TestClient};
+    use crate::json::JsonValue;
+    use std::sync::Mutex;
+
+    struct Page {
+        page: u64,
+        search: Option<String>,
+    }
+
+    impl FromFields for Page {
+        fn from_fields(fields: &Fields) -> Result<Self, String> {
+            Ok(Page {
+                page: fields.required("page")?,
+                search: fields.optional("search")?,
+            })
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct NewItem {
+        title: String,
+        tags: Vec<String>,
+        score: Option<f64>,
+    }
+
+    impl FromJson for NewItem {
+        fn from_json(value: &JsonValue) -> Result<Self, String> {
+            Ok(NewItem {
+                title: value.field("title")?,
+                tags: value.field("tags")?,
+                score: value.field("score")?,
+            })
+        }
+    }
+
+    fn server() -> HttpServer<u64> {
+        let mut server = HttpServer::new(0);
+        server.route(
+            "GET",
+            "/items/:id",
+            extract(|Path(id): Path<u64>, Query(page): Query<Page>| {
+                format!("{} {} {:?}", id, page.page, page.search)
+            }),
+        );
+        server.route(
+            "POST",
+            "/items",
+            extract(|Json(item): Json<NewItem>, state: State<u64>| {
+                *state.lock().unwrap() += 1;
+                (
+                    201,
+                    format!("{} {:?} {:?}", item.title, item.tags, item.score),
+                )
+            }),
+        );
+        server.route(
+            "GET",
+            "/users/:name",
+            extract(|Path(name): Path<String>| name),
+        );
+        server.route(
+            "POST",
+            "/login",
+            extract(
+                |Form(form): Form<HashMap<String, String>>, headers: Headers| {
+                    format!("{} {}", form["user"], headers.get("X-Test").unwrap_or(""))
+                },
+            ),
+        );
+        server
+    }
+
+    fn send(
+        client: &mut TestClient<u64>,
+        uri: &str,
+        content_type: &str,
+        body: &str,
+    ) -> HttpResponse {
+        let mut request = client.request("POST", uri);
+        request
+            .headers
+            .push(("Content-Type".to_owned(), content_type.to_owned()));
+        request.headers.push(("X-Test".to_owned(), "1".to_owned()));
+        request.body = body.to_owned();
+        client.send(request)
+    }
+
+    fn message(response: &HttpResponse) -> String {
+        String::from_utf8_lossy(response.body()).into_owned()
+    }
+
+    #[test]
+    fn path_and_query() {
+        let mut client = TestClient::new(&server());
+        let response = client.get("/items/7?page=2&search=moon%20web");
+        assert_eq!(response.body(), b"7 2 Some(\"moon web\")");
+
+        let response = client.get("/items/seven?page=2");
+        assert_eq!(response.status_code(), 400);
+        assert!(message(&response).contains("Invalid path parameter id: invalid digit"));
+        let response = client.get("/items/7");
+        assert_eq!(response.status_code(), 400);
+        assert!(message(&response).contains("Missing query parameter page"));
+    }
+
+    #[test]
+    fn percent_decoded_path() {
+        let mut client = TestClient::new(&server());
+        assert_eq!(client.get("/users/a%20b").body(), b"a b");
+        assert_eq!(
+            client.get("/users/caf%C3%A9?page=2").body(),
+            "café".as_bytes()
+        );
+        assert_eq!(client.get("/users/a+b").body(), b"a+b");
+        assert_eq!(client.get("/users/100%").status_code(), 400);
+    }
+
+    #[test]
+    fn json_body() {
+        let server = server();
+        let mut client = TestClient::new(&server);
+        let body = r#"{"title": "Moon", "tags": ["web", "rust"]}"#;
+        let response = send(&mut client, "/items", "application/json", body);
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(response.body(), b"Moon [\"web\", \"rust\"] None");
+        assert_eq!(*server.state().lock().unwrap(), 1);
+
+        let response = send(&mut client, "/items", "application/json", r#"{"title": 1}"#);
+        assert_eq!(response.status_code(), 400);
+        assert!(message(&response).contains("Invalid JSON body: title: expected a string"));
+        let response = send(&mut client, "/items", "application/json", r#"{"title": "#);
+        assert_eq!(response.status_code(), 400);
+        let response = send(&mut client, "/items", "text/plain", "{}");
+        assert_eq!(response.status_code(), 415);
+    }
+
+    #[test]
+    fn form_and_headers() {
+        let mut client = TestClient::new(&server());
+        let response = send(
+            &mut client,
+            "/login",
+            "application/x-www-form-urlencoded; charset=utf-8",
+            "user=moon+lover",
+        );
+        assert_eq!(response.body(), b"moon lover 1");
+    }
+
+    #[test]
+    fn optional_extractors() {
+        let state = Arc::new(Mutex::new(()));
+        let request = HttpRequest::new_with_uri("/?page=x".to_owned());
+        let query: Option<Query<Page>> = FromRequest::from_request(&request, &state).unwrap();
+        assert!(query.is_none());
+    }
+
+    /// Lists the items
+    #[get("/items")]
+    fn list_items(
+        Query(query): Query<HashMap<String, String>>,
+        items: State<Vec<String>>,
+    ) -> String {
+        let items = items.lock().unwrap();
+        let page = query.get("page").map_or("1", |page| page.as_str());
+        format!("page {} of {}", page, items.join(","))
+    }
+
+    #[get("/items/:id", name = "item")]
+    fn show_item(Path(id): Path<usize>, items: State<Vec<String>>) -> Result<String, HttpError> {
+        let items = items.lock().unwrap();
+        items
+            .get(id)
+            .cloned()
+            .ok_or_else(|| HttpError::not_found("No such item"))
+    }
+
+    #[post("/items")]
+    pub(super) fn add_item(
+        Json(item): Json<String>,
+        items: State<Vec<String>>,
+        urls: RouteUrls,
+    ) -> (u16, String) {
+        let mut items = items.lock().unwrap();
+        items.push(item);
+        let id = (items.len() - 1).to_string();
+        (201, urls.url_for("item", &[("id", &id)]).unwrap())
+    }
+
+    #[route("OPTIONS", "/items")]
+    fn item_options() -> &'static str {
+        "GET, POST"
+    }
+
+    #[test]
+    fn mount_route_attributes() {
+        let mut server = HttpServer::new(vec!["moon".to_owned()]);
+        server.mount((list_items, show_item, add_item));
+        server.mount(item_options);
+        let mut client = TestClient::new(&server);
+        assert_eq!(client.get("/items?page=2").body(), b"page 2 of moon");
+        assert_eq!(client.get("/items/0").body(), b"moon");
+        assert_eq!(client.get("/items/1").status_code(), 404);
+        assert_eq!(client.get("/items/first").status_code(), 400);
+
+        let response = client.post_json("/items", &JsonValue::from("web"));
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(response.body(), b"/items/1");
+        assert_eq!(client.get("/items/1").body(), b"web");
+        let request = client.request("OPTIONS", "/items");
+        assert_eq!(client.send(request).body(), b"GET, POST");
+
+        let urls = server.urls();
+        assert_eq!(
+            urls.url_for("list_items", &[("page", "2")]).unwrap(),
+            "/items?page=2"
+        );
+        assert_eq!(urls.url_for("item", &[("id", "7")]).unwrap(), "/items/7");
+    }
+}
@@ -0,0 +1,229 @@
+use super::HttpRequest;
+use std::net::{IpAddr, SocketAddr};
+
+/// Networks of the proxies in front of the server, whose `Forwarded` and `X-Forwarded-For`
+/// headers are believed. See `HttpServer::set_trusted_proxies`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrustedProxies {
+    // address, prefix length
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Parses addresses and networks like `127.0.0.1`, `10.0.0.0/8` or `fd00::/8`.
+    pub fn new(networks: &[&str]) -> Result<Self, String> {
+        let networks = networks
+            .iter()
+            .map(|network| parse_network(network))
+            .collect::<Result<_, _>>()?;
+        Ok(TrustedProxies { networks })
+    }
+
+    pub fn contains(&self, address: IpAddr) -> bool {
+        self.networks
+            .iter()
+            .any(|(network, prefix)| in_network(address, *network, *prefix))
+    }
+
+    /// Address of the client. Forwarding headers are only followed from a trusted peer, from
+    /// the closest hop back while the hops are trusted proxies.
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let mut client = request.peer_addr?.ip();
+        if !self.contains(client) {
+            return Some(client);
+        }
+        for hop in forwarded_for(request).iter().rev() {
+            match hop {
+                Some(address) => client = *address,
+                // unknown or obfuscated hops can't be followed
+                None => break,
+            }
+            if !self.contains(client) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+fn parse_network(network: &str) -> Result<(IpAddr, u8), String> {
+    let invalid = || format!("Invalid trusted proxy {}", network);
+    let (address, prefix) = match network.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (network, None),
+    };
+    let address: IpAddr = address.trim().parse().map_err(|_| invalid())?;
+    let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim().parse().map_err(|_| invalid())?,
+        None => max_prefix,
+    };
+    if prefix > max_prefix {
+        return Err(invalid());
+    }
+    Ok((address, prefix))
+}
+
+fn in_network(address: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    let (address, network, bits) = match (address, network) {
+        (IpAddr::V4(address), IpAddr::V4(network)) => {
+            (u32::from(address) as u128, u32::from(network) as u128, 32)
+        }
+        (IpAddr::V6(address), IpAddr::V6(network)) => {
+            (u128::from(address), u128::from(network), 128)
+        }
+        (IpAddr::V6(address), IpAddr::V4(network)) => match address.to_ipv4_mapped() {
+            Some(address) => (u32::from(address) as u128, u32::from(network) as u128, 32),
+            None => return false,
+        },
+        (IpAddr::V4(_), IpAddr::V6(_)) => return false,
+    };
+    let shift = bits - prefix as u32;
+    shift >= bits || address >> shift == network >> shift
+}
+
+/// Hops of the `Forwarded` headers, or of `X-Forwarded-For` without them, from the client
+/// to the closest proxy. Hops without an address are `None`.
+fn forwarded_for(request: &HttpRequest) -> Vec<Option<IpAddr>> {
+    let forwarded: Vec<&str> = request
+        .headers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("Forwarded"))
+        .flat_map(|(_, value)| value.split(','))
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded
+            .iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value.trim().trim_matches('"')))
+            })
+            .collect();
+    }
+    request
+        .headers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("X-Forwarded-For"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|hop| parse_node(hop.trim()))
+        .collect()
+}
+
+// `192.0.2.60`, `192.0.2.60:4711`, `[2001:db8::17]:4711` or `2001:db8::17`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|address| address.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .and_then(|node| node.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{HttpServer, TestServer};
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn request(peer: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        let mut request = HttpRequest::new_with_uri("/".to_owned());
+        request.peer_addr = Some(format!("{}:4000", peer).parse().unwrap());
+        request.headers = headers
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        request
+    }
+
+    fn client_ip(peer: &str, headers: &[(&str, &str)]) -> String {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8", "127.0.0.1", "fd00::/8"]).unwrap();
+        proxies
+            .client_ip(&request(peer, headers))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn networks() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8", "::1", "0.0.0.0/0"]).unwrap();
+        assert!(proxies.contains("10.1.2.3".parse().unwrap()));
+        assert!(proxies.contains("203.0.113.1".parse().unwrap()));
+        assert!(proxies.contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(!proxies.contains("2001:db8::1".parse().unwrap()));
+        assert!(TrustedProxies::new(&["10.0.0.0/33"]).is_err());
+        assert_eq!(
+            TrustedProxies::new(&["proxy"]),
+            Err("Invalid trusted proxy proxy".to_owned())
+        );
+    }
+
+    #[test]
+    fn forwarded_for() {
+        let forwarded = [("X-Forwarded-For", "203.0.113.7, 198.51.100.2, 10.0.0.2")];
+        // untrusted peers can't forward
+        assert_eq!(client_ip("198.51.100.9", &forwarded), "198.51.100.9");
+        // the first untrusted hop from the server is the client, the rest can be forged
+        assert_eq!(client_ip("10.0.0.1", &forwarded), "198.51.100.2");
+        assert_eq!(
+            client_ip("127.0.0.1", &[("X-Forwarded-For", "203.0.113.7, 10.0.0.2")]),
+            "203.0.113.7"
+        );
+        assert_eq!(client_ip("127.0.0.1", &[]), "127.0.0.1");
+        assert_eq!(
+            client_ip("127.0.0.1", &[("X-Forwarded-For", "unknown, 10.0.0.2")]),
+            "10.0.0.2"
+        );
+    }
+
+    #[test]
+    fn forwarded() {
+        let headers = [
+            ("Forwarded", "for=\"[2001:db8:cafe::17]:4711\";proto=https"),
+            ("Forwarded", "for=10.0.0.2;by=10.0.0.1"),
+            ("X-Forwarded-For", "198.51.100.2"),
+        ];
+        assert_eq!(client_ip("10.0.0.1", &headers), "2001:db8:cafe::17");
+        assert_eq!(
+            client_ip(
+                "10.0.0.1",
+                &[("Forwarded", "for=_hidden, for=192.0.2.60:80")]
+            ),
+            "192.0.2.60"
+        );
+    }
+
+    #[test]
+    fn connection_metadata() {
+        let mut server = HttpServer::new(());
+        server.set_trusted_proxies(&["127.0.0.1"]);
+        server.route("GET", "/whoami", |request, _, _| {
+            format!(
+                "{} {} {} {}",
+                request.client_ip.unwrap(),
+                request.peer_addr.unwrap().ip(),
+                request.local_addr.unwrap().ip(),
+                request.tls.is_some()
+            )
+        });
+        let server = TestServer::start(server).unwrap();
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        stream
+            .write_all(b"GET /whoami HTTP/1.1\r\nX-Forwarded-For: 203.0.113.7\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("203.0.113.7 127.0.0.1 127.0.0.1 false"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid trusted proxy 10.0.0.0/40")]
+    fn invalid_trusted_proxy() {
+        HttpServer::new(()).set_trusted_proxies(&["10.0.0.0/40"]);
+    }
+}
@@ -0,0 +1,898 @@
+use super::super::connection::Connection;
+use super::super::{HttpRequest, HttpResponse};
+use super::frame::{self, Frame, FrameReader, ReadFrame};
+use super::hpack::{self, Decoder};
+use super::Handler;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// https://tools.ietf.org/html/rfc7540#section-7
+const NO_ERROR: u32 = 0x0;
+const PROTOCOL_ERROR: u32 = 0x1;
+const FLOW_CONTROL_ERROR: u32 = 0x3;
+const STREAM_CLOSED: u32 = 0x5;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+const REFUSED_STREAM: u32 = 0x7;
+const COMPRESSION_ERROR: u32 = 0x9;
+const ENHANCE_YOUR_CALM: u32 = 0xb;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+const MAX_CONCURRENT_STREAMS: usize = 100;
+const DEFAULT_WINDOW_SIZE: i64 = 65_535;
+const MAX_WINDOW_SIZE: i64 = 0x7fff_ffff;
+const MAX_HEADER_BLOCK: usize = 64 * 1024;
+/// Bigger request bodies are answered with a 413.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+// How often the connection looks for responses while streams are open
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Messages from the threads that run handlers and upgraded streams.
+enum Event {
+    Response(u32, HttpResponse),
+    Data(u32, Vec<u8>),
+    End(u32),
+}
+
+enum Http2Error {
+    Io(io::Error),
+    /// Connection error, sent to the peer in a GOAWAY frame.
+    Connection(u32),
+}
+
+impl From<io::Error> for Http2Error {
+    fn from(err: io::Error) -> Self {
+        Http2Error::Io(err)
+    }
+}
+
+type Result<T> = std::result::Result<T, Http2Error>;
+
+#[derive(Default)]
+struct Stream {
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    // The request is complete, the client sent END_STREAM
+    received_end: bool,
+    // Body was too big, the rest of it is dropped
+    discard_body: bool,
+    // The response is complete but the client is still sending the request
+    sent_end: bool,
+    send_window: i64,
+    pending: Vec<u8>,
+    // END_STREAM goes out once `pending` is sent
+    end_pending: bool,
+    // Feeds DATA frames to an upgraded response
+    incoming: Option<Sender<Vec<u8>>>,
+}
+
+/// One stream of an upgraded response (like an event stream) seen as a `Connection`. Writes
+/// become DATA frames and dropping it ends the stream.
+struct StreamConnection {
+    stream_id: u32,
+    events: Sender<Event>,
+    incoming: Receiver<Vec<u8>>,
+    read_timeout: Cell<Option<Duration>>,
+}
+
+impl Read for StreamConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = match self.read_timeout.get() {
+            Some(timeout) => match self.incoming.recv_timeout(timeout) {
+                Ok(data) => data,
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::WouldBlock.into()),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            },
+            None => match self.incoming.recv() {
+                Ok(data) => data,
+                Err(_) => return Ok(0),
+            },
+        };
+        // DATA frames are never bigger than the buffers used to read them in this crate
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+}
+
+impl Write for StreamConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.events
+            .send(Event::Data(self.stream_id, buf.to_vec()))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for StreamConnection {
+    fn drop(&mut self) {
+        let _ = self.events.send(Event::End(self.stream_id));
+    }
+}
+
+impl Connection for StreamConnection {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout.set(timeout);
+        Ok(())
+    }
+}
+
+/// Server side of an HTTP/2 connection.
+pub struct Http2Connection {
+    connection: Box<dyn Connection>,
+    reader: FrameReader,
+    handler: Handler,
+    decoder: Decoder,
+    streams: HashMap<u32, Stream>,
+    last_stream_id: u32,
+    send_window: i64,
+    initial_window_size: i64,
+    max_frame_size: usize,
+    // Stream whose header block is waiting for CONTINUATION frames
+    continuation: Option<(u32, u8, Vec<u8>)>,
+    goaway_received: bool,
+    events: Sender<Event>,
+    receiver: Receiver<Event>,
+}
+
+impl Http2Connection {
+    /// `initial` holds bytes already read from the connection.
+    pub fn new(connection: Box<dyn Connection>, initial: Vec<u8>, handler: Handler) -> Self {
+        let (events, receiver) = mpsc::channel();
+        Http2Connection {
+            connection,
+            reader: FrameReader::new(initial),
+            handler,
+            decoder: Decoder::new(),
+            streams: HashMap::new(),
+            last_stream_id: 0,
+            send_window: DEFAULT_WINDOW_SIZE,
+            initial_window_size: DEFAULT_WINDOW_SIZE,
+            max_frame_size: frame::MAX_FRAME_SIZE,
+            continuation: None,
+            goaway_received: false,
+            events,
+            receiver,
+        }
+    }
+
+    /// Applies the settings from the `HTTP2-Settings` header of an `Upgrade: h2c` request and
+    /// handles the request as stream 1.
+    pub fn upgraded(&mut self, settings: &[u8], request: HttpRequest) -> io::Result<()> {
+        if self.apply_settings(settings).is_err() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        self.last_stream_id = 1;
+        self.streams.insert(1, self.new_stream());
+        self.streams.get_mut(&1).unwrap().received_end = true;
+        self.dispatch(1, request);
+        Ok(())
+    }
+
+    /// Serves the connection until the client closes it or breaks the protocol.
+    pub fn run(mut self) {
+        match self.serve() {
+            Ok(()) => {}
+            Err(Http2Error::Connection(code)) => {
+                let _ = self.send_goaway(code);
+                let _ = self.connection.flush();
+            }
+            Err(Http2Error::Io(err)) => eprintln!("HTTP/2 connection error: {}", err),
+        }
+    }
+
+    fn serve(&mut self) -> Result<()> {
+        let mut settings = Vec::new();
+        settings.extend_from_slice(&SETTINGS_MAX_CONCURRENT_STREAMS.to_be_bytes());
+        settings.extend_from_slice(&(MAX_CONCURRENT_STREAMS as u32).to_be_bytes());
+        self.write_frame(Frame::new(frame::SETTINGS, 0, 0, settings))?;
+        self.connection.flush()?;
+        if !self.reader.read_preface(self.connection.as_mut())? {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR));
+        }
+
+        let mut polling = false;
+        loop {
+            while let Ok(event) = self.receiver.try_recv() {
+                self.handle_event(event)?;
+            }
+            self.send_pending_data()?;
+            self.connection.flush()?;
+            if self.goaway_received && self.streams.is_empty() {
+                self.send_goaway(NO_ERROR)?;
+                return Ok(());
+            }
+
+            // Responses come from other threads, so while streams are open the socket is only
+            // read for short periods
+            let active = !self.streams.is_empty();
+            if polling != active {
+                polling = active;
+                let timeout = if polling { Some(POLL_INTERVAL) } else { None };
+                self.connection.set_read_timeout(timeout)?;
+            }
+            match self.reader.read_frame(self.connection.as_mut())? {
+                ReadFrame::Frame(frame) => self.handle_frame(frame)?,
+                ReadFrame::Pending => {}
+                ReadFrame::Closed => return Ok(()),
+                ReadFrame::TooBig => return Err(Http2Error::Connection(FRAME_SIZE_ERROR)),
+            }
+        }
+    }
+
+    fn new_stream(&self) -> Stream {
+        Stream {
+            send_window: self.initial_window_size,
+            ..Stream::default()
+        }
+    }
+
+    fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
+        frame::write_frame(self.connection.as_mut(), &frame)
+    }
+
+    fn send_goaway(&mut self, code: u32) -> io::Result<()> {
+        let mut payload = self.last_stream_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&code.to_be_bytes());
+        self.write_frame(Frame::new(frame::GOAWAY, 0, 0, payload))
+    }
+
+    fn reset_stream(&mut self, stream_id: u32, code: u32) -> io::Result<()> {
+        self.streams.remove(&stream_id);
+        self.write_frame(Frame::new(
+            frame::RST_STREAM,
+            0,
+            stream_id,
+            code.to_be_bytes().to_vec(),
+        ))
+    }
+
+    fn handle_frame(&mut self, frame: Frame) -> Result<()> {
+        if let Some((stream_id, _, _)) = self.continuation {
+            if frame.kind != frame::CONTINUATION || frame.stream_id != stream_id {
+                return Err(Http2Error::Connection(PROTOCOL_ERROR));
+            }
+        }
+        match frame.kind {
+            frame::DATA => self.handle_data(frame),
+            frame::HEADERS => self.handle_headers(frame),
+            frame::CONTINUATION => self.handle_continuation(frame),
+            frame::PRIORITY => {
+                if frame.stream_id == 0 {
+                    return Err(Http2Error::Connection(PROTOCOL_ERROR));
+                }
+                if frame.payload.len() != 5 {
+                    self.reset_stream(frame.stream_id, FRAME_SIZE_ERROR)?;
+                }
+                Ok(())
+            }
+            frame::RST_STREAM => {
+                if frame.stream_id == 0 || frame.stream_id > self.last_stream_id {
+                    return Err(Http2Error::Connection(PROTOCOL_ERROR));
+                }
+                if frame.payload.len() != 4 {
+                    return Err(Http2Error::Connection(FRAME_SIZE_ERROR));
+                }
+                self.streams.remove(&frame.stream_id);
+                Ok(())
+            }
+            frame::SETTINGS => self.handle_settings(frame),
+            frame::PING => {
+                if frame.stream_id != 0 {
+                    return Err(Http2Error::Connection(PROTOCOL_ERROR));
+                }
+                if frame.payload.len() != 8 {
+                    return Err(Http2Error::Connection(FRAME_SIZE_ERROR));
+                }
+                if !frame.has_flag(frame::ACK) {
+                    self.write_frame(Frame::new(frame::PING, frame::ACK, 0, frame.payload))?;
+                }
+                Ok(())
+            }
+            frame::GOAWAY => {
+                if frame.stream_id != 0 {
+                    return Err(Http2Error::Connection(PROTOCOL_ERROR));
+                }
+                self.goaway_received = true;
+                Ok(())
+            }
+            frame::WINDOW_UPDATE => self.handle_window_update(frame),
+            // Clients can't push
+            frame::PUSH_PROMISE => Err(Http2Error::Connection(PROTOCOL_ERROR)),
+            // Unknown frame types are ignored
+            _ => Ok(()),
+        }
+    }
+
+    fn handle_settings(&mut self, frame: Frame) -> Result<()> {
+        if frame.stream_id != 0 {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR));
+        }
+        if frame.has_flag(frame::ACK) {
+            if !frame.payload.is_empty() {
+                return Err(Http2Error::Connection(FRAME_SIZE_ERROR));
+            }
+            return Ok(());
+        }
+        self.apply_settings(&frame.payload)?;
+        self.write_frame(Frame::new(frame::SETTINGS, frame::ACK, 0, Vec::new()))?;
+        Ok(())
+    }
+
+    fn apply_settings(&mut self, payload: &[u8]) -> Result<()> {
+        if payload.len() % 6 != 0 {
+            return Err(Http2Error::Connection(FRAME_SIZE_ERROR));
+        }
+        for setting in payload.chunks(6) {
+            let identifier = u16::from_be_bytes([setting[0], setting[1]]);
+            let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+            match identifier {
+                // The encoder never uses the dynamic table, so its size doesn't matter
+                SETTINGS_HEADER_TABLE_SIZE => {}
+                SETTINGS_ENABLE_PUSH if value > 1 => {
+                    return Err(Http2Error::Connection(PROTOCOL_ERROR))
+                }
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    let value = value as i64;
+                    if value > MAX_WINDOW_SIZE {
+                        return Err(Http2Error::Connection(FLOW_CONTROL_ERROR));
+                    }
+                    // The change applies to the windows of every open stream
+                    let delta = value - self.initial_window_size;
+                    for stream in self.streams.values_mut() {
+                        stream.send_window += delta;
+                        if stream.send_window > MAX_WINDOW_SIZE {
+                            return Err(Http2Error::Connection(FLOW_CONTROL_ERROR));
+                        }
+                    }
+                    self.initial_window_size = value;
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    let value = value as usize;
+                    if !(frame::MAX_FRAME_SIZE..=0xff_ffff).contains(&value) {
+                        return Err(Http2Error::Connection(PROTOCOL_ERROR));
+                    }
+                    self.max_frame_size = value;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_window_update(&mut self, frame: Frame) -> Result<()> {
+        if frame.payload.len() != 4 {
+            return Err(Http2Error::Connection(FRAME_SIZE_ERROR));
+        }
+        let payload = &frame.payload;
+        let increment = (u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]])
+            & 0x7fff_ffff) as i64;
+        if frame.stream_id == 0 {
+            if increment == 0 {
+                return Err(Http2Error::Connection(PROTOCOL_ERROR));
+            }
+            self.send_window += increment;
+            if self.send_window > MAX_WINDOW_SIZE {
+                return Err(Http2Error::Connection(FLOW_CONTROL_ERROR));
+            }
+            return Ok(());
+        }
+        if frame.stream_id > self.last_stream_id {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR));
+        }
+        let stream = match self.streams.get_mut(&frame.stream_id) {
+            Some(stream) => stream,
+            // Frames can still be in flight after the stream is closed
+            None => return Ok(()),
+        };
+        stream.send_window += increment;
+        if increment == 0 {
+            self.reset_stream(frame.stream_id, PROTOCOL_ERROR)?;
+        } else if stream.send_window > MAX_WINDOW_SIZE {
+            self.reset_stream(frame.stream_id, FLOW_CONTROL_ERROR)?;
+        }
+        Ok(())
+    }
+
+    fn handle_data(&mut self, frame: Frame) -> Result<()> {
+        if frame.stream_id == 0 || frame.stream_id > self.last_stream_id {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR));
+        }
+        let data = frame
+            .data()
+            .ok_or(Http2Error::Connection(PROTOCOL_ERROR))?
+            .to_vec();
+        // Everything we receive is consumed right away, so the windows are refilled as is
+        if !frame.payload.is_empty() {
+            let increment = (frame.payload.len() as u32).to_be_bytes().to_vec();
+            self.write_frame(Frame::new(frame::WINDOW_UPDATE, 0, 0, increment.clone()))?;
+            if !frame.has_flag(frame::END_STREAM) {
+                self.write_frame(Frame::new(
+                    frame::WINDOW_UPDATE,
+                    0,
+                    frame.stream_id,
+                    increment,
+                ))?;
+            }
+        }
+
+        let stream = match self.streams.get_mut(&frame.stream_id) {
+            Some(stream) if !stream.received_end => stream,
+            _ => {
+                self.reset_stream(frame.stream_id, STREAM_CLOSED)?;
+                return Ok(());
+            }
+        };
+        if let Some(incoming) = &stream.incoming {
+            if !data.is_empty() {
+                let _ = incoming.send(data);
+            }
+        } else if !stream.discard_body {
+            stream.body.extend_from_slice(&data);
+        }
+        let too_big = stream.body.len() > MAX_BODY_SIZE;
+        if frame.has_flag(frame::END_STREAM) {
+            stream.received_end = true;
+        }
+
+        if too_big {
+            stream.discard_body = true;
+            stream.body = Vec::new();
+            let mut response = HttpResponse::new();
+            response.set_status_code(413);
+            response.set_body("Request body too large".to_owned());
+            self.respond(frame.stream_id, response)?;
+        } else if frame.has_flag(frame::END_STREAM) && stream.sent_end {
+            self.streams.remove(&frame.stream_id);
+        } else if frame.has_flag(frame::END_STREAM) && !stream.discard_body {
+            self.dispatch_stream(frame.stream_id)?;
+        }
+        Ok(())
+    }
+
+    fn handle_headers(&mut self, frame: Frame) -> Result<()> {
+        let stream_id = frame.stream_id;
+        if stream_id == 0 {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR));
+        }
+        let block = frame
+            .data()
+            .ok_or(Http2Error::Connection(PROTOCOL_ERROR))?
+            .to_vec();
+        if frame.has_flag(frame::END_HEADERS) {
+            self.handle_header_block(stream_id, frame.flags, &block)
+        } else {
+            self.continuation = Some((stream_id, frame.flags, block));
+            Ok(())
+        }
+    }
+
+    fn handle_continuation(&mut self, frame: Frame) -> Result<()> {
+        let (stream_id, flags, mut block) = self
+            .continuation
+            .take()
+            .ok_or(Http2Error::Connection(PROTOCOL_ERROR))?;
+        block.extend_from_slice(&frame.payload);
+        if block.len() > MAX_HEADER_BLOCK {
+            return Err(Http2Error::Connection(ENHANCE_YOUR_CALM));
+        }
+        if frame.has_flag(frame::END_HEADERS) {
+            self.handle_header_block(stream_id, flags, &block)
+        } else {
+            self.continuation = Some((stream_id, flags, block));
+            Ok(())
+        }
+    }
+
+    fn handle_header_block(&mut self, stream_id: u32, flags: u8, block: &[u8]) -> Result<()> {
+        // The block is decoded even when the stream is refused, to keep the table in sync
+        let headers = self
+            .decoder
+            .decode(block)
+            .map_err(|_| Http2Error::Connection(COMPRESSION_ERROR))?;
+        let end_stream = flags & frame::END_STREAM != 0;
+
+        if stream_id <= self.last_stream_id {
+            // Trailers, they have to end the stream
+            return match self.streams.get_mut(&stream_id) {
+                Some(stream) if !stream.received_end => {
+                    if !end_stream {
+                        return Err(Http2Error::Connection(PROTOCOL_ERROR));
+                    }
+                    stream.received_end = true;
+                    if stream.sent_end {
+                        self.streams.remove(&stream_id);
+                    } else if !stream.discard_body {
+                        self.dispatch_stream(stream_id)?;
+                    }
+                    Ok(())
+                }
+                _ => Err(Http2Error::Connection(STREAM_CLOSED)),
+            };
+        }
+        // Streams started by clients have odd ids, always increasing
+        if stream_id % 2 == 0 {
+            return Err(Http2Error::Connection(PROTOCOL_ERROR));
+        }
+        self.last_stream_id = stream_id;
+        if self.goaway_received || self.streams.len() >= MAX_CONCURRENT_STREAMS {
+            self.reset_stream(stream_id, REFUSED_STREAM)?;
+            return Ok(());
+        }
+        let mut stream = self.new_stream();
+        stream.headers = headers;
+        stream.received_end = end_stream;
+        self.streams.insert(stream_id, stream);
+        if end_stream {
+            self.dispatch_stream(stream_id)?;
+        }
+        Ok(())
+    }
+
+    fn dispatch_stream(&mut self, stream_id: u32) -> Result<()> {
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        let headers = std::mem::take(&mut stream.headers);
+        let body = std::mem::take(&mut stream.body);
+        match to_request(headers, body) {
+            Some(request) => self.dispatch(stream_id, request),
+            None => self.reset_stream(stream_id, PROTOCOL_ERROR)?,
+        }
+        Ok(())
+    }
+
+    fn dispatch(&self, stream_id: u32, mut request: HttpRequest) {
+        request.set_connection(self.connection.as_ref());
+        let handler = Arc::clone(&self.handler);
+        let events = self.events.clone();
+        thread::spawn(move || {
+            let head = request.method == "HEAD";
+            let mut response = handler(request);
+            set_content_length(&mut response);
+            if head {
+                response.body = Vec::new();
+            }
+            let _ = events.send(Event::Response(stream_id, response));
+        });
+    }
+
+    fn handle_event(&mut self, event: Event) -> Result<()> {
+        match event {
+            Event::Response(stream_id, response) => {
+                if self.streams.contains_key(&stream_id) {
+                    self.respond(stream_id, response)?;
+                }
+            }
+            Event::Data(stream_id, data) => {
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    stream.pending.extend_from_slice(&data);
+                }
+            }
+            Event::End(stream_id) => {
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    stream.end_pending = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the headers of `response` and queues its body. Upgraded responses keep the
+    /// stream open for their own thread.
+    fn respond(&mut self, stream_id: u32, mut response: HttpResponse) -> Result<()> {
+        set_content_length(&mut response);
+        let upgrade = response.take_upgrade();
+        let block = hpack::encode(&response_headers(&response));
+        let end_stream = upgrade.is_none() && response.body.is_empty();
+
+        // Header blocks bigger than a frame continue in CONTINUATION frames
+        let mut chunks = block.chunks(self.max_frame_size).peekable();
+        let mut kind = frame::HEADERS;
+        let mut flags = if end_stream { frame::END_STREAM } else { 0 };
+        loop {
+            let chunk = chunks.next().unwrap_or(&[]);
+            if chunks.peek().is_none() {
+                flags |= frame::END_HEADERS;
+            }
+            self.write_frame(Frame::new(kind, flags, stream_id, chunk.to_vec()))?;
+            if chunks.peek().is_none() {
+                break;
+            }
+            kind = frame::CONTINUATION;
+            flags = 0;
+        }
+        if end_stream {
+            self.close_stream(stream_id);
+            return Ok(());
+        }
+
+        let stream = self.streams.get_mut(&stream_id).unwrap();
+        match upgrade {
+            Some(upgrade) => {
+                let (sender, incoming) = mpsc::channel();
+                stream.incoming = Some(sender);
+                let connection = StreamConnection {
+                    stream_id,
+                    events: self.events.clone(),
+                    incoming,
+                    read_timeout: Cell::new(None),
+                };
+                thread::spawn(move || upgrade.run(Box::new(connection)));
+            }
+            None => {
+                stream.pending = response.body;
+                stream.end_pending = true;
+            }
+        }
+        Ok(())
+    }
+
+    // Our side is done. A stream whose request isn't complete yet, like one answered with a
+    // 413, stays around to drop the rest of the body.
+    fn close_stream(&mut self, stream_id: u32) {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            if stream.received_end {
+                self.streams.remove(&stream_id);
+            } else {
+                stream.sent_end = true;
+                stream.end_pending = false;
+            }
+        }
+    }
+
+    fn send_pending_data(&mut self) -> Result<()> {
+        let stream_ids: Vec<u32> = self.streams.keys().cloned().collect();
+        for stream_id in stream_ids {
+            loop {
+                let stream = self.streams.get_mut(&stream_id).unwrap();
+                let len = (stream.pending.len() as i64)
+                    .min(stream.send_window)
+                    .min(self.send_window)
+                    .min(self.max_frame_size as i64)
+                    .max(0) as usize;
+                let end = stream.end_pending && len == stream.pending.len();
+                if len == 0 && !end {
+                    break;
+                }
+                let data: Vec<u8> = stream.pending.drain(..len).collect();
+                stream.send_window -= len as i64;
+                self.send_window -= len as i64;
+                let flags = if end { frame::END_STREAM } else { 0 };
+                self.write_frame(Frame::new(frame::DATA, flags, stream_id, data))?;
+                if end {
+                    self.close_stream(stream_id);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Headers that only make sense for a single HTTP/1.1 connection
+const CONNECTION_HEADERS: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Builds a request from the headers and the body of a stream, `None` if it's malformed.
+fn to_request(headers: Vec<(String, String)>, body: Vec<u8>) -> Option<HttpRequest> {
+    let mut method = None;
+    let mut path = None;
+    let mut authority = None;
+    let mut regular_headers: Vec<(String, String)> = Vec::with_capacity(headers.len());
+    let mut cookies = Vec::new();
+    for (name, value) in headers {
+        if name.bytes().any(|byte| byte.is_ascii_uppercase()) {
+            return None;
+        }
+        match name.as_str() {
+            ":method" => method = Some(value),
+            ":path" => path = Some(value),
+            ":authority" => authority = Some(value),
+            ":scheme" => {}
+            name if name.starts_with(':') => return None,
+            "cookie" => cookies.push(value),
+            "te" if value != "trailers" => return None,
+            name if CONNECTION_HEADERS.contains(&name) => return None,
+            _ => regular_headers.push((name, value)),
+        }
+    }
+    let uri = path.filter(|path| !path.is_empty())?;
+    // Cookies can be split in several fields to compress better
+    if !cookies.is_empty() {
+        regular_headers.push(("cookie".to_owned(), cookies.join("; ")));
+    }
+    if let Some(authority) = authority {
+        if !regular_headers.iter().any(|(name, _)| name == "host") {
+            regular_headers.insert(0, ("host".to_owned(), authority));
+        }
+    }
+    Some(HttpRequest::new(
+        method?,
+        uri,
+        "2".to_owned(),
+        regular_headers,
+        String::from_utf8_lossy(&body).into_owned(),
+    ))
+}
+
+fn set_content_length(response: &mut HttpResponse) {
+    let has_body = response.status_code != 204 && response.status_code != 304;
+    if response.upgrade.is_none() && has_body && response.header("Content-Length").is_none() {
+        response.set_header("Content-Length", response.body.len().to_string());
+    }
+}
+
+fn response_headers(response: &HttpResponse) -> Vec<(String, String)> {
+    let mut headers = vec![(":status".to_owned(), response.status_code.to_string())];
+    for (name, value) in &response.headers {
+        let name = name.to_ascii_lowercase();
+        if !CONNECTION_HEADERS.contains(&name.as_str()) {
+            headers.push((name, value.clone()));
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::connection::MockConnection;
+    use super::super::frame::PREFACE;
+    use super::*;
+    use std::sync::Mutex;
+
+    fn headers_frame(stream_id: u32, headers: &[(&str, &str)], flags: u8) -> Vec<u8> {
+        let headers: Vec<(String, String)> = headers
+            .iter()
+            .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+            .collect();
+        Frame::new(frame::HEADERS, flags, stream_id, hpack::encode(&headers)).to_bytes()
+    }
+
+    fn read_frames(output: &[u8]) -> Vec<Frame> {
+        let mut reader = FrameReader::new(output.to_vec());
+        let mut frames = Vec::new();
+        while let ReadFrame::Frame(frame) = reader.read_frame(&mut &[][..]).unwrap() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[test]
+    fn request_with_pseudo_headers() {
+        let headers = vec![
+            (":method".to_owned(), "POST".to_owned()),
+            (":scheme".to_owned(), "http".to_owned()),
+            (":path".to_owned(), "/test?page=2".to_owned()),
+            (":authority".to_owned(), "localhost".to_owned()),
+            ("cookie".to_owned(), "a=1".to_owned()),
+            ("cookie".to_owned(), "b=2".to_owned()),
+        ];
+        let request = to_request(headers.clone(), b"body".to_vec()).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.uri, "/test?page=2");
+        assert_eq!(request.query.get("page").unwrap(), "2");
+        assert_eq!(request.header("Host"), Some("localhost"));
+        assert_eq!(request.header("Cookie"), Some("a=1; b=2"));
+        assert_eq!(request.body, "body");
+
+        let mut invalid = headers.clone();
+        invalid.push(("connection".to_owned(), "keep-alive".to_owned()));
+        assert!(to_request(invalid, Vec::new()).is_none());
+        assert!(to_request(headers[1..].to_vec(), Vec::new()).is_none());
+    }
+
+    // Like MockConnection, but the input ends with a pause so responses can be sent first
+    struct PausingConnection {
+        input: io::Cursor<Vec<u8>>,
+        output: Arc<Mutex<Vec<u8>>>,
+        idle_reads: usize,
+    }
+
+    impl Read for PausingConnection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.input.read(buf)?;
+            if len == 0 && self.idle_reads > 0 {
+                self.idle_reads -= 1;
+                thread::sleep(POLL_INTERVAL);
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            Ok(len)
+        }
+    }
+
+    impl Write for PausingConnection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Connection for PausingConnection {
+        fn set_read_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn serve_requests() {
+        let mut input = PREFACE.to_vec();
+        input.extend(Frame::new(frame::SETTINGS, 0, 0, Vec::new()).to_bytes());
+        input.extend(Frame::new(frame::PING, 0, 0, vec![1; 8]).to_bytes());
+        input.extend(headers_frame(
+            1,
+            &[(":method", "GET"), (":scheme", "http"), (":path", "/hello")],
+            frame::END_HEADERS | frame::END_STREAM,
+        ));
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let connection = PausingConnection {
+            input: io::Cursor::new(input),
+            output: Arc::clone(&output),
+            idle_reads: 20,
+        };
+        let handler: Handler = Arc::new(|request| {
+            let mut response = HttpResponse::new();
+            response.set_header("Content-Type", "text/plain".to_owned());
+            response.set_body(format!("{} over {}", request.uri, request.version));
+            response
+        });
+        Http2Connection::new(Box::new(connection), Vec::new(), handler).run();
+
+        let frames = read_frames(&output.lock().unwrap());
+        let kinds: Vec<(u8, u8)> = frames
+            .iter()
+            .map(|frame| (frame.kind, frame.flags))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (frame::SETTINGS, 0),
+                (frame::SETTINGS, frame::ACK),
+                (frame::PING, frame::ACK),
+                (frame::HEADERS, frame::END_HEADERS),
+                (frame::DATA, frame::END_STREAM),
+            ]
+        );
+        let headers = Decoder::new().decode(&frames[3].payload).unwrap();
+        assert_eq!(headers[0], (":status".to_owned(), "200".to_owned()));
+        assert!(headers.contains(&("content-type".to_owned(), "text/plain".to_owned())));
+        assert_eq!(frames[4].payload, b"/hello over 2".to_vec());
+    }
+
+    #[test]
+    fn protocol_errors_close_the_connection() {
+        let mut input = PREFACE.to_vec();
+        // Streams started by clients have odd ids
+        input.extend(headers_frame(
+            2,
+            &[(":method", "GET"), (":path", "/")],
+            frame::END_HEADERS | frame::END_STREAM,
+        ));
+        let (connection, output) = MockConnection::new(input);
+        let handler: Handler = Arc::new(|_| HttpResponse::new());
+        Http2Connection::new(Box::new(connection), Vec::new(), handler).run();
+        let frames = read_frames(&output.lock().unwrap());
+        let goaway = frames.last().unwrap();
+        assert_eq!(goaway.kind, frame::GOAWAY);
+        assert_eq!(goaway.payload, vec![0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+}
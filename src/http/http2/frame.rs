@@ -0,0 +1,214 @@
+// https://tools.ietf.org/html/rfc7540#section-4
+use std::io::{self, Read, Write};
+
+pub const DATA: u8 = 0x0;
+pub const HEADERS: u8 = 0x1;
+pub const PRIORITY: u8 = 0x2;
+pub const RST_STREAM: u8 = 0x3;
+pub const SETTINGS: u8 = 0x4;
+pub const PUSH_PROMISE: u8 = 0x5;
+pub const PING: u8 = 0x6;
+pub const GOAWAY: u8 = 0x7;
+pub const WINDOW_UPDATE: u8 = 0x8;
+pub const CONTINUATION: u8 = 0x9;
+
+pub const END_STREAM: u8 = 0x1;
+pub const ACK: u8 = 0x1;
+pub const END_HEADERS: u8 = 0x4;
+pub const PADDED: u8 = 0x8;
+pub const PRIORITY_FLAG: u8 = 0x20;
+
+/// Client connection preface, sent before its first SETTINGS frame.
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+pub const HEADER_LEN: usize = 9;
+/// SETTINGS_MAX_FRAME_SIZE we use, which is also the initial value.
+pub const MAX_FRAME_SIZE: usize = 16_384;
+
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub kind: u8,
+    pub flags: u8,
+    pub stream_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(kind: u8, flags: u8, stream_id: u32, payload: Vec<u8>) -> Self {
+        Frame {
+            kind,
+            flags,
+            stream_id,
+            payload,
+        }
+    }
+
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = self.payload.len() as u32;
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        bytes.extend_from_slice(&len.to_be_bytes()[1..]);
+        bytes.push(self.kind);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&(self.stream_id & 0x7fff_ffff).to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Payload without the padding of DATA, HEADERS and PUSH_PROMISE frames, and without the
+    /// priority fields of HEADERS frames.
+    pub fn data(&self) -> Option<&[u8]> {
+        let mut data = &self.payload[..];
+        let mut padding = 0;
+        if self.has_flag(PADDED) && self.kind != CONTINUATION {
+            let (&len, rest) = data.split_first()?;
+            padding = len as usize;
+            data = rest;
+        }
+        if self.kind == HEADERS && self.has_flag(PRIORITY_FLAG) {
+            data = data.get(5..)?;
+        }
+        if padding > data.len() {
+            return None;
+        }
+        Some(&data[..data.len() - padding])
+    }
+}
+
+/// Reads frames from a stream. Partial frames stay buffered, so a read timeout doesn't lose
+/// data.
+pub struct FrameReader {
+    buffer: Vec<u8>,
+}
+
+pub enum ReadFrame {
+    Frame(Frame),
+    /// The peer closed the connection.
+    Closed,
+    /// Nothing complete arrived before the read timeout.
+    Pending,
+    /// The frame is bigger than `MAX_FRAME_SIZE`.
+    TooBig,
+}
+
+impl FrameReader {
+    pub fn new(initial: Vec<u8>) -> Self {
+        FrameReader { buffer: initial }
+    }
+
+    fn take_frame(&mut self) -> Option<ReadFrame> {
+        if self.buffer.len() < HEADER_LEN {
+            return None;
+        }
+        let len = u32::from_be_bytes([0, self.buffer[0], self.buffer[1], self.buffer[2]]) as usize;
+        if len > MAX_FRAME_SIZE {
+            return Some(ReadFrame::TooBig);
+        }
+        if self.buffer.len() < HEADER_LEN + len {
+            return None;
+        }
+        let stream_id = u32::from_be_bytes([
+            self.buffer[5],
+            self.buffer[6],
+            self.buffer[7],
+            self.buffer[8],
+        ]) & 0x7fff_ffff;
+        let frame = Frame::new(
+            self.buffer[3],
+            self.buffer[4],
+            stream_id,
+            self.buffer[HEADER_LEN..HEADER_LEN + len].to_vec(),
+        );
+        self.buffer.drain(..HEADER_LEN + len);
+        Some(ReadFrame::Frame(frame))
+    }
+
+    /// Waits for the client connection preface, `false` if something else arrived.
+    pub fn read_preface(&mut self, stream: &mut dyn Read) -> io::Result<bool> {
+        let mut chunk = [0; 64];
+        while self.buffer.len() < PREFACE.len() {
+            let len = stream.read(&mut chunk)?;
+            if len == 0 {
+                return Ok(false);
+            }
+            self.buffer.extend_from_slice(&chunk[..len]);
+        }
+        if !self.buffer.starts_with(PREFACE) {
+            return Ok(false);
+        }
+        self.buffer.drain(..PREFACE.len());
+        Ok(true)
+    }
+
+    pub fn read_frame(&mut self, stream: &mut dyn Read) -> io::Result<ReadFrame> {
+        let mut chunk = [0; 8192];
+        loop {
+            if let Some(frame) = self.take_frame() {
+                return Ok(frame);
+            }
+            match stream.read(&mut chunk) {
+                Ok(0) => return Ok(ReadFrame::Closed),
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(err)
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Ok(ReadFrame::Pending)
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+pub fn write_frame(stream: &mut dyn Write, frame: &Frame) -> io::Result<()> {
+    stream.write_all(&frame.to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_round_trip() {
+        let frame = Frame::new(HEADERS, END_HEADERS, 3, vec![0x82, 0x84]);
+        let bytes = frame.to_bytes();
+        assert_eq!(&bytes[..HEADER_LEN], &[0, 0, 2, 1, 4, 0, 0, 0, 3]);
+        // Split in two reads
+        let mut reader = FrameReader::new(bytes[..4].to_vec());
+        match reader.read_frame(&mut &bytes[4..]).unwrap() {
+            ReadFrame::Frame(read) => assert_eq!(read, frame),
+            _ => panic!("expected a frame"),
+        }
+        assert!(matches!(
+            reader.read_frame(&mut &[][..]).unwrap(),
+            ReadFrame::Closed
+        ));
+    }
+
+    #[test]
+    fn padding_and_priority() {
+        let frame = Frame::new(
+            HEADERS,
+            PADDED | PRIORITY_FLAG,
+            1,
+            vec![2, 0, 0, 0, 0, 16, 0x82, 0, 0],
+        );
+        assert_eq!(frame.data(), Some(&[0x82][..]));
+        let frame = Frame::new(DATA, PADDED, 1, vec![5, 1, 2]);
+        assert_eq!(frame.data(), None);
+    }
+
+    #[test]
+    fn oversized_frame() {
+        let mut reader = FrameReader::new(vec![0, 0x40, 1, 0, 0, 0, 0, 0, 1]);
+        assert!(matches!(
+            reader.read_frame(&mut &[][..]).unwrap(),
+            ReadFrame::TooBig
+        ));
+    }
+}
@@ -0,0 +1,345 @@
+// https://tools.ietf.org/html/rfc7541
+use super::huffman;
+use std::collections::VecDeque;
+
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Size of the dynamic table until the peer says otherwise with SETTINGS_HEADER_TABLE_SIZE.
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+// Every entry counts 32 bytes on top of its name and value
+const ENTRY_OVERHEAD: usize = 32;
+
+#[derive(Debug, PartialEq)]
+pub struct HpackError(pub &'static str);
+
+/// Decodes header blocks, keeping the dynamic table between them.
+pub struct Decoder {
+    table: VecDeque<(String, String)>,
+    table_size: usize,
+    max_table_size: usize,
+    // Upper bound for size updates, the value we advertised
+    allowed_table_size: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            table: VecDeque::new(),
+            table_size: 0,
+            max_table_size: DEFAULT_TABLE_SIZE,
+            allowed_table_size: DEFAULT_TABLE_SIZE,
+        }
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), HpackError> {
+        if index == 0 {
+            return Err(HpackError("index 0"));
+        }
+        if let Some((name, value)) = STATIC_TABLE.get(index - 1) {
+            return Ok(((*name).to_owned(), (*value).to_owned()));
+        }
+        self.table
+            .get(index - 1 - STATIC_TABLE.len())
+            .cloned()
+            .ok_or(HpackError("index out of the table"))
+    }
+
+    fn evict(&mut self) {
+        while self.table_size > self.max_table_size {
+            if let Some((name, value)) = self.table.pop_back() {
+                self.table_size -= name.len() + value.len() + ENTRY_OVERHEAD;
+            }
+        }
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        self.table_size += name.len() + value.len() + ENTRY_OVERHEAD;
+        self.table.push_front((name, value));
+        self.evict();
+    }
+
+    /// Returns the headers of a complete header block, in order.
+    pub fn decode(&mut self, mut block: &[u8]) -> Result<Vec<(String, String)>, HpackError> {
+        let mut headers = Vec::new();
+        let mut first = true;
+        while let Some(&byte) = block.first() {
+            if byte & 0x80 != 0 {
+                // Indexed header field
+                let index = decode_integer(&mut block, 7)?;
+                headers.push(self.entry(index)?);
+            } else if byte & 0xe0 == 0x20 {
+                // Dynamic table size update, only allowed at the start of a block
+                if !first {
+                    return Err(HpackError("table size update after a header"));
+                }
+                let size = decode_integer(&mut block, 5)?;
+                if size > self.allowed_table_size {
+                    return Err(HpackError("table size update over the limit"));
+                }
+                self.max_table_size = size;
+                self.evict();
+                continue;
+            } else {
+                // Literal, with incremental indexing (01), without indexing (0000) or never
+                // indexed (0001)
+                let indexing = byte & 0xc0 == 0x40;
+                let prefix = if indexing { 6 } else { 4 };
+                let index = decode_integer(&mut block, prefix)?;
+                let name = if index == 0 {
+                    decode_string(&mut block)?
+                } else {
+                    self.entry(index)?.0
+                };
+                let value = decode_string(&mut block)?;
+                if indexing {
+                    self.insert(name.clone(), value.clone());
+                }
+                headers.push((name, value));
+            }
+            first = false;
+        }
+        Ok(headers)
+    }
+}
+
+/// Encodes header blocks. It never adds entries to the dynamic table, so the peer's table
+/// size setting doesn't matter.
+pub fn encode(headers: &[(String, String)]) -> Vec<u8> {
+    let mut output = Vec::new();
+    for (name, value) in headers {
+        let full_match = STATIC_TABLE
+            .iter()
+            .position(|entry| entry.0 == name && entry.1 == value);
+        if let Some(index) = full_match {
+            encode_integer(&mut output, 0x80, 7, index + 1);
+            continue;
+        }
+        // Literal without indexing, with the name from the static table if it's there
+        match STATIC_TABLE.iter().position(|entry| entry.0 == name) {
+            Some(index) => encode_integer(&mut output, 0, 4, index + 1),
+            None => {
+                output.push(0);
+                encode_string(&mut output, name.as_bytes());
+            }
+        }
+        encode_string(&mut output, value.as_bytes());
+    }
+    output
+}
+
+fn decode_integer(input: &mut &[u8], prefix_bits: u8) -> Result<usize, HpackError> {
+    let truncated = HpackError("truncated integer");
+    let (&first, rest) = input.split_first().ok_or(truncated)?;
+    *input = rest;
+    let max_prefix = (1 << prefix_bits) - 1;
+    let mut value = (first & max_prefix) as usize;
+    if value < max_prefix as usize {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = input.split_first().ok_or(HpackError("truncated integer"))?;
+        *input = rest;
+        if shift > 28 {
+            return Err(HpackError("integer overflow"));
+        }
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn encode_integer(output: &mut Vec<u8>, flags: u8, prefix_bits: u8, mut value: usize) {
+    let max_prefix = (1 << prefix_bits) - 1;
+    if value < max_prefix {
+        output.push(flags | value as u8);
+        return;
+    }
+    output.push(flags | max_prefix as u8);
+    value -= max_prefix;
+    while value >= 0x80 {
+        output.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn decode_string(input: &mut &[u8]) -> Result<String, HpackError> {
+    let huffman_encoded = input.first().map(|byte| byte & 0x80 != 0) == Some(true);
+    let len = decode_integer(input, 7)?;
+    if input.len() < len {
+        return Err(HpackError("truncated string"));
+    }
+    let (data, rest) = input.split_at(len);
+    *input = rest;
+    let data = if huffman_encoded {
+        huffman::decode(data).ok_or(HpackError("invalid Huffman code"))?
+    } else {
+        data.to_vec()
+    };
+    String::from_utf8(data).map_err(|_| HpackError("header is not UTF-8"))
+}
+
+fn encode_string(output: &mut Vec<u8>, data: &[u8]) {
+    let encoded = huffman::encode(data);
+    if encoded.len() < data.len() {
+        encode_integer(output, 0x80, 7, encoded.len());
+        output.extend_from_slice(&encoded);
+    } else {
+        encode_integer(output, 0, 7, data.len());
+        output.extend_from_slice(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn integers() {
+        // RFC 7541 C.1
+        let mut output = Vec::new();
+        encode_integer(&mut output, 0, 5, 10);
+        encode_integer(&mut output, 0, 5, 1337);
+        assert_eq!(output, vec![0x0a, 0x1f, 0x9a, 0x0a]);
+        let mut input = &output[..];
+        assert_eq!(decode_integer(&mut input, 5), Ok(10));
+        assert_eq!(decode_integer(&mut input, 5), Ok(1337));
+        assert!(decode_integer(&mut &[0x1f, 0xff, 0xff, 0xff, 0xff, 0xff][..], 5).is_err());
+    }
+
+    #[test]
+    fn decode_requests_with_huffman() {
+        // RFC 7541 C.4
+        let mut decoder = Decoder::new();
+        let first = [
+            0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab,
+            0x90, 0xf4, 0xff,
+        ];
+        assert_eq!(
+            decoder.decode(&first).unwrap(),
+            headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ])
+        );
+        let second = [
+            0x82, 0x86, 0x84, 0xbe, 0x58, 0x86, 0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf,
+        ];
+        assert_eq!(
+            decoder.decode(&second).unwrap(),
+            headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ])
+        );
+        assert_eq!(decoder.table_size, 110);
+    }
+
+    #[test]
+    fn table_size_updates() {
+        let mut decoder = Decoder::new();
+        // Literal with indexing of custom-key: custom-header, RFC 7541 C.2.1
+        let mut block = vec![0x40, 0x0a];
+        block.extend_from_slice(b"custom-key");
+        block.push(0x0d);
+        block.extend_from_slice(b"custom-header");
+        decoder.decode(&block).unwrap();
+        assert_eq!(decoder.table_size, 55);
+        // Shrinking the table to 0 evicts everything, the entry can't be used anymore
+        assert!(decoder.decode(&[0x20, 0xbe]).is_err());
+        assert_eq!(decoder.table_size, 0);
+        assert!(decoder.decode(&[0x3f, 0xe2, 0x1f]).is_err());
+        assert!(decoder.decode(&[0x82, 0x20]).is_err());
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let response = headers(&[
+            (":status", "200"),
+            (":status", "302"),
+            ("content-type", "text/html"),
+            ("x-powered-by", "moon"),
+        ]);
+        let encoded = encode(&response);
+        assert_eq!(encoded[0], 0x88);
+        assert_eq!(Decoder::new().decode(&encoded).unwrap(), response);
+    }
+}
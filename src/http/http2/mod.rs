@@ -0,0 +1,78 @@
+// https://tools.ietf.org/html/rfc7540
+//
+// HTTP/2 on top of the same handlers as HTTP/1.1. Connections start with the client preface
+// (prior knowledge, or ALPN "h2" over TLS) or with an `Upgrade: h2c` request. Each request
+// runs on its own thread and the connection thread multiplexes the responses. No server push
+// and no priorities.
+mod connection;
+mod frame;
+mod hpack;
+mod huffman;
+pub use connection::Http2Connection;
+pub use frame::PREFACE;
+
+use super::super::base64;
+use super::websocket::header_has_token;
+use super::{HttpRequest, HttpResponse};
+use std::sync::Arc;
+
+/// Turns a request into a response, it's called from a thread per stream.
+pub type Handler = Arc<dyn Fn(HttpRequest) -> HttpResponse + Send + Sync>;
+
+/// Payload of the `HTTP2-Settings` header when `request` asks to upgrade to h2c. Requests
+/// over TLS can't, HTTP/2 over TLS is negotiated with ALPN (RFC 9113 section 3.1).
+pub fn upgrade_settings(request: &HttpRequest) -> Option<Vec<u8>> {
+    if request.secure
+        || !header_has_token(request, "Upgrade", "h2c")
+        || !header_has_token(request, "Connection", "HTTP2-Settings")
+    {
+        return None;
+    }
+    // base64url without padding
+    let mut settings: String = request
+        .header("HTTP2-Settings")?
+        .trim()
+        .chars()
+        .map(|ch| match ch {
+            '-' => '+',
+            '_' => '/',
+            ch => ch,
+        })
+        .collect();
+    while settings.len() % 4 != 0 {
+        settings.push('=');
+    }
+    base64::decode(&settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn h2c_upgrade_settings() {
+        let mut request = HttpRequest::new_with_uri("/".to_owned());
+        request.headers = vec![
+            (
+                "Connection".to_owned(),
+                "Upgrade, HTTP2-Settings".to_owned(),
+            ),
+            ("Upgrade".to_owned(), "h2c".to_owned()),
+            (
+                "HTTP2-Settings".to_owned(),
+                "AAMAAABkAAQAoAAAAAIAAAAA".to_owned(),
+            ),
+        ];
+        assert_eq!(
+            upgrade_settings(&request),
+            Some(vec![
+                0, 3, 0, 0, 0, 100, 0, 4, 0, 160, 0, 0, 0, 2, 0, 0, 0, 0
+            ])
+        );
+        request.secure = true;
+        assert_eq!(upgrade_settings(&request), None);
+        request.secure = false;
+        request.headers.remove(1);
+        assert_eq!(upgrade_settings(&request), None);
+    }
+}
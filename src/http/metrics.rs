@@ -0,0 +1,237 @@
+use crate::thread_pool::PoolStats;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of the latency buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    // count per bucket, not cumulative
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    // route, method, status
+    requests: BTreeMap<(String, String, u16), u64>,
+    // route, method
+    latency: BTreeMap<(String, String), Histogram>,
+}
+
+/// Request counters and latency histograms of a server, in the Prometheus text format with
+/// `render`. See `HttpServer::serve_metrics`.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Mutex<Counters>>,
+    in_flight: Arc<AtomicI64>,
+    pool: Arc<Mutex<Option<PoolStats>>>,
+}
+
+/// Counts a request in flight until dropped.
+pub struct InFlight(Arc<AtomicI64>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start_request(&self) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(Arc::clone(&self.in_flight))
+    }
+
+    /// Counts a finished request. Requests without a route are counted with an empty route.
+    pub fn record(&self, route: Option<&str>, method: &str, status: u16, duration: Duration) {
+        let route = route.unwrap_or("").to_owned();
+        let mut counters = self.counters.lock().unwrap();
+        *counters
+            .requests
+            .entry((route.clone(), method.to_owned(), status))
+            .or_insert(0) += 1;
+        let histogram = counters
+            .latency
+            .entry((route, method.to_owned()))
+            .or_default();
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    pub(super) fn set_pool(&self, stats: PoolStats) {
+        *self.pool.lock().unwrap() = Some(stats);
+    }
+
+    /// Metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut output = String::new();
+        output.push_str("# HELP http_requests_total Requests by route, method and status.\n");
+        output.push_str("# TYPE http_requests_total counter\n");
+        for ((route, method, status), count) in &counters.requests {
+            output.push_str(&format!(
+                "http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}\n",
+                escape_label(route),
+                escape_label(method),
+                status,
+                count
+            ));
+        }
+
+        output.push_str(
+            "# HELP http_request_duration_seconds Time to build the response, by route and \
+             method.\n",
+        );
+        output.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for ((route, method), histogram) in &counters.latency {
+            let labels = format!(
+                "route=\"{}\",method=\"{}\"",
+                escape_label(route),
+                escape_label(method)
+            );
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                output.push_str(&format!(
+                    "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}\n",
+                    labels, le, cumulative
+                ));
+            }
+            output.push_str(&format!(
+                "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}\n",
+                labels, histogram.count
+            ));
+            output.push_str(&format!(
+                "http_request_duration_seconds_sum{{{}}} {}\n",
+                labels, histogram.sum
+            ));
+            output.push_str(&format!(
+                "http_request_duration_seconds_count{{{}}} {}\n",
+                labels, histogram.count
+            ));
+        }
+
+        gauge(
+            &mut output,
+            "http_requests_in_flight",
+            "Requests being handled.",
+            self.in_flight.load(Ordering::SeqCst),
+        );
+        if let Some(pool) = &*self.pool.lock().unwrap() {
+            gauge(
+                &mut output,
+                "thread_pool_workers",
+                "Workers of the thread pool.",
+                pool.size() as i64,
+            );
+            gauge(
+                &mut output,
+                "thread_pool_busy_workers",
+                "Workers running a connection.",
+                pool.busy() as i64,
+            );
+            gauge(
+                &mut output,
+                "thread_pool_queued_jobs",
+                "Connections waiting for a free worker.",
+                pool.queued() as i64,
+            );
+        }
+        output
+    }
+}
+
+fn gauge(output: &mut String, name: &str, help: &str, value: i64) {
+    output.push_str(&format!("# HELP {} {}\n", name, help));
+    output.push_str(&format!("# TYPE {} gauge\n", name));
+    output.push_str(&format!("{} {}\n", name, value));
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{HttpServer, TestClient};
+    use super::*;
+
+    #[test]
+    fn render_metrics() {
+        let metrics = Metrics::new();
+        let in_flight = metrics.start_request();
+        metrics.record(Some("/hn/:id<int>"), "GET", 200, Duration::from_millis(20));
+        metrics.record(Some("/hn/:id<int>"), "GET", 200, Duration::from_secs(3));
+        metrics.record(None, "GET", 404, Duration::from_millis(1));
+        metrics.record(Some("/say/\"hi\""), "POST", 500, Duration::from_secs(20));
+        let output = metrics.render();
+        let lines: Vec<&str> = output.lines().collect();
+
+        for line in &[
+            "http_requests_total{route=\"/hn/:id<int>\",method=\"GET\",status=\"200\"} 2",
+            "http_requests_total{route=\"\",method=\"GET\",status=\"404\"} 1",
+            "http_requests_total{route=\"/say/\\\"hi\\\"\",method=\"POST\",status=\"500\"} 1",
+            "http_request_duration_seconds_bucket{route=\"/hn/:id<int>\",method=\"GET\",le=\"0.01\"} 0",
+            "http_request_duration_seconds_bucket{route=\"/hn/:id<int>\",method=\"GET\",le=\"0.025\"} 1",
+            "http_request_duration_seconds_bucket{route=\"/hn/:id<int>\",method=\"GET\",le=\"5\"} 2",
+            "http_request_duration_seconds_bucket{route=\"/hn/:id<int>\",method=\"GET\",le=\"+Inf\"} 2",
+            "http_request_duration_seconds_sum{route=\"/hn/:id<int>\",method=\"GET\"} 3.02",
+            "http_request_duration_seconds_count{route=\"/hn/:id<int>\",method=\"GET\"} 2",
+            "http_request_duration_seconds_bucket{route=\"/say/\\\"hi\\\"\",method=\"POST\",le=\"10\"} 0",
+            "http_request_duration_seconds_bucket{route=\"/say/\\\"hi\\\"\",method=\"POST\",le=\"+Inf\"} 1",
+            "http_requests_in_flight 1",
+        ] {
+            assert!(lines.contains(line), "missing {}", line);
+        }
+        assert!(!output.contains("thread_pool"));
+
+        drop(in_flight);
+        metrics.set_pool(PoolStats::default());
+        let output = metrics.render();
+        assert!(output.contains("http_requests_in_flight 0\n"));
+        assert!(
+            output.contains("# TYPE thread_pool_queued_jobs gauge\nthread_pool_queued_jobs 0\n")
+        );
+    }
+
+    #[test]
+    fn metrics_route() {
+        let mut server = HttpServer::new(());
+        server.route("GET", "/hn/:id<int>", |_, _, _| "item");
+        server.serve_metrics("/metrics");
+        let mut client = TestClient::new(&server);
+        client.get("/hn/1");
+        client.get("/hn/2");
+        client.get("/missing");
+        let response = client.get("/metrics");
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/plain; version=0.0.4; charset=utf-8")
+        );
+        let body = String::from_utf8_lossy(response.body()).into_owned();
+        assert!(body.contains(
+            "http_requests_total{route=\"/hn/:id<int>\",method=\"GET\",status=\"200\"} 2\n"
+        ));
+        assert!(body.contains("http_requests_total{route=\"\",method=\"GET\",status=\"404\"} 1\n"));
+        assert!(body.contains("http_requests_in_flight 1\n"));
+        assert!(server.metrics().is_some());
+    }
+}
@@ -18,16 +18,70 @@ impl From<HttpHeaders> for JsonValue {
     }
 }
 
+mod access_log;
+mod auth;
+mod balancer;
 mod client;
+mod conditional;
+mod connection;
+mod constraints;
+mod cors;
+mod date;
+mod error;
+mod extensions;
+mod extract;
+mod forwarded;
+mod http2;
+mod metrics;
 mod parser;
+mod proxy;
+mod ranges;
 mod request;
 mod response;
+mod route_table;
+mod route_urls;
 pub mod server;
+pub mod sse;
+mod static_files;
+#[cfg(unix)]
+mod unix_socket;
+mod test_client;
+mod test_server;
 mod url;
+mod virtual_host;
+pub mod websocket;
+pub use access_log::{AccessLog, LogFormat};
+pub use auth::{Auth, Credentials};
+pub use balancer::{Balance, UpstreamPool, UpstreamStatus};
 pub use client::{send_http_request, send_http_request_with_headers};
+pub use connection::{Connection, TlsInfo};
+pub use constraints::ParamPredicate;
+pub use cors::Cors;
+pub use conditional::{
+    apply_preconditions, check_preconditions, conditional_get, etag, evaluate_preconditions,
+    ETagStrength,
+};
+pub use date::{format_http_date, parse_http_date};
+pub use error::{error_page, HttpError, IntoResponse, Panic};
+pub use extensions::Extensions;
+pub use extract::{
+    extract, Extension, Fields, Form, FromFields, FromRequest, Handler, Headers, Json, Path, Query,
+};
+pub use forwarded::TrustedProxies;
+pub use metrics::{InFlight, Metrics, LATENCY_BUCKETS};
 pub use parser::HttpParser;
 pub use parser::HttpParserError;
+pub use proxy::{proxy, Proxy, ProxyError, Upstream};
+pub use ranges::{byte_ranges, parse_range, serve_ranges, ByteRange, RangeRequest};
 pub use request::HttpRequest;
-pub use response::HttpResponse;
-pub use server::HttpServer;
+pub use response::{HttpResponse, Upgrade};
+pub use route_table::{RouteInfo, RouteTable};
+pub use route_urls::{RouteUrlError, RouteUrls};
+pub use server::{Endpoint, HttpServer, PlainHttp, Router};
 pub use server::HttpServer as Route;
+pub use sse::{Event, EventBroadcaster, EventStream};
+pub use static_files::{serve_file, static_files};
+pub use test_client::TestClient;
+pub use test_server::TestServer;
+pub use websocket::WebSocket;
+pub use webserver_macros::{delete, get, patch, post, put, route};
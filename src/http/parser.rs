@@ -216,7 +216,7 @@ impl HttpParser {
         Ok(headers)
     }
 
-    fn parse_query_params(uri: &String) -> HashMap<String, String> {
+    pub(super) fn parse_query_params(uri: &String) -> HashMap<String, String> {
         let mut query_params = HashMap::new();
 
         let uri_without_fragment: String = uri.split('#').take(1).collect();
@@ -286,15 +286,7 @@ impl HttpParser {
         let (method, uri, version) = self.parse_request_line()?;
         let (headers, body) = self.parse_message()?;
 
-        Ok(HttpRequest {
-            method,
-            headers,
-            query: HttpParser::parse_query_params(&uri),
-            uri,
-            version,
-            body,
-            params: HashMap::new(),
-        })
+        Ok(HttpRequest::new(method, uri, version, headers, body))
     }
 
     pub fn parse_response(&mut self) -> Result<HttpResponse> {
@@ -306,7 +298,9 @@ impl HttpParser {
             status_code,
             reason,
             headers,
-            body,
+            body: body.into_bytes(),
+            upgrade: None,
+            error: None,
         })
     }
 }
@@ -392,7 +386,7 @@ mod tests {
         assert_eq!(response.headers.len(), 1);
         assert_eq!(response.headers[0].0, "x-test");
         assert_eq!(response.headers[0].1, "more test");
-        assert_eq!(response.body, "lol request to /");
+        assert_eq!(response.body, b"lol request to /");
     }
 
     #[test]
@@ -0,0 +1,682 @@
+use super::balancer::{ActiveRequest, Balance, UpstreamPool};
+use super::server::State;
+use super::{HttpRequest, HttpResponse};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+// https://tools.ietf.org/html/rfc7230#section-6.1
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+// Set by the proxy itself, whatever the client sent is dropped
+const FORWARDED_HEADERS: [&str; 4] = [
+    "host",
+    "content-length",
+    "x-forwarded-host",
+    "x-forwarded-proto",
+];
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+/// Responses with a known length up to this size are read whole, bigger ones are streamed.
+const MAX_BUFFERED_BODY: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum ProxyError {
+    Connect(io::Error),
+    Timeout,
+    InvalidResponse(&'static str),
+    Io(io::Error),
+    /// Every upstream of the pool is unhealthy or ejected.
+    Unavailable,
+}
+
+impl ProxyError {
+    /// 504 when the upstream took too long, 503 when no upstream is available and 502 for
+    /// everything else.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ProxyError::Timeout => 504,
+            ProxyError::Unavailable => 503,
+            _ => 502,
+        }
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Connect(err) => write!(f, "Error connecting to upstream: {}", err),
+            Self::Timeout => write!(f, "Upstream timed out"),
+            Self::InvalidResponse(message) => write!(f, "Invalid upstream response: {}", message),
+            Self::Io(err) => write!(f, "Upstream IO error: {}", err),
+            Self::Unavailable => write!(f, "No upstream available"),
+        }
+    }
+}
+
+impl From<io::Error> for ProxyError {
+    fn from(err: io::Error) -> Self {
+        if is_timeout(&err) {
+            ProxyError::Timeout
+        } else {
+            ProxyError::Io(err)
+        }
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+}
+
+fn is_hop_by_hop(name: &str, connection_header: Option<&str>) -> bool {
+    let name = name.to_ascii_lowercase();
+    HOP_BY_HOP_HEADERS.contains(&name.as_str())
+        || connection_header.is_some_and(|value| {
+            value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case(&name))
+        })
+}
+
+/// Plain HTTP server requests are forwarded to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upstream {
+    pub host: String,
+    pub port: u16,
+    /// Prepended to the path of every request.
+    pub path: String,
+}
+
+impl Upstream {
+    /// Parses `http://host:port/path`, the scheme, port and path being optional.
+    pub fn parse(upstream: &str) -> Option<Self> {
+        let upstream = upstream.strip_prefix("http://").unwrap_or(upstream);
+        if upstream.contains("://") {
+            return None;
+        }
+        let (authority, path) = match upstream.find('/') {
+            Some(slash) => upstream.split_at(slash),
+            None => (upstream, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(Upstream {
+            host: host.to_owned(),
+            port,
+            path: path.trim_end_matches('/').to_owned(),
+        })
+    }
+
+    pub(super) fn authority(&self) -> String {
+        if self.port == 80 {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    pub(super) fn connect(
+        &self,
+        connect_timeout: Duration,
+        timeout: Duration,
+    ) -> Result<TcpStream, ProxyError> {
+        let addresses = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(ProxyError::Connect)?;
+        let mut last_error = ProxyError::InvalidResponse("upstream host has no address");
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, connect_timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(timeout))?;
+                    stream.set_write_timeout(Some(timeout))?;
+                    return Ok(stream);
+                }
+                Err(err) if is_timeout(&err) => last_error = ProxyError::Timeout,
+                Err(err) => last_error = ProxyError::Connect(err),
+            }
+        }
+        Err(last_error)
+    }
+}
+
+enum Framing {
+    Length(usize),
+    ChunkSize,
+    ChunkData(usize),
+    UntilClose,
+    Done,
+}
+
+/// Body of an upstream response, without the chunked encoding.
+struct UpstreamBody {
+    stream: TcpStream,
+    buffered: Vec<u8>,
+    framing: Framing,
+}
+
+impl UpstreamBody {
+    // Reads more from the upstream, `false` when it closed the connection
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 8192];
+        let len = self.stream.read(&mut chunk)?;
+        self.buffered.extend_from_slice(&chunk[..len]);
+        Ok(len > 0)
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        loop {
+            if let Some(end) = self.buffered.windows(2).position(|bytes| bytes == b"\r\n") {
+                let line = String::from_utf8_lossy(&self.buffered[..end]).into_owned();
+                self.buffered.drain(..end + 2);
+                return Ok(line);
+            }
+            if self.buffered.len() > MAX_HEAD_SIZE || !self.fill()? {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid chunked encoding",
+                ));
+            }
+        }
+    }
+
+    fn take(&mut self, buf: &mut [u8], limit: usize) -> io::Result<usize> {
+        if self.buffered.is_empty() && !self.fill()? {
+            return Ok(0);
+        }
+        let len = buf.len().min(limit).min(self.buffered.len());
+        buf[..len].copy_from_slice(&self.buffered[..len]);
+        self.buffered.drain(..len);
+        Ok(len)
+    }
+}
+
+impl Read for UpstreamBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "truncated upstream body");
+        loop {
+            match self.framing {
+                Framing::Done | Framing::Length(0) => return Ok(0),
+                Framing::Length(remaining) => {
+                    let len = self.take(buf, remaining)?;
+                    if len == 0 {
+                        return Err(truncated());
+                    }
+                    self.framing = Framing::Length(remaining - len);
+                    return Ok(len);
+                }
+                Framing::UntilClose => {
+                    let len = self.take(buf, usize::MAX)?;
+                    if len == 0 {
+                        self.framing = Framing::Done;
+                    }
+                    return Ok(len);
+                }
+                Framing::ChunkSize => {
+                    let line = self.read_line()?;
+                    let size = line.split(';').next().unwrap_or("").trim();
+                    let size = usize::from_str_radix(size, 16).map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size")
+                    })?;
+                    if size > 0 {
+                        self.framing = Framing::ChunkData(size);
+                        continue;
+                    }
+                    // Trailers are dropped
+                    while !self.read_line()?.is_empty() {}
+                    self.framing = Framing::Done;
+                }
+                Framing::ChunkData(remaining) => {
+                    let len = self.take(buf, remaining)?;
+                    if len == 0 {
+                        return Err(truncated());
+                    }
+                    if len == remaining {
+                        self.read_line()?;
+                        self.framing = Framing::ChunkSize;
+                    } else {
+                        self.framing = Framing::ChunkData(remaining - len);
+                    }
+                    return Ok(len);
+                }
+            }
+        }
+    }
+}
+
+pub(super) struct ResponseHead {
+    pub(super) status_code: u16,
+    headers: Vec<(String, String)>,
+}
+
+impl ResponseHead {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn parse(head: &str) -> Result<Self, ProxyError> {
+        let mut lines = head.split("\r\n");
+        let status_line = lines.next().unwrap_or("");
+        let mut parts = status_line.splitn(3, ' ');
+        if !parts.next().unwrap_or("").starts_with("HTTP/1.") {
+            return Err(ProxyError::InvalidResponse("not an HTTP/1.x response"));
+        }
+        let status_code = parts
+            .next()
+            .and_then(|code| code.parse().ok())
+            .ok_or(ProxyError::InvalidResponse("invalid status code"))?;
+        let mut headers = Vec::new();
+        for line in lines.filter(|line| !line.is_empty()) {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or(ProxyError::InvalidResponse("invalid header"))?;
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+        Ok(ResponseHead {
+            status_code,
+            headers,
+        })
+    }
+}
+
+pub(super) fn read_head(stream: &mut TcpStream) -> Result<(ResponseHead, Vec<u8>), ProxyError> {
+    let mut buffered = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        if let Some(end) = buffered.windows(4).position(|bytes| bytes == b"\r\n\r\n") {
+            let head = ResponseHead::parse(&String::from_utf8_lossy(&buffered[..end]))?;
+            buffered.drain(..end + 4);
+            // Interim responses, like 103 Early Hints, are skipped
+            if (100..200).contains(&head.status_code) && head.status_code != 101 {
+                continue;
+            }
+            return Ok((head, buffered));
+        }
+        if buffered.len() > MAX_HEAD_SIZE {
+            return Err(ProxyError::InvalidResponse("response head too big"));
+        }
+        let len = stream.read(&mut chunk)?;
+        if len == 0 {
+            return Err(ProxyError::InvalidResponse(
+                "connection closed before the response",
+            ));
+        }
+        buffered.extend_from_slice(&chunk[..len]);
+    }
+}
+
+/// Forwards requests to an upstream server, or a pool of them, over HTTP/1.1.
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    pool: Arc<UpstreamPool>,
+    strip_prefix: String,
+    connect_timeout: Duration,
+    timeout: Duration,
+}
+
+impl Proxy {
+    /// Panics if `upstream` isn't a plain `http://host:port/path` URL, HTTPS upstreams are not
+    /// supported.
+    pub fn new(upstream: &str) -> Self {
+        Proxy::with_pool(UpstreamPool::new(&[upstream], Balance::RoundRobin))
+    }
+
+    /// Spreads requests over the upstreams of `pool`.
+    pub fn with_pool(pool: UpstreamPool) -> Self {
+        Proxy {
+            pool: Arc::new(pool),
+            strip_prefix: String::new(),
+            connect_timeout: Duration::from_secs(5),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Removes `prefix` from the request path before forwarding it, so `/api/:path` can be
+    /// served by an upstream that knows nothing about `/api`.
+    pub fn set_strip_prefix(&mut self, prefix: &str) {
+        self.strip_prefix = prefix.to_owned();
+    }
+
+    pub fn set_connect_timeout(&mut self, timeout: Duration) {
+        self.connect_timeout = timeout;
+    }
+
+    /// Longest the upstream can take to answer or to send the next bit of the body.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Shared with every clone of the proxy, to look at the upstreams with
+    /// `UpstreamPool::status` or serve them with `UpstreamPool::status_handler`.
+    pub fn pool(&self) -> &Arc<UpstreamPool> {
+        &self.pool
+    }
+
+    fn request_head(&self, upstream: &Upstream, request: &HttpRequest) -> String {
+        let uri = request
+            .uri
+            .strip_prefix(self.strip_prefix.as_str())
+            .unwrap_or(&request.uri);
+        let mut path = format!("{}{}", upstream.path, uri);
+        if !path.starts_with('/') {
+            path.insert(0, '/');
+        }
+
+        let mut head = format!("{} {} HTTP/1.1\r\n", request.method, path);
+        let connection_header = request.header("Connection");
+        let mut forwarded_for = None;
+        for (name, value) in &request.headers {
+            if is_hop_by_hop(name, connection_header)
+                || FORWARDED_HEADERS.contains(&name.to_ascii_lowercase().as_str())
+            {
+                continue;
+            }
+            if name.eq_ignore_ascii_case("X-Forwarded-For") {
+                forwarded_for = Some(value.clone());
+                continue;
+            }
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        head.push_str(&format!("Host: {}\r\n", upstream.authority()));
+        let client = request.peer_addr.map(|address| address.ip().to_string());
+        let forwarded_for = match (forwarded_for, client) {
+            (Some(chain), Some(client)) => Some(format!("{}, {}", chain, client)),
+            (chain, client) => chain.or(client),
+        };
+        if let Some(forwarded_for) = forwarded_for {
+            head.push_str(&format!("X-Forwarded-For: {}\r\n", forwarded_for));
+        }
+        let proto = if request.secure { "https" } else { "http" };
+        head.push_str(&format!("X-Forwarded-Proto: {}\r\n", proto));
+        if let Some(host) = request.header("Host") {
+            head.push_str(&format!("X-Forwarded-Host: {}\r\n", host));
+        }
+        if !request.body.is_empty() || ["POST", "PUT", "PATCH"].contains(&request.method.as_str()) {
+            head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+        }
+        head.push_str("Connection: close\r\n\r\n");
+        head
+    }
+
+    /// Sends `request` upstream and fills `response` with the answer. Big bodies and bodies
+    /// of unknown length are streamed to the client once the head is sent. Upstreams that
+    /// can't be connected to are failed over to the next one of the pool, nothing has been
+    /// sent to them yet.
+    pub fn forward(
+        &self,
+        request: &HttpRequest,
+        response: &mut HttpResponse,
+    ) -> Result<(), ProxyError> {
+        let mut tried = Vec::new();
+        loop {
+            let index = self
+                .pool
+                .choose(request, &tried)
+                .ok_or(ProxyError::Unavailable)?;
+            tried.push(index);
+            let upstream = self.pool.upstream(index);
+            let active = self.pool.start_request(index);
+            let result = upstream
+                .connect(self.connect_timeout, self.timeout)
+                .and_then(|stream| self.exchange(upstream, stream, active, request, response));
+            match result {
+                Ok(()) => {
+                    self.pool.report_success(index);
+                    return Ok(());
+                }
+                Err(err) => {
+                    self.pool.report_failure(index);
+                    let connect_error = matches!(err, ProxyError::Connect(_));
+                    if !connect_error || tried.len() == self.pool.len() {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+
+    fn exchange(
+        &self,
+        upstream: &Upstream,
+        mut stream: TcpStream,
+        active: ActiveRequest,
+        request: &HttpRequest,
+        response: &mut HttpResponse,
+    ) -> Result<(), ProxyError> {
+        let mut head = self.request_head(upstream, request).into_bytes();
+        head.extend_from_slice(request.body.as_bytes());
+        stream.write_all(&head)?;
+        let (head, buffered) = read_head(&mut stream)?;
+        if head.status_code == 101 {
+            return Err(ProxyError::InvalidResponse("upgrades are not supported"));
+        }
+
+        let chunked = head
+            .header("Transfer-Encoding")
+            .is_some_and(|value| value.to_ascii_lowercase().contains("chunked"));
+        let content_length = head
+            .header("Content-Length")
+            .and_then(|value| value.trim().parse().ok());
+        let framing =
+            if request.method == "HEAD" || head.status_code == 204 || head.status_code == 304 {
+                Framing::Done
+            } else if chunked {
+                Framing::ChunkSize
+            } else if let Some(content_length) = content_length {
+                Framing::Length(content_length)
+            } else {
+                Framing::UntilClose
+            };
+        let mut body = UpstreamBody {
+            stream,
+            buffered,
+            framing,
+        };
+        let small_body = match body.framing {
+            Framing::Done => Some(Vec::new()),
+            Framing::Length(len) if len <= MAX_BUFFERED_BODY => {
+                let mut data = Vec::with_capacity(len);
+                body.read_to_end(&mut data)?;
+                Some(data)
+            }
+            _ => None,
+        };
+
+        response.set_status_code(head.status_code);
+        let connection_header = head.header("Connection");
+        for (name, value) in &head.headers {
+            let dropped = is_hop_by_hop(name, connection_header)
+                || (chunked && name.eq_ignore_ascii_case("Content-Length"));
+            if !dropped {
+                response.add_header(name.clone(), value.clone());
+            }
+        }
+        match small_body {
+            Some(data) => response.set_body_bytes(data),
+            None => response.set_upgrade(move |mut connection| {
+                // The upstream is busy until the whole body is sent
+                let _active = active;
+                if let Err(err) = io::copy(&mut body, &mut connection) {
+                    eprintln!("Error streaming upstream response: {}", err);
+                }
+            }),
+        }
+        Ok(())
+    }
+
+    /// Route handler forwarding every request, upstream failures become 502 or 504
+    /// responses.
+    pub fn handler<T>(self) -> impl Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync {
+        self.pool.start_health_checks();
+        move |request, response, _| {
+            if let Err(err) = self.forward(request, response) {
+                eprintln!("{}", err);
+                response.set_status_code(err.status_code());
+                response.set_body(err.to_string());
+            }
+        }
+    }
+}
+
+/// Route handler forwarding requests to `upstream` with the default timeouts.
+pub fn proxy<T>(
+    upstream: &str,
+) -> impl Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync {
+    Proxy::new(upstream).handler()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::connection::MockConnection;
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Answers one request with `reply` and returns what it received
+    fn upstream(reply: &'static [u8]) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            let mut chunk = [0; 1024];
+            while !String::from_utf8_lossy(&received).contains("\r\n\r\n") {
+                let len = stream.read(&mut chunk).unwrap();
+                received.extend_from_slice(&chunk[..len]);
+            }
+            stream.write_all(reply).unwrap();
+            String::from_utf8(received).unwrap()
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn parse_upstreams() {
+        assert_eq!(
+            Upstream::parse("http://localhost:8081/v0/"),
+            Some(Upstream {
+                host: "localhost".to_owned(),
+                port: 8081,
+                path: "/v0".to_owned()
+            })
+        );
+        assert_eq!(Upstream::parse("example.com").unwrap().port, 80);
+        assert_eq!(Upstream::parse("https://example.com"), None);
+        assert_eq!(Upstream::parse("localhost:http"), None);
+    }
+
+    #[test]
+    fn forward_request() {
+        let (address, upstream) = upstream(
+            b"HTTP/1.1 201 Created\r\nContent-Length: 5\r\nConnection: close, X-Private\r\n\
+              X-Private: 1\r\nX-Upstream: yes\r\n\r\nhello",
+        );
+        let mut proxy = Proxy::new(&format!("http://{}", address));
+        proxy.set_strip_prefix("/api");
+        let mut request = HttpRequest::new_with_uri("/api/items?page=2".to_owned());
+        request.method = "POST".to_owned();
+        request.body = "data".to_owned();
+        request.peer_addr = Some("192.0.2.1:4000".parse().unwrap());
+        request.headers = vec![
+            ("Host".to_owned(), "example.com".to_owned()),
+            ("Connection".to_owned(), "keep-alive".to_owned()),
+            ("X-Forwarded-For".to_owned(), "10.0.0.1".to_owned()),
+            ("Accept".to_owned(), "text/plain".to_owned()),
+        ];
+        let mut response = HttpResponse::new();
+        proxy.forward(&request, &mut response).unwrap();
+
+        let received = upstream.join().unwrap();
+        assert!(received.starts_with("POST /items?page=2 HTTP/1.1\r\n"));
+        assert!(received.contains(&format!("Host: {}\r\n", address)));
+        assert!(received.contains("Accept: text/plain\r\n"));
+        assert!(received.contains("X-Forwarded-For: 10.0.0.1, 192.0.2.1\r\n"));
+        assert!(received.contains("X-Forwarded-Host: example.com\r\n"));
+        assert!(received.contains("X-Forwarded-Proto: http\r\n"));
+        assert!(received.contains("Connection: close\r\n"));
+        assert!(!received.contains("keep-alive"));
+        assert!(received.contains("Content-Length: 4\r\nConnection: close\r\n\r\n"));
+
+        assert_eq!(response.status_code, 201);
+        assert_eq!(response.body, b"hello".to_vec());
+        assert_eq!(response.header("X-Upstream"), Some("yes"));
+        assert_eq!(response.header("X-Private"), None);
+        assert_eq!(response.header("Connection"), None);
+    }
+
+    #[test]
+    fn stream_chunked_response() {
+        let (address, _) = upstream(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n6;name=value\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\n",
+        );
+        let mut response = HttpResponse::new();
+        Proxy::new(&address)
+            .forward(&HttpRequest::new_with_uri("/".to_owned()), &mut response)
+            .unwrap();
+        assert_eq!(response.header("Transfer-Encoding"), None);
+        let (connection, output) = MockConnection::new(Vec::new());
+        response.take_upgrade().unwrap().run(Box::new(connection));
+        assert_eq!(output.lock().unwrap().as_slice(), b"hello world");
+    }
+
+    #[test]
+    fn upstream_failures() {
+        let request = HttpRequest::new_with_uri("/".to_owned());
+        // Nothing listens on the port once the listener is dropped
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let handler = proxy::<()>(&address.to_string());
+        let mut response = HttpResponse::new();
+        handler(&request, &mut response, State::default());
+        assert_eq!(response.status_code, 502);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut proxy = Proxy::new(&listener.local_addr().unwrap().to_string());
+        proxy.set_timeout(Duration::from_millis(50));
+        let err = proxy
+            .forward(&request, &mut HttpResponse::new())
+            .unwrap_err();
+        assert_eq!(err.status_code(), 504);
+    }
+
+    #[test]
+    fn fail_over_to_next_upstream() {
+        let down = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let (address, _) = upstream(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        let proxy = Proxy::with_pool(UpstreamPool::new(&[&down, &address], Balance::RoundRobin));
+        let mut response = HttpResponse::new();
+        proxy
+            .forward(&HttpRequest::new_with_uri("/".to_owned()), &mut response)
+            .unwrap();
+        assert_eq!(response.body, b"ok".to_vec());
+        let status = proxy.pool().status();
+        assert_eq!((status[0].requests, status[0].failures), (1, 1));
+        assert_eq!((status[1].requests, status[1].failures), (1, 0));
+        assert_eq!(status[1].active_connections, 0);
+    }
+}
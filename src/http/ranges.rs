@@ -0,0 +1,324 @@
+// https://tools.ietf.org/html/rfc7233
+use super::conditional::strong_etag_match;
+use super::date::parse_http_date;
+use super::server::State;
+use super::{HttpRequest, HttpResponse};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static BOUNDARY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Headers with more ranges than this are ignored and get the whole representation.
+const MAX_RANGES: usize = 16;
+
+/// Inclusive range of bytes, like the ones in `Content-Range`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    fn content_range(&self, length: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, length)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+    /// The header is malformed or uses another unit, so it has to be ignored.
+    Ignored,
+    Unsatisfiable,
+    Satisfiable(Vec<ByteRange>),
+}
+
+fn parse_range_spec(spec: &str, length: u64) -> Option<Option<ByteRange>> {
+    let (first, last) = spec.trim().split_once('-')?;
+    if first.is_empty() {
+        let suffix_length = last.parse::<u64>().ok()?;
+        if suffix_length == 0 || length == 0 {
+            return Some(None);
+        }
+        return Some(Some(ByteRange {
+            start: length.saturating_sub(suffix_length),
+            end: length - 1,
+        }));
+    }
+    let start = first.parse::<u64>().ok()?;
+    let end = if last.is_empty() {
+        u64::MAX
+    } else {
+        last.parse::<u64>().ok()?
+    };
+    if end < start {
+        return None;
+    }
+    if start >= length {
+        return Some(None);
+    }
+    Some(Some(ByteRange {
+        start,
+        end: end.min(length - 1),
+    }))
+}
+
+/// Parses a `Range` header value for a representation of `length` bytes. Overlapping and
+/// adjacent ranges are merged, in ascending order. Headers with more than 16 ranges, or with
+/// ranges adding up to more than the representation, are ignored so they can't multiply the
+/// size of the response.
+pub fn parse_range(value: &str, length: u64) -> RangeRequest {
+    let specs = match value.trim().strip_prefix("bytes=") {
+        Some(specs) => specs,
+        None => return RangeRequest::Ignored,
+    };
+    if specs.split(',').count() > MAX_RANGES {
+        return RangeRequest::Ignored;
+    }
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        match parse_range_spec(spec, length) {
+            None => return RangeRequest::Ignored,
+            Some(None) => {}
+            Some(Some(range)) => ranges.push(range),
+        }
+    }
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    if ranges.iter().map(ByteRange::len).sum::<u64>() > length {
+        return RangeRequest::Ignored;
+    }
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end + 1 => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    RangeRequest::Satisfiable(merged)
+}
+
+fn if_range_matches(request: &HttpRequest, response: &HttpResponse) -> bool {
+    let if_range = match request.header("If-Range") {
+        None => return true,
+        Some(if_range) => if_range.trim(),
+    };
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return match response.header("ETag") {
+            Some(etag) => strong_etag_match(if_range, etag),
+            None => false,
+        };
+    }
+    let last_modified = response.header("Last-Modified").and_then(parse_http_date);
+    match (parse_http_date(if_range), last_modified) {
+        (Some(if_range), Some(last_modified)) => if_range == last_modified,
+        _ => false,
+    }
+}
+
+fn multipart_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let counter = BOUNDARY_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("moon-byteranges-{:08x}{:04x}", nanos, counter & 0xffff)
+}
+
+/// Fills the body of a response for a representation of `length` bytes, honoring the `Range`
+/// and `If-Range` headers of the request. `read` is called with the offset and the number of
+/// bytes to read, so files don't have to be loaded entirely to serve a small range.
+pub fn serve_ranges<F>(
+    request: &HttpRequest,
+    response: &mut HttpResponse,
+    length: u64,
+    mut read: F,
+) -> io::Result<()>
+where
+    F: FnMut(u64, u64) -> io::Result<Vec<u8>>,
+{
+    response.set_header("Accept-Ranges", "bytes".to_owned());
+
+    let range_request = match request.header("Range") {
+        Some(range) if request.method == "GET" && if_range_matches(request, response) => {
+            parse_range(range, length)
+        }
+        _ => RangeRequest::Ignored,
+    };
+
+    match range_request {
+        RangeRequest::Ignored => {
+            response.set_body_bytes(read(0, length)?);
+        }
+        RangeRequest::Unsatisfiable => {
+            response.set_status_code(416);
+            response.set_header("Content-Range", format!("bytes */{}", length));
+            response.set_body_bytes(Vec::new());
+        }
+        RangeRequest::Satisfiable(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            response.set_status_code(206);
+            response.set_header("Content-Range", range.content_range(length));
+            response.set_body_bytes(read(range.start, range.len())?);
+        }
+        RangeRequest::Satisfiable(ranges) => {
+            let boundary = multipart_boundary();
+            let content_type = response
+                .header("Content-Type")
+                .map(|value| value.to_owned());
+            let mut body = Vec::new();
+            for range in ranges {
+                body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+                if let Some(content_type) = &content_type {
+                    body.extend_from_slice(
+                        format!("Content-Type: {}\r\n", content_type).as_bytes(),
+                    );
+                }
+                body.extend_from_slice(
+                    format!("Content-Range: {}\r\n\r\n", range.content_range(length)).as_bytes(),
+                );
+                body.append(&mut read(range.start, range.len())?);
+                body.extend_from_slice(b"\r\n");
+            }
+            body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+            response.set_status_code(206);
+            response.set_header(
+                "Content-Type",
+                format!("multipart/byteranges; boundary={}", boundary),
+            );
+            response.set_body_bytes(body);
+        }
+    }
+
+    Ok(())
+}
+
+/// After middleware that serves byte ranges of successful responses with an in memory body.
+/// Register it after `conditional_get` so `If-Range` can use the generated `ETag`.
+pub fn byte_ranges<T>() -> impl Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync {
+    |request, response, _| {
+        if response.status_code() != 200 || response.upgrade.is_some() {
+            return;
+        }
+        let body = std::mem::take(&mut response.body);
+        let length = body.len() as u64;
+        serve_ranges(request, response, length, |start, len| {
+            Ok(body[start as usize..(start + len) as usize].to_vec())
+        })
+        .expect("reading from memory doesn't fail");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn ranged_response(range: &str) -> HttpResponse {
+        let mut request = HttpRequest::new_with_uri("/".to_owned());
+        request.headers.push(("Range".to_owned(), range.to_owned()));
+        let mut response = HttpResponse::new();
+        response.add_header("Content-Type".to_owned(), "text/plain".to_owned());
+        response.set_body("0123456789".to_owned());
+        byte_ranges::<()>()(&request, &mut response, Arc::new(Mutex::new(())));
+        response
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(
+            parse_range("bytes=-2, 0-1, 3-4", 10),
+            RangeRequest::Satisfiable(vec![
+                ByteRange { start: 0, end: 1 },
+                ByteRange { start: 3, end: 4 },
+                ByteRange { start: 8, end: 9 }
+            ])
+        );
+        // overlapping and adjacent ranges are merged
+        assert_eq!(
+            parse_range("bytes=0-2, 8-, -3, 3-4", 10),
+            RangeRequest::Satisfiable(vec![
+                ByteRange { start: 0, end: 4 },
+                ByteRange { start: 7, end: 9 }
+            ])
+        );
+        assert_eq!(
+            parse_range("bytes=5-100", 10),
+            RangeRequest::Satisfiable(vec![ByteRange { start: 5, end: 9 }])
+        );
+        assert_eq!(parse_range("bytes=10-", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=4-2", 10), RangeRequest::Ignored);
+        assert_eq!(parse_range("items=0-1", 10), RangeRequest::Ignored);
+    }
+
+    #[test]
+    fn amplifying_ranges_are_ignored() {
+        assert_eq!(parse_range("bytes=0-,0-", 10), RangeRequest::Ignored);
+        let many = vec!["0-0"; MAX_RANGES + 1].join(",");
+        assert_eq!(
+            parse_range(&format!("bytes={}", many), 1000),
+            RangeRequest::Ignored
+        );
+        let response = ranged_response("bytes=0-,0-,0-");
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), b"0123456789");
+    }
+
+    #[test]
+    fn single_range() {
+        let response = ranged_response("bytes=2-4");
+        assert_eq!(response.status_code(), 206);
+        assert_eq!(response.header("Content-Range"), Some("bytes 2-4/10"));
+        assert_eq!(response.body(), b"234");
+    }
+
+    #[test]
+    fn multiple_ranges() {
+        let response = ranged_response("bytes=0-1,-2");
+        assert_eq!(response.status_code(), 206);
+        let content_type = response.header("Content-Type").unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        let expected = format!(
+            "--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n\
+             --{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n\
+             --{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(response.body(), expected.as_bytes());
+    }
+
+    #[test]
+    fn unsatisfiable_range() {
+        let response = ranged_response("bytes=20-30");
+        assert_eq!(response.status_code(), 416);
+        assert_eq!(response.header("Content-Range"), Some("bytes */10"));
+        assert_eq!(response.body(), b"");
+    }
+
+    #[test]
+    fn if_range_mismatch_sends_full_body() {
+        let mut request = HttpRequest::new_with_uri("/".to_owned());
+        request
+            .headers
+            .push(("Range".to_owned(), "bytes=0-1".to_owned()));
+        request
+            .headers
+            .push(("If-Range".to_owned(), "\"v1\"".to_owned()));
+        let mut response = HttpResponse::new();
+        response.add_header("ETag".to_owned(), "\"v2\"".to_owned());
+        response.set_body("0123456789".to_owned());
+        byte_ranges::<()>()(&request, &mut response, Arc::new(Mutex::new(())));
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), b"0123456789");
+        assert_eq!(response.header("Accept-Ranges"), Some("bytes"));
+    }
+}
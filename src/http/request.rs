@@ -1,7 +1,12 @@
-use super::HttpHeaders;
+use super::connection::{Connection, TlsInfo};
+use super::extensions::Extensions;
+use super::route_urls::RouteUrls;
+use super::{HttpHeaders, HttpParser};
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HttpRequest {
     pub method: String,
     pub version: String,
@@ -9,7 +14,76 @@ pub struct HttpRequest {
     pub uri: String,
     pub body: String,
     pub params: HashMap<String, String>,
+    /// Pattern of the route handling the request, like `/hn/:id<int>`.
+    pub route: Option<String>,
     pub query: HashMap<String, String>,
+    /// Address of the client, if the connection has one.
+    pub peer_addr: Option<SocketAddr>,
+    /// Address the connection was accepted on.
+    pub local_addr: Option<SocketAddr>,
+    /// Address of the client, the peer address unless it is a trusted proxy, see
+    /// `HttpServer::set_trusted_proxies`.
+    pub client_ip: Option<IpAddr>,
+    /// Whether the request came in over TLS.
+    pub secure: bool,
+    pub tls: Option<TlsInfo>,
+    /// When the request was read, to time the response.
+    pub received_at: Instant,
+    /// Values added by middleware for the handler, see `Extensions`.
+    pub extensions: Extensions,
+}
+
+impl HttpRequest {
+    /// Request with the query string of `uri` parsed. The connection, route and parameter
+    /// fields are filled in by the server.
+    pub fn new(
+        method: String,
+        uri: String,
+        version: String,
+        headers: HttpHeaders,
+        body: String,
+    ) -> Self {
+        HttpRequest {
+            query: HttpParser::parse_query_params(&uri),
+            method,
+            version,
+            headers,
+            uri,
+            body,
+            params: HashMap::new(),
+            route: None,
+            peer_addr: None,
+            local_addr: None,
+            client_ip: None,
+            secure: false,
+            tls: None,
+            received_at: Instant::now(),
+            extensions: Extensions::new(),
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(super) fn set_connection(&mut self, connection: &dyn Connection) {
+        self.peer_addr = connection.peer_addr();
+        self.local_addr = connection.local_addr();
+        self.secure = connection.is_secure();
+        self.tls = connection.tls_info();
+    }
+
+    /// Named routes of the router handling the request, to build links with `url_for`.
+    /// Empty before the request reaches a router.
+    pub fn urls(&self) -> RouteUrls {
+        self.extensions
+            .get::<RouteUrls>()
+            .cloned()
+            .unwrap_or_default()
+    }
 }
 
 impl ToString for HttpRequest {
@@ -30,14 +104,12 @@ impl ToString for HttpRequest {
 #[cfg(test)]
 impl HttpRequest {
     pub fn new_with_uri(uri: String) -> Self {
-        Self {
-            method: "GET".to_owned(),
+        Self::new(
+            "GET".to_owned(),
             uri,
-            headers: Vec::new(),
-            version: "1.1".to_owned(),
-            body: String::new(),
-            params: HashMap::new(),
-            query: HashMap::new(),
-        }
+            "1.1".to_owned(),
+            Vec::new(),
+            String::new(),
+        )
     }
 }
@@ -1,25 +1,67 @@
 use super::super::json::{JsonParser, JsonValue};
+use super::connection::Connection;
+use super::error::HttpError;
 use super::HttpHeaders;
 use std::collections::HashMap;
+use std::fmt;
+
+/// Takes over the connection once the response head has been written.
+pub struct Upgrade(Box<dyn FnOnce(Box<dyn Connection>) + Send>);
+
+impl Upgrade {
+    pub fn run(self, connection: Box<dyn Connection>) {
+        (self.0)(connection)
+    }
+}
+
+impl fmt::Debug for Upgrade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Upgrade")
+    }
+}
 
 #[derive(Debug)]
 pub struct HttpResponse {
     pub headers: HttpHeaders,
     pub status_code: u16,
-    pub body: String,
+    pub body: Vec<u8>,
     pub version: String,
     pub reason: String,
+    pub upgrade: Option<Upgrade>,
+    /// Set by handlers returning an error, the router turns it into the actual response.
+    pub error: Option<HttpError>,
 }
 
 const HTTP_VERSION: &str = "HTTP/1.1";
 
+pub(super) fn reason_phrase(status_code: u16) -> Option<&'static str> {
+    HttpResponse::http_reasons().get(&status_code).copied()
+}
+
 impl HttpResponse {
     fn http_reasons() -> HashMap<u16, &'static str> {
         [
+            (101, "Switching protocols"),
             (200, "Ok"),
+            (204, "No content"),
+            (206, "Partial content"),
+            (301, "Moved permanently"),
+            (304, "Not modified"),
+            (308, "Permanent redirect"),
             (400, "Bad request"),
+            (401, "Unauthorized"),
+            (403, "Forbidden"),
             (404, "Not found"),
+            (405, "Method not allowed"),
+            (412, "Precondition failed"),
+            (413, "Payload too large"),
+            (415, "Unsupported media type"),
+            (416, "Range not satisfiable"),
+            (426, "Upgrade required"),
             (500, "Internal server error"),
+            (502, "Bad gateway"),
+            (503, "Service unavailable"),
+            (504, "Gateway timeout"),
         ]
         .iter()
         .cloned()
@@ -30,16 +72,18 @@ impl HttpResponse {
         HttpResponse {
             headers: Vec::new(),
             status_code: 200,
-            body: String::new(),
+            body: Vec::new(),
             version: String::from("1.1"),
             reason: String::from(""),
+            upgrade: None,
+            error: None,
         }
     }
 
     pub fn bad_request(message: &str) -> HttpResponse {
         let mut response = Self::new();
         response.status_code = 404;
-        response.body = message.to_owned();
+        response.body = message.as_bytes().to_vec();
         response
     }
 
@@ -55,6 +99,23 @@ impl HttpResponse {
         self.headers_mut().push((header_key, header_value));
     }
 
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_header(&mut self, header_key: &str, header_value: String) {
+        self.remove_header(header_key);
+        self.add_header(header_key.to_owned(), header_value);
+    }
+
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+
     pub fn status_code(&self) -> u16 {
         self.status_code
     }
@@ -63,19 +124,39 @@ impl HttpResponse {
         self.status_code = status_code;
     }
 
-    pub fn body(&self) -> &str {
-        self.body.as_str()
+    pub fn body(&self) -> &[u8] {
+        &self.body
     }
 
     pub fn set_body(&mut self, body: String) {
+        self.body = body.into_bytes();
+    }
+
+    pub fn set_body_bytes(&mut self, body: Vec<u8>) {
         self.body = body;
     }
 
+    pub fn set_upgrade<F>(&mut self, upgrade: F)
+    where
+        F: FnOnce(Box<dyn Connection>) + Send + 'static,
+    {
+        self.upgrade = Some(Upgrade(Box::new(upgrade)));
+    }
+
+    pub fn take_upgrade(&mut self) -> Option<Upgrade> {
+        self.upgrade.take()
+    }
+
+    pub fn set_error(&mut self, error: HttpError) {
+        self.error = Some(error);
+    }
+
+    pub fn take_error(&mut self) -> Option<HttpError> {
+        self.error.take()
+    }
+
     fn get_status_line(&self) -> String {
-        let reasons = HttpResponse::http_reasons();
-        let reason_phrase = reasons
-            .get(&self.status_code())
-            .unwrap_or(&"Something happened");
+        let reason_phrase = reason_phrase(self.status_code()).unwrap_or("Something happened");
         format!("{} {} {}", HTTP_VERSION, self.status_code(), reason_phrase)
     }
 
@@ -89,14 +170,20 @@ impl HttpResponse {
         headers_string
     }
 
-    pub fn to_string(&self) -> String {
+    pub fn to_bytes(&self) -> Vec<u8> {
         let status_line = self.get_status_line();
         let headers = self.headers_to_string();
-        format!("{}\r\n{}\r\n{}", status_line, headers, self.body())
+        let mut bytes = format!("{}\r\n{}\r\n", status_line, headers).into_bytes();
+        bytes.extend_from_slice(self.body());
+        bytes
+    }
+
+    pub fn to_string(&self) -> String {
+        String::from_utf8_lossy(&self.to_bytes()).into_owned()
     }
 
     pub fn json(&self) -> JsonValue {
-        JsonParser::new(&self.body).parse()
+        JsonParser::new(&String::from_utf8_lossy(&self.body)).parse()
     }
 }
 
@@ -0,0 +1,310 @@
+use super::constraints::{split_parameter, Constraint};
+use super::route_urls::RouteUrls;
+use crate::json::JsonValue;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+const OPENAPI_METHODS: [&str; 8] = [
+    "GET", "PUT", "POST", "DELETE", "OPTIONS", "HEAD", "PATCH", "TRACE",
+];
+
+/// Route registered in a router, as listed by `Router::route_table`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteInfo {
+    pub method: String,
+    pub uri: String,
+    pub name: Option<String>,
+    pub middleware: usize,
+    pub summary: Option<String>,
+    /// Schema of the route parameter, replacing the one derived from its constraint.
+    pub param_schema: Option<JsonValue>,
+}
+
+/// Routes of a router. Clones share them, so they see routes added after they were taken.
+#[derive(Clone, Debug, Default)]
+pub struct RouteTable {
+    routes: Arc<RwLock<Vec<RouteInfo>>>,
+    urls: RouteUrls,
+}
+
+impl RouteTable {
+    pub(super) fn new(urls: RouteUrls) -> Self {
+        RouteTable {
+            routes: Arc::default(),
+            urls,
+        }
+    }
+
+    pub(super) fn add(&self, method: &str, uri: &str, middleware: usize) {
+        self.routes.write().unwrap().push(RouteInfo {
+            method: method.to_owned(),
+            uri: uri.to_owned(),
+            name: None,
+            middleware,
+            summary: None,
+            param_schema: None,
+        });
+    }
+
+    /// Panics if there is no route for `method` and `uri`.
+    pub(super) fn update<F: FnOnce(&mut RouteInfo)>(&self, method: &str, uri: &str, update: F) {
+        let mut routes = self.routes.write().unwrap();
+        match routes
+            .iter_mut()
+            .rfind(|route| route.method == method && route.uri == uri)
+        {
+            Some(route) => update(route),
+            None => panic!("There is no route {} {}", method, uri),
+        }
+    }
+
+    /// Registered routes, in registration order.
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        let mut routes = self.routes.read().unwrap().clone();
+        for route in &mut routes {
+            route.name = self.urls.name_of(&route.method, &route.uri);
+        }
+        routes
+    }
+
+    /// OpenAPI 3 document describing the routes. Methods OpenAPI doesn't know are left out.
+    pub fn openapi(&self, title: &str, version: &str) -> JsonValue {
+        let mut paths: HashMap<String, JsonValue> = HashMap::new();
+        for route in self.routes() {
+            if !OPENAPI_METHODS.contains(&route.method.as_str()) {
+                continue;
+            }
+            let (path, parameter) = openapi_path(&route.uri);
+            let mut operation = HashMap::new();
+            if let Some(summary) = &route.summary {
+                operation.insert("summary".to_owned(), JsonValue::from(summary));
+            }
+            if let Some(name) = &route.name {
+                operation.insert("operationId".to_owned(), JsonValue::from(name));
+            }
+            if let Some((name, constraint)) = parameter {
+                let schema = route
+                    .param_schema
+                    .clone()
+                    .unwrap_or_else(|| constraint_schema(constraint));
+                operation.insert(
+                    "parameters".to_owned(),
+                    JsonValue::Array(vec![object(vec![
+                        ("name", JsonValue::from(name)),
+                        ("in", JsonValue::from("path")),
+                        ("required", JsonValue::Boolean(true)),
+                        ("schema", schema),
+                    ])]),
+                );
+            }
+            operation.insert(
+                "responses".to_owned(),
+                object(vec![(
+                    "default",
+                    object(vec![("description", JsonValue::from("Response"))]),
+                )]),
+            );
+            let methods = paths
+                .entry(path)
+                .or_insert_with(|| JsonValue::Object(HashMap::new()));
+            if let Some(methods) = methods.as_object_mut() {
+                methods.insert(route.method.to_lowercase(), JsonValue::Object(operation));
+            }
+        }
+        object(vec![
+            ("openapi", JsonValue::from("3.0.3")),
+            (
+                "info",
+                object(vec![
+                    ("title", JsonValue::from(title)),
+                    ("version", JsonValue::from(version)),
+                ]),
+            ),
+            ("paths", JsonValue::Object(paths)),
+        ])
+    }
+}
+
+fn object(fields: Vec<(&str, JsonValue)>) -> JsonValue {
+    JsonValue::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect(),
+    )
+}
+
+/// `/hn/:id<int>` becomes `/hn/{id}`, with the parameter and its constraint.
+fn openapi_path(uri: &str) -> (String, Option<(&str, Option<&str>)>) {
+    match uri.split_once(':') {
+        Some((prefix, parameter)) => {
+            let (name, constraint) = split_parameter(parameter);
+            (format!("{}{{{}}}", prefix, name), Some((name, constraint)))
+        }
+        None => (uri.to_owned(), None),
+    }
+}
+
+fn constraint_schema(constraint: Option<&str>) -> JsonValue {
+    let constraint = match constraint {
+        Some(constraint) => constraint,
+        None => return object(vec![("type", JsonValue::from("string"))]),
+    };
+    match Constraint::parse(constraint) {
+        Ok(Constraint::Int) => object(vec![
+            ("type", JsonValue::from("integer")),
+            ("minimum", JsonValue::from(0.0)),
+        ]),
+        Ok(Constraint::Uuid) => object(vec![
+            ("type", JsonValue::from("string")),
+            ("format", JsonValue::from("uuid")),
+        ]),
+        Ok(Constraint::Class(_)) => object(vec![
+            ("type", JsonValue::from("string")),
+            ("pattern", JsonValue::from(format!("^{}$", constraint))),
+        ]),
+        _ => object(vec![("type", JsonValue::from("string"))]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{get, post, HttpServer, Path, TestClient};
+    use super::*;
+    use crate::json::JsonParser;
+
+    fn table() -> RouteTable {
+        let urls = RouteUrls::default();
+        let table = RouteTable::new(urls.clone());
+        table.add("GET", "/hn", 0);
+        table.add("GET", "/hn/:id<int>", 1);
+        table.add("DELETE", "/hn/:id<int>", 0);
+        table.add("GET", "/tags/:tag<[a-z]+>", 0);
+        table.add("BREW", "/coffee", 0);
+        urls.add("hn_item", "GET", "/hn/:id<int>");
+        table.update("GET", "/hn/:id<int>", |route| {
+            route.summary = Some("Item of \"Hacker News\"".to_owned())
+        });
+        table
+    }
+
+    #[test]
+    fn list_routes() {
+        let routes = table().routes();
+        assert_eq!(routes.len(), 5);
+        assert_eq!(routes[1].method, "GET");
+        assert_eq!(routes[1].uri, "/hn/:id<int>");
+        assert_eq!(routes[1].name.as_deref(), Some("hn_item"));
+        assert_eq!(routes[1].middleware, 1);
+        assert_eq!(routes[0].name, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "There is no route POST /hn")]
+    fn update_missing_route() {
+        table().update("POST", "/hn", |_| {});
+    }
+
+    #[test]
+    fn openapi_document() {
+        let document = table().openapi("Moon", "1.0");
+        let paths = document.as_object().unwrap()["paths"].as_object().unwrap();
+        assert_eq!(paths.len(), 3);
+        let item = paths["/hn/{id}"].as_object().unwrap();
+        assert!(item.contains_key("delete"));
+        let get = item["get"].as_object().unwrap();
+        assert_eq!(get["summary"], JsonValue::from("Item of \"Hacker News\""));
+        assert_eq!(get["operationId"], JsonValue::from("hn_item"));
+        let parameter = &get["parameters"].as_array().unwrap()[0];
+        assert_eq!(parameter.field::<String>("name"), Ok("id".to_owned()));
+        assert_eq!(
+            parameter.as_object().unwrap()["schema"],
+            object(vec![
+                ("type", JsonValue::from("integer")),
+                ("minimum", JsonValue::from(0.0)),
+            ])
+        );
+        let tag = &paths["/tags/{tag}"].as_object().unwrap()["get"]
+            .as_object()
+            .unwrap()["parameters"]
+            .as_array()
+            .unwrap()[0];
+        assert_eq!(
+            tag.as_object().unwrap()["schema"],
+            object(vec![
+                ("type", JsonValue::from("string")),
+                ("pattern", JsonValue::from("^[a-z]+$")),
+            ])
+        );
+        assert_eq!(
+            JsonParser::new(&document.stringify()).try_parse(),
+            Ok(document)
+        );
+    }
+
+    /// Lists the items
+    #[get("/items")]
+    fn list_items() -> &'static str {
+        "items"
+    }
+
+    #[post("/items")]
+    fn add_item() -> (u16, &'static str) {
+        (201, "added")
+    }
+
+    #[get("/items/:id", name = "item")]
+    fn show_item(Path(id): Path<usize>) -> String {
+        id.to_string()
+    }
+
+    #[test]
+    fn route_table_and_openapi() {
+        let mut server = HttpServer::new(());
+        server.mount((list_items, add_item, show_item));
+        server.set_param_schema("GET", "/items/:id", JsonValue::from("custom"));
+        server.serve_openapi("/openapi.json", "Items", "1.0");
+        server.route("DELETE", "/items/:id<int>", |_, _, _| "");
+
+        let routes = server.route_table().routes();
+        let listed: Vec<(&str, &str)> = routes
+            .iter()
+            .map(|route| (route.method.as_str(), route.uri.as_str()))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("GET", "/items"),
+                ("POST", "/items"),
+                ("GET", "/items/:id"),
+                ("GET", "/openapi.json"),
+                ("DELETE", "/items/:id<int>"),
+            ]
+        );
+        assert_eq!(routes[0].summary.as_deref(), Some("Lists the items"));
+        assert_eq!(routes[0].name.as_deref(), Some("list_items"));
+        assert_eq!(routes[1].name.as_deref(), Some("add_item"));
+        assert_eq!(routes[2].name.as_deref(), Some("item"));
+
+        let mut client = TestClient::new(&server);
+        let response = client.get("/openapi.json");
+        assert_eq!(response.header("Content-Type"), Some("application/json"));
+        let document = JsonParser::new(&String::from_utf8_lossy(response.body())).parse();
+        let items = document.as_object().unwrap()["paths"].as_object().unwrap()["/items"]
+            .as_object()
+            .unwrap();
+        let operation_id = |method: &str| items[method].as_object().unwrap()["operationId"].clone();
+        assert_eq!(operation_id("get"), JsonValue::from("list_items"));
+        assert_eq!(operation_id("post"), JsonValue::from("add_item"));
+        let item = &document.as_object().unwrap()["paths"].as_object().unwrap()["/items/{id}"];
+        let get = item.as_object().unwrap()["get"].as_object().unwrap();
+        assert_eq!(get["operationId"], JsonValue::from("item"));
+        assert_eq!(
+            get["parameters"].as_array().unwrap()[0]
+                .as_object()
+                .unwrap()["schema"],
+            JsonValue::from("custom")
+        );
+        assert!(item.as_object().unwrap().contains_key("delete"));
+    }
+}
@@ -0,0 +1,208 @@
+use super::constraints::split_parameter;
+use super::url::percent_encode;
+use crate::json::JsonValue;
+use crate::templating::{HelperArguments, Helpers};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, PartialEq)]
+pub enum RouteUrlError {
+    UnknownRoute(String),
+    // route, parameter
+    MissingParameter(String, String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for RouteUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownRoute(name) => write!(f, "There is no route named {}", name),
+            Self::MissingParameter(name, parameter) => {
+                write!(f, "Route {} needs the parameter {}", name, parameter)
+            }
+            Self::InvalidValue(name) => {
+                write!(
+                    f,
+                    "Parameter {} has to be a string, a number or a boolean",
+                    name
+                )
+            }
+        }
+    }
+}
+
+impl Error for RouteUrlError {}
+
+/// Names of the routes of a router, to build their URLs instead of hardcoding them. Clones
+/// share the names, so they see routes named after they were taken.
+#[derive(Clone, Debug, Default)]
+pub struct RouteUrls {
+    // name, method and uri
+    routes: Arc<RwLock<HashMap<String, (String, String)>>>,
+}
+
+impl RouteUrls {
+    pub(super) fn add(&self, name: &str, method: &str, uri: &str) {
+        let mut routes = self.routes.write().unwrap();
+        let route = (method.to_owned(), uri.to_owned());
+        if let Some((existing_method, existing_uri)) = routes.get(name) {
+            if *existing_method != route.0 || *existing_uri != route.1 {
+                panic!(
+                    "Route name {} is already used by {} {}",
+                    name, existing_method, existing_uri
+                );
+            }
+        }
+        routes.insert(name.to_owned(), route);
+    }
+
+    pub(super) fn name_of(&self, method: &str, uri: &str) -> Option<String> {
+        let routes = self.routes.read().unwrap();
+        routes
+            .iter()
+            .find(|(_, (route_method, route_uri))| route_method == method && route_uri == uri)
+            .map(|(name, _)| name.clone())
+    }
+
+    /// URL of the route `name`, with its parameter taken from `params`. The rest of
+    /// `params` become the query string. Values are percent-encoded.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, RouteUrlError> {
+        let routes = self.routes.read().unwrap();
+        let (_, uri) = routes
+            .get(name)
+            .ok_or_else(|| RouteUrlError::UnknownRoute(name.to_owned()))?;
+        let (mut url, parameter) = match uri.split_once(':') {
+            Some((prefix, parameter)) => {
+                let (parameter, _) = split_parameter(parameter);
+                let value = params
+                    .iter()
+                    .find(|(key, _)| *key == parameter)
+                    .map(|(_, value)| value)
+                    .ok_or_else(|| {
+                        RouteUrlError::MissingParameter(name.to_owned(), parameter.to_owned())
+                    })?;
+                (
+                    format!("{}{}", prefix, percent_encode(value)),
+                    Some(parameter),
+                )
+            }
+            None => (uri.clone(), None),
+        };
+        let query: Vec<String> = params
+            .iter()
+            .filter(|(key, _)| Some(*key) != parameter)
+            .map(|(key, value)| format!("{}={}", percent_encode(key), percent_encode(value)))
+            .collect();
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        Ok(url)
+    }
+
+    /// Template helper building URLs like `url_for`, used as
+    /// `{{url_for "hn_item" id=parent}}`. Parameters whose value is null are left out.
+    pub fn helper(&self) -> impl Fn(&HelperArguments) -> Result<String, String> + Send + Sync {
+        let urls = self.clone();
+        move |arguments| {
+            let name = match arguments.positional.as_slice() {
+                [JsonValue::String(name)] => name,
+                _ => return Err("Expected the name of the route".to_owned()),
+            };
+            let mut values = Vec::new();
+            for (key, value) in &arguments.named {
+                let value = match value {
+                    JsonValue::Null => continue,
+                    JsonValue::String(value) => value.clone(),
+                    JsonValue::Number(_) | JsonValue::Boolean(_) => value.stringify(),
+                    _ => return Err(RouteUrlError::InvalidValue(key.clone()).to_string()),
+                };
+                values.push((key.as_str(), value));
+            }
+            let params: Vec<(&str, &str)> = values
+                .iter()
+                .map(|(key, value)| (*key, value.as_str()))
+                .collect();
+            urls.url_for(name, &params).map_err(|err| err.to_string())
+        }
+    }
+
+    /// Template helpers with `url_for`.
+    pub fn helpers(&self) -> Helpers {
+        let mut helpers = Helpers::new();
+        helpers.add("url_for", self.helper());
+        helpers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templating::render_with_helpers;
+
+    fn urls() -> RouteUrls {
+        let urls = RouteUrls::default();
+        urls.add("hn", "GET", "/hn");
+        urls.add("hn_item", "GET", "/hn/:id<int>");
+        urls.add("add_hn", "POST", "/hn");
+        urls
+    }
+
+    #[test]
+    fn build_urls() {
+        let urls = urls();
+        assert_eq!(urls.url_for("hn", &[]).unwrap(), "/hn");
+        assert_eq!(
+            urls.url_for("hn", &[("page", "2"), ("q", "moon & web")])
+                .unwrap(),
+            "/hn?page=2&q=moon%20%26%20web"
+        );
+        assert_eq!(
+            urls.url_for("hn_item", &[("id", "a/b"), ("sort", "new")])
+                .unwrap(),
+            "/hn/a%2Fb?sort=new"
+        );
+        assert_eq!(
+            urls.url_for("missing", &[]),
+            Err(RouteUrlError::UnknownRoute("missing".to_owned()))
+        );
+        assert_eq!(
+            urls.url_for("hn_item", &[("page", "2")])
+                .unwrap_err()
+                .to_string(),
+            "Route hn_item needs the parameter id"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Route name hn is already used by GET /hn")]
+    fn unique_names() {
+        urls().add("hn", "POST", "/hn");
+    }
+
+    #[test]
+    fn names_by_method() {
+        let urls = urls();
+        assert_eq!(urls.name_of("GET", "/hn").as_deref(), Some("hn"));
+        assert_eq!(urls.name_of("POST", "/hn").as_deref(), Some("add_hn"));
+        assert_eq!(urls.name_of("PUT", "/hn"), None);
+        assert_eq!(urls.url_for("add_hn", &[]).unwrap(), "/hn");
+    }
+
+    #[test]
+    fn template_helper() {
+        let urls = urls();
+        let context = JsonValue::Object(
+            vec![("parent".to_owned(), JsonValue::Number(42.0))]
+                .into_iter()
+                .collect(),
+        );
+        let template = "{{&url_for \"hn_item\" id=parent}} {{&url_for \"hn\" page=missing}}";
+        assert_eq!(
+            render_with_helpers(template, &context, &HashMap::new(), &urls.helpers()),
+            "/hn/42 /hn"
+        );
+    }
+}
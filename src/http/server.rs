@@ -1,19 +1,49 @@
 use super::super::thread_pool::ThreadPool;
+use super::super::tls::{self, TlsConfig};
+use super::connection::Connection;
+use super::constraints::{split_parameter, Constraint, ParamPredicate};
+use super::cors::Cors;
+use super::error::{self, HttpError, IntoResponse};
+use super::forwarded::TrustedProxies;
+use super::http2::{self, Http2Connection};
+use super::metrics::Metrics;
+use super::route_table::RouteTable;
+use super::route_urls::RouteUrls;
+use super::sse::{self, EventStream};
+#[cfg(unix)]
+use super::unix_socket::UnixSocket;
+use super::virtual_host::VirtualHosts;
+use super::websocket::{self, WebSocket};
 use super::HttpParser;
 use super::HttpResponse;
+use crate::json::JsonValue;
+use std::collections::HashMap;
 use std::env;
-use std::io::prelude::*;
 use std::net::TcpListener;
-use std::net::TcpStream;
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(unix)]
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::thread;
 
 use super::HttpRequest;
 
-type State<T> = Arc<Mutex<T>>;
-type RouteHandler<T> = dyn Fn(&HttpRequest, &mut HttpResponse, State<T>) -> () + Send + Sync;
-type Middleware<T> = dyn Fn(&HttpRequest, &mut HttpResponse, State<T>) -> bool + Send + Sync;
+pub type State<T> = Arc<Mutex<T>>;
+pub type RouteHandler<T> = dyn Fn(&HttpRequest, &mut HttpResponse, State<T>) -> () + Send + Sync;
+/// Runs before the handler and can change the request, like adding `extensions` for it.
+/// Returning `false` answers with the response as it is, without calling the handler.
+pub type Middleware<T> =
+    dyn Fn(&mut HttpRequest, &mut HttpResponse, State<T>) -> bool + Send + Sync;
+pub type EventStreamHandler<T> = dyn Fn(&HttpRequest, EventStream, State<T>) + Send + Sync;
+pub type WebSocketHandler<T> = dyn Fn(&HttpRequest, WebSocket, State<T>) + Send + Sync;
+/// Runs once the response is built, for every request, including the ones without a route.
+pub type AfterMiddleware<T> = dyn Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync;
+/// Builds the response for an error returned by a handler, or for a request without a route.
+pub type ErrorHandler<T> =
+    dyn Fn(&HttpRequest, &HttpError, &mut HttpResponse, State<T>) + Send + Sync;
 
 pub struct Route<T> {
+    /// `*` answers every method, like proxies forwarding all of them.
     pub method: String,
     pub uri: String,
     pub middleware: Arc<Vec<Box<Middleware<T>>>>,
@@ -36,6 +66,30 @@ impl<T> Route<T> {
         uri.get(colon_position + 1..).is_some()
     }
 
+    // The constraint is checked against the parameter without the query string
+    fn satisfies_constraint(
+        &self,
+        uri: &str,
+        constraint: &Constraint,
+        custom: &HashMap<String, Box<ParamPredicate>>,
+    ) -> bool {
+        let colon_position = match self.uri.find(':') {
+            Some(position) => position,
+            None => return true,
+        };
+        let value = uri
+            .get(colon_position..)
+            .unwrap_or("")
+            .split('?')
+            .next()
+            .unwrap_or("");
+        constraint.matches(value, custom)
+    }
+
+    fn accepts_method(&self, method: &str) -> bool {
+        self.method == method || self.method == "*" || (method == "HEAD" && self.method == "GET")
+    }
+
     pub fn add_params(&self, request: &mut HttpRequest) {
         let colon_position = match self.uri.find(':') {
             None => {
@@ -45,11 +99,12 @@ impl<T> Route<T> {
         };
 
         let route_before_color = self.uri.get(..colon_position).unwrap();
-        if !request.uri.starts_with(route_before_color) {
+        let path = request.uri.split('?').next().unwrap_or("");
+        if !path.starts_with(route_before_color) {
             return;
         }
-        let param_key = self.uri.get(colon_position + 1..).unwrap();
-        let param_value = match request.uri.get(colon_position..) {
+        let (param_key, _) = split_parameter(self.uri.get(colon_position + 1..).unwrap());
+        let param_value = match path.get(colon_position..) {
             None => return,
             Some(value) => value,
         };
@@ -59,10 +114,332 @@ impl<T> Route<T> {
     }
 }
 
-fn send_response(stream: &mut TcpStream, response: HttpResponse) {
-    let response = response.to_string();
+/// Handler declared with a route attribute like `#[get("/hn/:id")]`, ready to be added to a
+/// router with `mount`. Tuples of endpoints mount all of them.
+pub trait Endpoint<T> {
+    fn mount(self, router: &mut Router<T>);
+}
+
+macro_rules! impl_endpoint {
+    ($($endpoint:ident),+) => {
+        impl<T, $($endpoint: Endpoint<T>,)+> Endpoint<T> for ($($endpoint,)+) {
+            #[allow(non_snake_case)]
+            fn mount(self, router: &mut Router<T>) {
+                let ($($endpoint,)+) = self;
+                $($endpoint.mount(router);)+
+            }
+        }
+    };
+}
+
+impl_endpoint!(A);
+impl_endpoint!(A, B);
+impl_endpoint!(A, B, C);
+impl_endpoint!(A, B, C, D);
+impl_endpoint!(A, B, C, D, E);
+impl_endpoint!(A, B, C, D, E, F);
+impl_endpoint!(A, B, C, D, E, F, G);
+impl_endpoint!(A, B, C, D, E, F, G, H);
+impl_endpoint!(A, B, C, D, E, F, G, H, I);
+impl_endpoint!(A, B, C, D, E, F, G, H, I, J);
+impl_endpoint!(A, B, C, D, E, F, G, H, I, J, K);
+impl_endpoint!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+/// Routes and after middleware of a site. `HttpServer` has one for requests without a
+/// virtual host of their own, more can be added with `HttpServer::host`.
+pub struct Router<T> {
+    routes: Vec<Route<T>>,
+    before_middleware: Vec<Box<Middleware<T>>>,
+    after_middleware: Vec<Box<AfterMiddleware<T>>>,
+    fallback: Option<Box<RouteHandler<T>>>,
+    error_handlers: HashMap<u16, Box<ErrorHandler<T>>>,
+    default_error_handler: Option<Box<ErrorHandler<T>>>,
+    urls: RouteUrls,
+    table: RouteTable,
+    param_constraints: HashMap<String, Box<ParamPredicate>>,
+    // parsed constraints of the routes, by pattern
+    route_constraints: HashMap<String, Constraint>,
+}
+
+impl<T: Send + 'static> Router<T> {
+    pub fn new() -> Self {
+        let urls = RouteUrls::default();
+        Router {
+            routes: Vec::new(),
+            before_middleware: Vec::new(),
+            after_middleware: Vec::new(),
+            fallback: None,
+            error_handlers: HashMap::new(),
+            default_error_handler: None,
+            table: RouteTable::new(urls.clone()),
+            urls,
+            param_constraints: HashMap::new(),
+            route_constraints: HashMap::new(),
+        }
+    }
+
+    pub fn routes(&self) -> &[Route<T>] {
+        &self.routes
+    }
+
+    /// Panics if the parameter of the route has an invalid constraint, or a custom one that
+    /// wasn't registered with `set_param_constraint` yet.
+    pub fn add_route(&mut self, route: Route<T>) {
+        if let Some((_, parameter)) = route.uri.split_once(':') {
+            if let (_, Some(constraint)) = split_parameter(parameter) {
+                let constraint = match Constraint::parse(constraint) {
+                    Ok(constraint) => constraint,
+                    Err(err) => panic!("Invalid route {}: {}", route.uri, err),
+                };
+                if let Constraint::Custom(name) = &constraint {
+                    if !self.param_constraints.contains_key(name) {
+                        panic!(
+                            "Invalid route {}: Unknown parameter constraint <{}>",
+                            route.uri, name
+                        );
+                    }
+                }
+                self.route_constraints.insert(route.uri.clone(), constraint);
+            }
+        }
+        self.table
+            .add(&route.method, &route.uri, route.middleware.len());
+        self.routes.push(route);
+    }
+
+    /// Registers a constraint for route parameters, used like `/users/:name<username>`.
+    /// Requests whose parameter doesn't satisfy it don't match the route. It has to be
+    /// registered before the routes using it.
+    pub fn set_param_constraint<F>(&mut self, name: &str, predicate: F)
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.param_constraints
+            .insert(name.to_owned(), Box::new(predicate));
+    }
+
+    /// Adds the routes of handlers declared with `#[get]`, `#[post]` and the other route
+    /// attributes: `router.mount((index, item, new_item))`.
+    pub fn mount<E: Endpoint<T>>(&mut self, endpoint: E) {
+        endpoint.mount(self);
+    }
+
+    /// Names the route for `method` and `uri`, so links to it can be built with
+    /// `RouteUrls::url_for` or the `url_for` template helper. Panics if the name is taken by
+    /// another route.
+    pub fn set_route_name(&mut self, name: &str, method: &str, uri: &str) {
+        self.urls.add(name, method, uri);
+    }
+
+    /// Named routes of the router. Handlers get them with `HttpRequest::urls`.
+    pub fn urls(&self) -> RouteUrls {
+        self.urls.clone()
+    }
+
+    /// Method, pattern, name and middleware count of the routes, see `serve_openapi` to
+    /// publish them.
+    pub fn route_table(&self) -> RouteTable {
+        self.table.clone()
+    }
+
+    /// Summary of the route in the OpenAPI document. Routes declared with an attribute
+    /// take the first line of their doc comment. Panics if there is no such route.
+    pub fn set_route_summary(&mut self, method: &str, uri: &str, summary: &str) {
+        self.table.update(method, uri, |route| {
+            route.summary = Some(summary.to_owned())
+        });
+    }
+
+    /// Schema of the route parameter in the OpenAPI document, instead of the one derived
+    /// from its constraint. Panics if there is no such route.
+    pub fn set_param_schema(&mut self, method: &str, uri: &str, schema: JsonValue) {
+        self.table
+            .update(method, uri, |route| route.param_schema = Some(schema));
+    }
+
+    /// Serves the OpenAPI document of the router at `uri`. It is built on every request, so
+    /// it includes the routes added later.
+    pub fn serve_openapi(&mut self, uri: &str, title: &str, version: &str) {
+        let table = self.route_table();
+        let title = title.to_owned();
+        let version = version.to_owned();
+        self.add_route(Route {
+            uri: uri.to_owned(),
+            method: "GET".to_owned(),
+            middleware: Arc::new(Vec::new()),
+            handler: Arc::new(move |_, response, _| {
+                response.set_header("Content-Type", "application/json".to_owned());
+                response.set_body(table.openapi(&title, &version).stringify());
+            }),
+        });
+    }
+
+    pub fn get(&mut self, uri: &str, handler: &'static RouteHandler<T>) {
+        self.add_route(Route {
+            uri: uri.to_owned(),
+            method: "GET".to_owned(),
+            middleware: Arc::new(Vec::new()),
+            handler: Arc::new(handler),
+        });
+    }
+
+    /// Registers a route whose handler returns something else than `()`, like
+    /// `Result<(), HttpError>` so it can fail with `?`. Errors are rendered as JSON or HTML
+    /// depending on the `Accept` header of the request.
+    pub fn route<F, R>(&mut self, method: &str, uri: &str, handler: F)
+    where
+        F: Fn(&HttpRequest, &mut HttpResponse, State<T>) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.add_route(Route {
+            uri: uri.to_owned(),
+            method: method.to_owned(),
+            middleware: Arc::new(Vec::new()),
+            handler: Arc::new(move |request, response, state| {
+                handler(request, response, state).into_response(response);
+            }),
+        });
+    }
+
+    /// Registers a route that accepts WebSocket handshakes. Once the `101` response is sent the
+    /// connection is handed to `handler` on its own thread, so long lived sockets don't hold
+    /// on to the workers of the pool.
+    pub fn websocket<F>(&mut self, uri: &str, handler: F)
+    where
+        F: Fn(&HttpRequest, WebSocket, State<T>) + Send + Sync + 'static,
+    {
+        let handler: Arc<WebSocketHandler<T>> = Arc::new(handler);
+        self.add_route(Route {
+            uri: uri.to_owned(),
+            method: "GET".to_owned(),
+            middleware: Arc::new(Vec::new()),
+            handler: Arc::new(move |request, response, state| {
+                if !websocket::handshake(request, response) {
+                    return;
+                }
+                let handler = Arc::clone(&handler);
+                let request = request.clone();
+                response.set_upgrade(move |connection| {
+                    handler(&request, WebSocket::new(connection), state);
+                });
+            }),
+        });
+    }
+
+    /// Registers a route that answers with a `text/event-stream` response kept open after the
+    /// head is sent. Like WebSockets, `handler` runs on its own thread and owns the stream
+    /// until it returns.
+    pub fn event_stream<F>(&mut self, uri: &str, handler: F)
+    where
+        F: Fn(&HttpRequest, EventStream, State<T>) + Send + Sync + 'static,
+    {
+        let handler: Arc<EventStreamHandler<T>> = Arc::new(handler);
+        self.add_route(Route {
+            uri: uri.to_owned(),
+            method: "GET".to_owned(),
+            middleware: Arc::new(Vec::new()),
+            handler: Arc::new(move |request, response, state| {
+                sse::set_event_stream_headers(response);
+                let handler = Arc::clone(&handler);
+                let request = request.clone();
+                let last_event_id = request.header("Last-Event-ID").map(|id| id.to_owned());
+                response.set_upgrade(move |connection| {
+                    handler(&request, EventStream::new(connection, last_event_id), state);
+                });
+            }),
+        });
+    }
+
+    /// Registers a middleware that runs before the routes are matched, for every request,
+    /// in registration order. Returning `false` skips the route and the middleware after it.
+    pub fn before<F>(&mut self, middleware: F)
+    where
+        F: Fn(&mut HttpRequest, &mut HttpResponse, State<T>) -> bool + Send + Sync + 'static,
+    {
+        self.before_middleware.push(Box::new(middleware));
+    }
+
+    /// Answers CORS preflights and adds the CORS headers to every response. Register it
+    /// before the middleware that can reject requests, so preflights get through.
+    pub fn set_cors(&mut self, cors: Cors) {
+        self.before(cors.preflight());
+        self.after(cors.middleware());
+    }
+
+    /// Registers a middleware that runs after the route handler, in registration order.
+    pub fn after<F>(&mut self, middleware: F)
+    where
+        F: Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync + 'static,
+    {
+        self.after_middleware.push(Box::new(middleware));
+    }
+
+    /// Handles requests no route matches, instead of the 404 error.
+    pub fn set_fallback<F, R>(&mut self, handler: F)
+    where
+        F: Fn(&HttpRequest, &mut HttpResponse, State<T>) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.fallback = Some(Box::new(move |request, response, state| {
+            handler(request, response, state).into_response(response);
+        }));
+    }
+
+    /// Renders errors with `status_code`, like the 404 of requests without a route. See
+    /// `error_page` for a handler rendering a template.
+    pub fn set_error_handler<F>(&mut self, status_code: u16, handler: F)
+    where
+        F: Fn(&HttpRequest, &HttpError, &mut HttpResponse, State<T>) + Send + Sync + 'static,
+    {
+        self.error_handlers.insert(status_code, Box::new(handler));
+    }
+
+    /// Renders errors whose status has no handler of its own.
+    pub fn set_default_error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&HttpRequest, &HttpError, &mut HttpResponse, State<T>) + Send + Sync + 'static,
+    {
+        self.default_error_handler = Some(Box::new(handler));
+    }
+
+    fn render_error(
+        &self,
+        request: &HttpRequest,
+        error: HttpError,
+        response: &mut HttpResponse,
+        state: State<T>,
+        dev_mode: bool,
+    ) {
+        if error.status_code() >= 500 {
+            eprintln!("{} {}: {}", request.method, request.uri, error);
+            if dev_mode {
+                return error.render_details(request, response);
+            }
+        }
+        let handler = self
+            .error_handlers
+            .get(&error.status_code())
+            .or(self.default_error_handler.as_ref());
+        match handler {
+            Some(handler) => {
+                response.set_status_code(error.status_code());
+                handler(request, &error, response, state);
+            }
+            None => error.render(request, response),
+        }
+    }
+}
+
+impl<T: Send + 'static> Default for Router<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn send_response(stream: &mut dyn Connection, response: HttpResponse) {
+    let response = response.to_bytes();
     // println!("response: {:?}", response);
-    match stream.write(response.as_bytes()) {
+    match stream.write_all(&response) {
         Ok(_) => {}
         Err(err) => {
             eprintln!("Error writing to TCP socket: {}", err);
@@ -76,16 +453,293 @@ fn send_response(stream: &mut TcpStream, response: HttpResponse) {
     };
 }
 
+fn handle_request<T: Send + 'static>(
+    router: &Router<T>,
+    state: State<T>,
+    request: &mut HttpRequest,
+    dev_mode: bool,
+) -> HttpResponse {
+    request.extensions.insert(router.urls());
+    let mut response = HttpResponse::new();
+    // A panicking handler gets a 500 instead of taking the worker thread down with it
+    let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+        for middleware in &router.before_middleware {
+            if !middleware(request, &mut response, state.clone()) {
+                return;
+            }
+        }
+        let matching = router.routes.iter().filter(|route| {
+            route.matches_uri(&request.uri)
+                && match router.route_constraints.get(&route.uri) {
+                    Some(constraint) => route.satisfies_constraint(
+                        &request.uri,
+                        constraint,
+                        &router.param_constraints,
+                    ),
+                    None => true,
+                }
+        });
+        let found_route = matching
+            .clone()
+            .rfind(|route| route.accepts_method(&request.method));
+
+        match found_route {
+            Some(route) => {
+                route.add_params(request);
+                request.route = Some(route.uri.clone());
+                for middleware in route.middleware.iter() {
+                    if !middleware(request, &mut response, state.clone()) {
+                        return;
+                    }
+                }
+                (route.handler)(request, &mut response, state.clone());
+            }
+            // Routes for the uri but not for the method answer 405 with the ones they take
+            None if matching.clone().next().is_some() => {
+                let mut allowed: Vec<&str> = Vec::new();
+                for route in matching {
+                    let methods = if route.method == "GET" {
+                        vec!["GET", "HEAD"]
+                    } else {
+                        vec![route.method.as_str()]
+                    };
+                    for method in methods {
+                        if !allowed.contains(&method) {
+                            allowed.push(method);
+                        }
+                    }
+                }
+                response.set_header("Allow", allowed.join(", "));
+                response.set_error(HttpError::new(405, "Method not allowed"));
+            }
+            None => match &router.fallback {
+                Some(fallback) => fallback(request, &mut response, state.clone()),
+                None => response.set_error(HttpError::not_found("Not found")),
+            },
+        }
+    }));
+    if let Err(payload) = handled {
+        response = HttpResponse::new();
+        response.set_error(HttpError::from_panic(payload));
+    }
+    if let Some(error) = response.take_error() {
+        router.render_error(request, error, &mut response, state.clone(), dev_mode);
+    }
+
+    for middleware in &router.after_middleware {
+        middleware(request, &mut response, state.clone());
+    }
+    // HEAD runs the GET route, the client gets the headers of its response without the body
+    if request.method == "HEAD" && response.upgrade.is_none() {
+        let has_body = response.status_code() != 204 && response.status_code() != 304;
+        if has_body && response.header("Content-Length").is_none() {
+            response.set_header("Content-Length", response.body().len().to_string());
+        }
+        response.set_body_bytes(Vec::new());
+    }
+
+    response
+}
+
+const DEFAULT_TLS_PORT: &str = "7443";
+
+/// What the plain HTTP port does once TLS is set up.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PlainHttp {
+    /// Serve the same routes over HTTP and HTTPS.
+    Serve,
+    /// Answer every request with a permanent redirect to the HTTPS port.
+    RedirectToHttps,
+    /// Only listen for HTTPS.
+    Disabled,
+}
+
+const REQUEST_BUFFER_SIZE: usize = 8192;
+
+fn read_bytes(connection: &mut dyn Connection) -> Option<Vec<u8>> {
+    let mut buffer = [0; REQUEST_BUFFER_SIZE];
+    let read_result = connection.read(&mut buffer);
+    if read_result.is_err() {
+        return None;
+    }
+    let bytes_read = read_result.unwrap();
+    if bytes_read == 0 {
+        return None;
+    }
+    Some(buffer[..bytes_read].to_vec())
+}
+
+fn parse_request(connection: &mut dyn Connection, bytes: &[u8]) -> Option<HttpRequest> {
+    if bytes.len() >= REQUEST_BUFFER_SIZE {
+        return None;
+    }
+    let raw_request = String::from_utf8_lossy(bytes).replace('\0', "");
+    // println!("raw_request: {:?}", raw_request);
+
+    match HttpParser::new(&raw_request).parse_request() {
+        Ok(request) => Some(request),
+        Err(err) => {
+            eprintln!("{}", err);
+            send_response(
+                connection,
+                HttpResponse::bad_request("Error parsing http request"),
+            );
+            None
+        }
+    }
+}
+
+fn read_request(connection: &mut dyn Connection) -> Option<HttpRequest> {
+    let bytes = read_bytes(connection)?;
+    parse_request(connection, &bytes)
+}
+
+fn redirect_to_https(mut connection: Box<dyn Connection>, tls_port: &str) {
+    let request = match read_request(connection.as_mut()) {
+        Some(request) => request,
+        None => return,
+    };
+    let host = match request.header("Host") {
+        // IPv6 literals have colons of their own, the port comes after the bracket
+        Some(host) => match host.rsplit_once(':') {
+            Some((name, _)) if !host.contains('[') || name.ends_with(']') => name,
+            _ => host,
+        },
+        None => {
+            send_response(
+                connection.as_mut(),
+                HttpResponse::bad_request("Missing Host header"),
+            );
+            return;
+        }
+    };
+    let port = if tls_port == "443" {
+        String::new()
+    } else {
+        format!(":{}", tls_port)
+    };
+
+    let mut response = HttpResponse::new();
+    // 308 keeps the method and the body, browsers only follow 301 with a GET
+    let status_code = if request.method == "GET" || request.method == "HEAD" {
+        301
+    } else {
+        308
+    };
+    response.set_status_code(status_code);
+    response.set_header(
+        "Location",
+        format!("https://{}{}{}", host, port, request.uri),
+    );
+    send_response(connection.as_mut(), response);
+}
+
+/// Everything a worker needs to turn a connection into a response.
+pub(super) struct Dispatcher<T> {
+    hosts: Arc<VirtualHosts<T>>,
+    state: State<T>,
+    dev_mode: bool,
+    trusted_proxies: Arc<TrustedProxies>,
+    metrics: Option<Metrics>,
+}
+
+impl<T> Clone for Dispatcher<T> {
+    fn clone(&self) -> Self {
+        Dispatcher {
+            hosts: Arc::clone(&self.hosts),
+            state: Arc::clone(&self.state),
+            dev_mode: self.dev_mode,
+            trusted_proxies: Arc::clone(&self.trusted_proxies),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> Dispatcher<T> {
+    pub(super) fn handle(&self, mut request: HttpRequest) -> HttpResponse {
+        let _in_flight = self.metrics.as_ref().map(Metrics::start_request);
+        request.client_ip = self.trusted_proxies.client_ip(&request);
+        let router = self.hosts.router(request.header("Host"));
+        let response = handle_request(router, Arc::clone(&self.state), &mut request, self.dev_mode);
+        if let Some(metrics) = &self.metrics {
+            metrics.record(
+                request.route.as_deref(),
+                &request.method,
+                response.status_code(),
+                request.received_at.elapsed(),
+            );
+        }
+        response
+    }
+
+    fn http2_handler(&self) -> http2::Handler {
+        let dispatcher = self.clone();
+        Arc::new(move |request| dispatcher.handle(request))
+    }
+
+    pub(super) fn serve(&self, mut connection: Box<dyn Connection>) {
+        let bytes = match read_bytes(connection.as_mut()) {
+            Some(bytes) => bytes,
+            None => return,
+        };
+        // HTTP/2 connections live on their own thread, like upgraded responses
+        if bytes.starts_with(&http2::PREFACE[..14]) {
+            let http2 = Http2Connection::new(connection, bytes, self.http2_handler());
+            thread::spawn(move || http2.run());
+            return;
+        }
+        let mut request = match parse_request(connection.as_mut(), &bytes) {
+            Some(request) => request,
+            None => return,
+        };
+        request.set_connection(connection.as_ref());
+        if let Some(settings) = http2::upgrade_settings(&request) {
+            let mut response = HttpResponse::new();
+            response.set_status_code(101);
+            response.set_header("Connection", "Upgrade".to_owned());
+            response.set_header("Upgrade", "h2c".to_owned());
+            send_response(connection.as_mut(), response);
+            let mut http2 = Http2Connection::new(connection, Vec::new(), self.http2_handler());
+            if http2.upgraded(&settings, request).is_ok() {
+                thread::spawn(move || http2.run());
+            }
+            return;
+        }
+
+        let mut result = self.handle(request);
+        let upgrade = result.take_upgrade();
+
+        send_response(connection.as_mut(), result);
+        if let Some(upgrade) = upgrade {
+            thread::spawn(move || upgrade.run(connection));
+        }
+    }
+}
+
 pub struct HttpServer<T: Send + Sync + 'static> {
-    routes: Arc<Vec<Route<T>>>,
+    hosts: Arc<VirtualHosts<T>>,
     state: Arc<Mutex<T>>,
+    tls: Option<Arc<TlsConfig>>,
+    plain_http: PlainHttp,
+    #[cfg(unix)]
+    unix_socket: Option<UnixSocket>,
+    dev_mode: bool,
+    trusted_proxies: Arc<TrustedProxies>,
+    metrics: Option<Metrics>,
 }
 
 impl<T: Send + Sync> HttpServer<T> {
     pub fn new(state: T) -> HttpServer<T> {
         HttpServer {
-            routes: Arc::new(Vec::new()),
+            hosts: Arc::new(VirtualHosts::new(Router::new())),
             state: Arc::new(Mutex::new(state)),
+            tls: None,
+            plain_http: PlainHttp::Serve,
+            #[cfg(unix)]
+            unix_socket: None,
+            dev_mode: false,
+            trusted_proxies: Arc::default(),
+            metrics: None,
         }
     }
 
@@ -93,111 +747,348 @@ impl<T: Send + Sync> HttpServer<T> {
         Arc::clone(&self.state)
     }
 
+    /// Router for requests that don't match any virtual host.
+    pub fn router(&mut self) -> &mut Router<T> {
+        &mut Arc::get_mut(&mut self.hosts).unwrap().default
+    }
+
+    /// Serves `router` to requests whose `Host` header matches `pattern`, like
+    /// `api.example.com` or `*.example.com`. Every other request goes to the routes added to
+    /// the server itself.
+    pub fn host(&mut self, pattern: &str, router: Router<T>) {
+        Arc::get_mut(&mut self.hosts).unwrap().add(pattern, router);
+    }
+
     pub fn add_route(&mut self, route: Route<T>) {
-        Arc::get_mut(&mut self.routes).unwrap().push(route);
+        self.router().add_route(route);
+    }
+
+    /// See `Router::mount`.
+    pub fn mount<E: Endpoint<T>>(&mut self, endpoint: E) {
+        self.router().mount(endpoint);
     }
 
     pub fn get(&mut self, uri: &str, handler: &'static RouteHandler<T>) {
-        let route = Route {
+        self.router().get(uri, handler);
+    }
+
+    /// See `Router::set_route_name`.
+    pub fn set_route_name(&mut self, name: &str, method: &str, uri: &str) {
+        self.router().set_route_name(name, method, uri);
+    }
+
+    /// Named routes of the server, not the ones of its virtual hosts.
+    pub fn urls(&self) -> RouteUrls {
+        self.hosts.default.urls()
+    }
+
+    /// Routes of the server, not the ones of its virtual hosts.
+    pub fn route_table(&self) -> RouteTable {
+        self.hosts.default.route_table()
+    }
+
+    /// See `Router::set_route_summary`.
+    pub fn set_route_summary(&mut self, method: &str, uri: &str, summary: &str) {
+        self.router().set_route_summary(method, uri, summary);
+    }
+
+    /// See `Router::set_param_schema`.
+    pub fn set_param_schema(&mut self, method: &str, uri: &str, schema: JsonValue) {
+        self.router().set_param_schema(method, uri, schema);
+    }
+
+    /// See `Router::serve_openapi`.
+    pub fn serve_openapi(&mut self, uri: &str, title: &str, version: &str) {
+        self.router().serve_openapi(uri, title, version);
+    }
+
+    /// See `Router::set_param_constraint`.
+    pub fn set_param_constraint<F>(&mut self, name: &str, predicate: F)
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.router().set_param_constraint(name, predicate);
+    }
+
+    /// See `Router::before`.
+    pub fn before<F>(&mut self, middleware: F)
+    where
+        F: Fn(&mut HttpRequest, &mut HttpResponse, State<T>) -> bool + Send + Sync + 'static,
+    {
+        self.router().before(middleware);
+    }
+
+    /// See `Router::set_cors`.
+    pub fn set_cors(&mut self, cors: Cors) {
+        self.router().set_cors(cors);
+    }
+
+    /// See `Router::route`.
+    pub fn route<F, R>(&mut self, method: &str, uri: &str, handler: F)
+    where
+        F: Fn(&HttpRequest, &mut HttpResponse, State<T>) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router().route(method, uri, handler);
+    }
+
+    /// See `Router::websocket`.
+    pub fn websocket<F>(&mut self, uri: &str, handler: F)
+    where
+        F: Fn(&HttpRequest, WebSocket, State<T>) + Send + Sync + 'static,
+    {
+        self.router().websocket(uri, handler);
+    }
+
+    /// See `Router::event_stream`.
+    pub fn event_stream<F>(&mut self, uri: &str, handler: F)
+    where
+        F: Fn(&HttpRequest, EventStream, State<T>) + Send + Sync + 'static,
+    {
+        self.router().event_stream(uri, handler);
+    }
+
+    /// See `Router::after`.
+    pub fn after<F>(&mut self, middleware: F)
+    where
+        F: Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync + 'static,
+    {
+        self.router().after(middleware);
+    }
+
+    /// See `Router::set_fallback`.
+    pub fn set_fallback<F, R>(&mut self, handler: F)
+    where
+        F: Fn(&HttpRequest, &mut HttpResponse, State<T>) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        self.router().set_fallback(handler);
+    }
+
+    /// See `Router::set_error_handler`.
+    pub fn set_error_handler<F>(&mut self, status_code: u16, handler: F)
+    where
+        F: Fn(&HttpRequest, &HttpError, &mut HttpResponse, State<T>) + Send + Sync + 'static,
+    {
+        self.router().set_error_handler(status_code, handler);
+    }
+
+    /// See `Router::set_default_error_handler`.
+    pub fn set_default_error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&HttpRequest, &HttpError, &mut HttpResponse, State<T>) + Send + Sync + 'static,
+    {
+        self.router().set_default_error_handler(handler);
+    }
+
+    /// Answers server errors, including panics, with a page showing the error, its sources
+    /// and the backtrace of the panic. Never turn it on in production.
+    pub fn set_dev_mode(&mut self, dev_mode: bool) {
+        if dev_mode {
+            error::capture_backtraces();
+        }
+        self.dev_mode = dev_mode;
+    }
+
+    /// Addresses and networks of the proxies in front of the server, like `10.0.0.0/8`.
+    /// Requests from them get the client address from their `Forwarded` or
+    /// `X-Forwarded-For` headers in `HttpRequest::client_ip`. Panics on invalid networks.
+    pub fn set_trusted_proxies(&mut self, networks: &[&str]) {
+        let proxies = TrustedProxies::new(networks).unwrap_or_else(|err| panic!("{}", err));
+        self.trusted_proxies = Arc::new(proxies);
+    }
+
+    /// Collects request counts and latencies, along with the load of the thread pool, and
+    /// serves them at `uri` in the Prometheus text format.
+    pub fn serve_metrics(&mut self, uri: &str) {
+        let metrics = self.metrics.get_or_insert_with(Metrics::new).clone();
+        self.router().add_route(Route {
             uri: uri.to_owned(),
             method: "GET".to_owned(),
             middleware: Arc::new(Vec::new()),
-            handler: Arc::new(handler),
-        };
-        Arc::get_mut(&mut self.routes).unwrap().push(route);
+            handler: Arc::new(move |_, response, _| {
+                response.set_header(
+                    "Content-Type",
+                    "text/plain; version=0.0.4; charset=utf-8".to_owned(),
+                );
+                response.set_body(metrics.render());
+            }),
+        });
     }
 
+    /// Metrics of the server, once `serve_metrics` turned them on.
+    pub fn metrics(&self) -> Option<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Serves HTTPS on `TLS_PORT` (7443 by default) with `config`. `plain_http` says what
+    /// happens on the plain HTTP port.
+    pub fn set_tls(&mut self, config: TlsConfig, plain_http: PlainHttp) {
+        self.tls = Some(Arc::new(config));
+        self.plain_http = plain_http;
+    }
+
+    /// Listens on a Unix domain socket at `path` instead of the plain HTTP port, with `mode`
+    /// as the file permissions of the socket (like `0o660`). A stale socket at `path` is
+    /// replaced.
+    #[cfg(unix)]
+    pub fn set_unix_socket<P: AsRef<Path>>(&mut self, path: P, mode: u32) {
+        self.unix_socket = Some(UnixSocket {
+            path: path.as_ref().to_owned(),
+            mode,
+        });
+    }
+
+    pub(super) fn dispatcher(&self) -> Dispatcher<T> {
+        Dispatcher {
+            hosts: Arc::clone(&self.hosts),
+            state: Arc::clone(&self.state),
+            dev_mode: self.dev_mode,
+            trusted_proxies: Arc::clone(&self.trusted_proxies),
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    fn start_tls(&self, config: Arc<TlsConfig>, pool: Arc<ThreadPool>) -> thread::JoinHandle<()> {
+        let port = env::var("TLS_PORT").unwrap_or_else(|_| DEFAULT_TLS_PORT.to_owned());
+        let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).unwrap();
+        let dispatcher = self.dispatcher();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        eprintln!("Error opening TCP stream: {}", err);
+                        continue;
+                    }
+                };
+                let config = Arc::clone(&config);
+                let dispatcher = dispatcher.clone();
+
+                pool.execute(move || match tls::accept(stream, &config) {
+                    Ok(stream) => dispatcher.serve(Box::new(stream)),
+                    Err(err) => eprintln!("TLS handshake failed: {}", err),
+                });
+            }
+        })
+    }
+
+    #[cfg(unix)]
+    fn start_unix_socket(
+        &self,
+        unix_socket: &UnixSocket,
+        pool: Arc<ThreadPool>,
+    ) -> thread::JoinHandle<()> {
+        let listener = unix_socket.bind().unwrap();
+        let dispatcher = self.dispatcher();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        eprintln!("Error opening Unix socket stream: {}", err);
+                        continue;
+                    }
+                };
+                let dispatcher = dispatcher.clone();
+                pool.execute(move || dispatcher.serve(Box::new(stream)));
+            }
+        })
+    }
+
+    /// Listens on the TLS port, the Unix socket and the plain HTTP port that are set up,
+    /// each on its own thread, until they all stop.
     pub fn start(&self) {
+        let pool = Arc::new(ThreadPool::new(4));
+        if let Some(metrics) = &self.metrics {
+            metrics.set_pool(pool.stats());
+        }
+        let mut listeners = Vec::new();
+        if let Some(config) = &self.tls {
+            listeners.push(self.start_tls(Arc::clone(config), Arc::clone(&pool)));
+        }
+        let plain_http = match self.tls {
+            Some(_) => self.plain_http,
+            None => PlainHttp::Serve,
+        };
+        // The Unix socket takes the place of the plain HTTP port
+        #[cfg(unix)]
+        let plain_http = match &self.unix_socket {
+            Some(unix_socket) => {
+                listeners.push(self.start_unix_socket(unix_socket, Arc::clone(&pool)));
+                PlainHttp::Disabled
+            }
+            None => plain_http,
+        };
+        if plain_http != PlainHttp::Disabled {
+            self.serve_plain_http(plain_http, &pool);
+        }
+        for listener in listeners {
+            listener.join().unwrap();
+        }
+    }
+
+    fn serve_plain_http(&self, plain_http: PlainHttp, pool: &ThreadPool) {
+        let tls_port = env::var("TLS_PORT").unwrap_or_else(|_| DEFAULT_TLS_PORT.to_owned());
+        let serve = |connection: Box<dyn Connection>| {
+            let dispatcher = self.dispatcher();
+            let tls_port = tls_port.clone();
+
+            pool.execute(move || {
+                if plain_http == PlainHttp::RedirectToHttps {
+                    redirect_to_https(connection, &tls_port);
+                } else {
+                    dispatcher.serve(connection);
+                }
+            });
+        };
+
         let port = match env::var("PORT") {
             Ok(port) => port,
             Err(_) => "7878".to_owned(),
         };
         let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).unwrap();
-        let pool = ThreadPool::new(4);
 
         for stream in listener.incoming() {
-            let mut stream = match stream {
+            let stream = match stream {
                 Ok(stream) => stream,
                 Err(err) => {
                     eprintln!("Error opening TCP stream: {}", err);
                     continue;
                 }
             };
-            let routes = Arc::clone(&self.routes);
-            let mutex = Arc::clone(&self.state);
-
-            pool.execute(move || {
-                routes.len();
-                let mut buffer = [0; 8192];
-                let read_result = stream.read(&mut buffer);
-                if read_result.is_err() {
-                    return;
-                }
-                let bytes_read = read_result.unwrap();
-                if bytes_read == 0 || bytes_read >= buffer.len() {
-                    return;
-                }
-                let raw_request = String::from_utf8_lossy(&buffer).replace('\0', "");
-                // println!("raw_request: {:?}", raw_request);
-
-                let mut request = match HttpParser::new(&raw_request).parse_request() {
-                    Ok(request) => request,
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        send_response(
-                            &mut stream,
-                            HttpResponse::bad_request("Error parsing http request"),
-                        );
-                        return;
-                    }
-                };
-
-                let mut found_route = None;
-                for i in 0..routes.len() {
-                    let route = routes.get(i).unwrap();
-                    if route.matches_uri(&request.uri) {
-                        found_route = Some(route);
-                    }
-                }
-
-                let result: HttpResponse;
-                result = match found_route {
-                    Some(route) => {
-                        let handler = &route.handler;
-                        route.add_params(&mut request);
-                        let mut response = HttpResponse::new();
-                        let middlewares = Arc::clone(&route.middleware);
-                        let mut should_handle = true;
-                        for middleware in middlewares.iter() {
-                            should_handle = middleware(&request, &mut response, mutex.clone());
-                            if !should_handle {
-                                break;
-                            }
-                        }
-                        if should_handle {
-                            handler(&request, &mut response, mutex);
-                        }
-                        response
-                    }
-                    None => {
-                        let mut result = HttpResponse::new();
-                        result.set_status_code(404);
-                        result.set_body("Not found".to_owned());
-                        result
-                    }
-                };
-
-                send_response(&mut stream, result);
-            });
+            serve(Box::new(stream));
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::connection::MockConnection;
     use super::super::request::HttpRequest;
+    use super::super::TestClient;
     use super::*;
 
+    fn redirect(host: &str) -> String {
+        let request = format!("GET /hn?page=2 HTTP/1.1\r\nHost: {}\r\n\r\n", host);
+        let (connection, output) = MockConnection::new(request.into_bytes());
+        redirect_to_https(Box::new(connection), "7443");
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        output
+    }
+
+    #[test]
+    fn redirect_plain_http_to_https() {
+        let output = redirect("localhost:7878");
+        assert!(output.starts_with("HTTP/1.1 301 Moved permanently\r\n"));
+        assert!(output.contains("Location:https://localhost:7443/hn?page=2\r\n"));
+        assert!(redirect("localhost").contains("Location:https://localhost:7443/hn?page=2\r\n"));
+        assert!(redirect("[::1]").contains("Location:https://[::1]:7443/hn?page=2\r\n"));
+        assert!(redirect("[::1]:8080").contains("Location:https://[::1]:7443/hn?page=2\r\n"));
+    }
+
     #[test]
     fn basic_route_match() {
         let route: Route<()> = Route {
@@ -233,5 +1124,52 @@ mod tests {
         route.add_params(&mut request);
         assert_eq!(request.params.get("test_param").is_some(), true);
         assert_eq!(request.params.get("test_param").unwrap(), "some_param");
+
+        let mut request = HttpRequest::new_with_uri("/test/some_param?page=2".to_owned());
+        route.add_params(&mut request);
+        assert_eq!(request.params.get("test_param").unwrap(), "some_param");
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let mut server = HttpServer::new(());
+        server.route("GET", "/items", |_, _, _| "list");
+        server.route("POST", "/items", |_, _, _| "add");
+        server.route("*", "/proxy", |request, _, _| request.method.clone());
+        let mut client = TestClient::new(&server);
+        let response = client.send(client.request("DELETE", "/items"));
+        assert_eq!(response.status_code(), 405);
+        assert_eq!(response.header("Allow"), Some("GET, HEAD, POST"));
+        assert_eq!(client.send(client.request("POST", "/items")).body(), b"add");
+        assert_eq!(client.send(client.request("PUT", "/proxy")).body(), b"PUT");
+        assert_eq!(
+            client.send(client.request("PUT", "/other")).status_code(),
+            404
+        );
+    }
+
+    #[test]
+    fn head_requests() {
+        let mut server = HttpServer::new(());
+        server.route("GET", "/items", |_, response, _| {
+            response.set_header("Content-Type", "text/plain".to_owned());
+            "moon"
+        });
+        server.route("HEAD", "/status", |_, _, _| "ok");
+        let mut client = TestClient::new(&server);
+        let response = client.send(client.request("HEAD", "/items"));
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.header("Content-Type"), Some("text/plain"));
+        assert_eq!(response.header("Content-Length"), Some("4"));
+        assert!(response.body().is_empty());
+        assert_eq!(client.get("/items").body(), b"moon");
+
+        let response = client.send(client.request("HEAD", "/status"));
+        assert_eq!(response.header("Content-Length"), Some("2"));
+        assert!(response.body().is_empty());
+        let response = client.send(client.request("HEAD", "/missing"));
+        assert_eq!(response.status_code(), 404);
+        assert!(response.body().is_empty());
+        assert_eq!(client.get("/status").status_code(), 405);
     }
 }
@@ -0,0 +1,298 @@
+// https://html.spec.whatwg.org/multipage/server-sent-events.html
+use super::connection::Connection;
+use super::HttpResponse;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Event {
+    pub id: Option<String>,
+    pub event: Option<String>,
+    pub data: String,
+    pub retry: Option<u64>,
+}
+
+// Field values can't contain line breaks, they would start a new field
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+impl Event {
+    pub fn new(data: &str) -> Self {
+        Event {
+            data: data.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = Some(single_line(id));
+        self
+    }
+
+    pub fn event(mut self, event: &str) -> Self {
+        self.event = Some(single_line(event));
+        self
+    }
+
+    /// Reconnection time in milliseconds for the browser.
+    pub fn retry(mut self, retry: u64) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {}", id)?;
+        }
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {}", event)?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry)?;
+        }
+        for line in self.data.split('\n') {
+            writeln!(f, "data: {}", line.trim_end_matches('\r'))?;
+        }
+        writeln!(f)
+    }
+}
+
+struct BroadcasterInner {
+    history: VecDeque<Event>,
+    history_size: usize,
+    next_id: u64,
+    subscribers: Vec<Sender<Event>>,
+}
+
+/// Fans out events to every subscribed stream and keeps the last ones around, so clients
+/// reconnecting with `Last-Event-ID` get what they missed.
+#[derive(Clone)]
+pub struct EventBroadcaster {
+    inner: Arc<Mutex<BroadcasterInner>>,
+}
+
+impl EventBroadcaster {
+    pub fn new(history_size: usize) -> Self {
+        EventBroadcaster {
+            inner: Arc::new(Mutex::new(BroadcasterInner {
+                history: VecDeque::with_capacity(history_size),
+                history_size,
+                next_id: 1,
+                subscribers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Sends an event to all the subscribers. Events without an id get a sequential one.
+    pub fn send(&self, mut event: Event) {
+        let mut inner = self.inner.lock().unwrap();
+        if event.id.is_none() {
+            event.id = Some(inner.next_id.to_string());
+            inner.next_id += 1;
+        }
+        inner
+            .subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        if inner.history_size == 0 {
+            return;
+        }
+        if inner.history.len() == inner.history_size {
+            inner.history.pop_front();
+        }
+        inner.history.push_back(event);
+    }
+
+    /// Returns the events sent after `last_event_id` that are still in the history, and a
+    /// receiver for the next ones.
+    pub fn subscribe(&self, last_event_id: Option<&str>) -> (Vec<Event>, Receiver<Event>) {
+        let mut inner = self.inner.lock().unwrap();
+        let (sender, receiver) = channel();
+        inner.subscribers.push(sender);
+        let missed = match last_event_id {
+            None => Vec::new(),
+            Some(last_event_id) => {
+                let position = inner
+                    .history
+                    .iter()
+                    .position(|event| event.id.as_deref() == Some(last_event_id));
+                match position {
+                    Some(position) => inner.history.iter().skip(position + 1).cloned().collect(),
+                    None => inner.history.iter().cloned().collect(),
+                }
+            }
+        };
+        (missed, receiver)
+    }
+}
+
+/// Open `text/event-stream` response, handed to event stream routes after the response head
+/// has been sent.
+pub struct EventStream {
+    connection: Box<dyn Connection>,
+    last_event_id: Option<String>,
+    heartbeat_interval: Duration,
+}
+
+impl EventStream {
+    pub fn new(connection: Box<dyn Connection>, last_event_id: Option<String>) -> Self {
+        EventStream {
+            connection,
+            last_event_id,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+        }
+    }
+
+    /// Value of the `Last-Event-ID` header the browser sent when reconnecting.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    pub fn set_heartbeat_interval(&mut self, heartbeat_interval: Duration) {
+        self.heartbeat_interval = heartbeat_interval;
+    }
+
+    pub fn send(&mut self, event: &Event) -> io::Result<()> {
+        self.connection.write_all(event.to_string().as_bytes())?;
+        self.connection.flush()
+    }
+
+    /// Sends a comment line, which keeps proxies from closing an idle connection.
+    pub fn heartbeat(&mut self) -> io::Result<()> {
+        self.connection.write_all(b":\n\n")?;
+        self.connection.flush()
+    }
+
+    /// Browsers never send anything on an event stream, so a read that returns 0 bytes means
+    /// the client went away.
+    pub fn is_closed(&mut self) -> bool {
+        if self
+            .connection
+            .set_read_timeout(Some(Duration::from_millis(1)))
+            .is_err()
+        {
+            return true;
+        }
+        let mut buffer = [0; 512];
+        let closed = match self.connection.read(&mut buffer) {
+            Ok(0) => true,
+            Ok(_) => false,
+            Err(err) => err.kind() != ErrorKind::WouldBlock && err.kind() != ErrorKind::TimedOut,
+        };
+        closed || self.connection.set_read_timeout(None).is_err()
+    }
+
+    /// Writes every event from `receiver` until the sending side is dropped or the client
+    /// disconnects. A heartbeat is sent whenever no event arrives for the heartbeat interval.
+    pub fn run(mut self, receiver: Receiver<Event>) {
+        loop {
+            let result = match receiver.recv_timeout(self.heartbeat_interval) {
+                Ok(event) => self.send(&event),
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.is_closed() {
+                        return;
+                    }
+                    self.heartbeat()
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            if result.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Subscribes to a broadcaster, replays the events missed since `Last-Event-ID` and then
+    /// streams the new ones.
+    pub fn stream_from(mut self, broadcaster: &EventBroadcaster) {
+        let (missed, receiver) = broadcaster.subscribe(self.last_event_id());
+        for event in missed {
+            if self.send(&event).is_err() {
+                return;
+            }
+        }
+        self.run(receiver);
+    }
+}
+
+/// Sets the headers of an event stream response.
+pub fn set_event_stream_headers(response: &mut HttpResponse) {
+    response.set_header("Content-Type", "text/event-stream".to_owned());
+    response.set_header("Cache-Control", "no-cache".to_owned());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::connection::MockConnection;
+    use super::*;
+
+    #[test]
+    fn serialize_event() {
+        let event = Event::new("first line\nsecond line")
+            .id("42")
+            .event("update\n")
+            .retry(3000);
+        assert_eq!(
+            event.to_string(),
+            "id: 42\nevent: update\nretry: 3000\ndata: first line\ndata: second line\n\n"
+        );
+    }
+
+    #[test]
+    fn replay_missed_events() {
+        let broadcaster = EventBroadcaster::new(2);
+        broadcaster.send(Event::new("a"));
+        broadcaster.send(Event::new("b"));
+        broadcaster.send(Event::new("c"));
+
+        let (missed, _) = broadcaster.subscribe(Some("2"));
+        assert_eq!(missed, vec![Event::new("c").id("3")]);
+        let (missed, _) = broadcaster.subscribe(Some("1"));
+        assert_eq!(
+            missed,
+            vec![Event::new("b").id("2"), Event::new("c").id("3")]
+        );
+        let (missed, receiver) = broadcaster.subscribe(None);
+        assert_eq!(missed, vec![]);
+
+        broadcaster.send(Event::new("d").event("item"));
+        assert_eq!(
+            receiver.recv().unwrap(),
+            Event::new("d").id("4").event("item")
+        );
+    }
+
+    #[test]
+    fn stream_events_until_sender_is_dropped() {
+        let (connection, output) = MockConnection::new(Vec::new());
+        let stream = EventStream::new(Box::new(connection), None);
+        let (sender, receiver) = channel();
+        sender.send(Event::new("first").id("1")).unwrap();
+        sender.send(Event::new("second")).unwrap();
+        drop(sender);
+        stream.run(receiver);
+
+        assert_eq!(
+            String::from_utf8(output.lock().unwrap().clone()).unwrap(),
+            "id: 1\ndata: first\n\ndata: second\n\n"
+        );
+    }
+
+    #[test]
+    fn closed_connection_stops_heartbeats() {
+        let (connection, output) = MockConnection::new(Vec::new());
+        let mut stream = EventStream::new(Box::new(connection), None);
+        stream.set_heartbeat_interval(Duration::from_millis(1));
+        let (_sender, receiver) = channel();
+        stream.run(receiver);
+        assert!(output.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+use super::conditional::apply_preconditions;
+use super::date::format_http_date;
+use super::ranges::serve_ranges;
+use super::server::State;
+use super::{HttpError, HttpRequest, HttpResponse};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+fn content_types() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("html", "text/html"),
+        ("htm", "text/html"),
+        ("hbs", "text/html"),
+        ("css", "text/css"),
+        ("js", "application/javascript"),
+        ("json", "application/json"),
+        ("txt", "text/plain"),
+        ("xml", "application/xml"),
+        ("svg", "image/svg+xml"),
+        ("png", "image/png"),
+        ("jpg", "image/jpeg"),
+        ("jpeg", "image/jpeg"),
+        ("gif", "image/gif"),
+        ("ico", "image/x-icon"),
+        ("webp", "image/webp"),
+        ("mp3", "audio/mpeg"),
+        ("mp4", "video/mp4"),
+        ("webm", "video/webm"),
+        ("pdf", "application/pdf"),
+        ("wasm", "application/wasm"),
+        ("woff2", "font/woff2"),
+    ]
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => extension.to_ascii_lowercase(),
+        None => return "application/octet-stream",
+    };
+    content_types()
+        .into_iter()
+        .find(|(known_extension, _)| *known_extension == extension)
+        .map(|(_, content_type)| content_type)
+        .unwrap_or("application/octet-stream")
+}
+
+// Only plain file names are allowed, so requests can't escape the root with `..`
+fn resolve_path(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let request_path: String = request_path.split('?').take(1).collect();
+    let mut path = root.to_path_buf();
+    for component in Path::new(&request_path).components() {
+        match component {
+            Component::Normal(name) => path.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+fn read_range(file: &mut File, start: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Serves the file at `path`, with `Last-Modified` and `ETag` validators, conditional
+/// requests and byte ranges.
+pub fn serve_file(request: &HttpRequest, response: &mut HttpResponse, path: &Path) {
+    let not_found = |response: &mut HttpResponse| {
+        response.set_status_code(404);
+        response.set_error(HttpError::not_found("Not found"));
+    };
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return not_found(response),
+    };
+    let metadata = match file.metadata() {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return not_found(response),
+    };
+
+    let length = metadata.len();
+    response.set_header("Content-Type", content_type(path).to_owned());
+    if let Ok(modified) = metadata.modified() {
+        let seconds = modified
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        response.set_header("Last-Modified", format_http_date(modified));
+        // weak, two versions of the same length written within a second would share it, and
+        // If-Range could then mix their bytes
+        response.set_header("ETag", format!("W/\"{:x}-{:x}\"", seconds, length));
+    }
+
+    if apply_preconditions(request, response) {
+        return;
+    }
+
+    let result = serve_ranges(request, response, length, |start, len| {
+        read_range(&mut file, start, len)
+    });
+    if let Err(err) = result {
+        eprintln!("Error reading file {:?}: {}", path, err);
+        response.set_status_code(500);
+        response.set_body("Error reading file".to_owned());
+    }
+}
+
+/// Route handler that serves files under `root`. The route has to have a `:path` parameter,
+/// e.g. `/static/:path`.
+pub fn static_files<T>(
+    root: &str,
+) -> impl Fn(&HttpRequest, &mut HttpResponse, State<T>) + Send + Sync {
+    let root = PathBuf::from(root);
+    move |request, response, _| {
+        let path = request
+            .params
+            .get("path")
+            .and_then(|path| resolve_path(&root, path));
+        match path {
+            Some(path) => serve_file(request, response, &path),
+            None => {
+                response.set_status_code(404);
+                response.set_error(HttpError::not_found("Not found"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn resolve_paths() {
+        let root = Path::new("/srv/static");
+        assert_eq!(
+            resolve_path(root, "css/main.css?v=2"),
+            Some(PathBuf::from("/srv/static/css/main.css"))
+        );
+        assert_eq!(resolve_path(root, "../secret"), None);
+        assert_eq!(resolve_path(root, "/etc/passwd"), None);
+    }
+
+    #[test]
+    fn serve_file_range() {
+        let dir = std::env::temp_dir().join("moon-static-files-test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("file.txt"), "0123456789").unwrap();
+
+        let handler = static_files::<()>(dir.to_str().unwrap());
+        let mut request = HttpRequest::new_with_uri("/static/file.txt".to_owned());
+        request
+            .params
+            .insert("path".to_owned(), "file.txt".to_owned());
+        request
+            .headers
+            .push(("Range".to_owned(), "bytes=-4".to_owned()));
+        let mut response = HttpResponse::new();
+        handler(&request, &mut response, Arc::new(Mutex::new(())));
+
+        assert_eq!(response.status_code(), 206);
+        assert_eq!(response.header("Content-Type"), Some("text/plain"));
+        assert_eq!(response.header("Content-Range"), Some("bytes 6-9/10"));
+        assert_eq!(response.body(), b"6789");
+
+        let etag = response.header("ETag").unwrap().to_owned();
+        assert!(etag.starts_with("W/\""));
+        let mut request = HttpRequest::new_with_uri("/static/file.txt".to_owned());
+        request
+            .params
+            .insert("path".to_owned(), "file.txt".to_owned());
+        request
+            .headers
+            .push(("If-None-Match".to_owned(), etag.clone()));
+        let mut response = HttpResponse::new();
+        handler(&request, &mut response, Arc::new(Mutex::new(())));
+        assert_eq!(response.status_code(), 304);
+
+        // If-Range compares entity tags strongly, a weak one gets the whole file
+        let mut request = HttpRequest::new_with_uri("/static/file.txt".to_owned());
+        request
+            .params
+            .insert("path".to_owned(), "file.txt".to_owned());
+        request
+            .headers
+            .push(("Range".to_owned(), "bytes=-4".to_owned()));
+        request.headers.push(("If-Range".to_owned(), etag));
+        let mut response = HttpResponse::new();
+        handler(&request, &mut response, Arc::new(Mutex::new(())));
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), b"0123456789");
+    }
+}
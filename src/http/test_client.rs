@@ -0,0 +1,231 @@
+use super::server::{Dispatcher, HttpServer};
+use super::{HttpParser, HttpRequest, HttpResponse};
+use crate::json::JsonValue;
+
+/// Runs requests through the routes, middleware and state of an `HttpServer` without
+/// opening a socket. Cookies set by responses are sent back on the next requests, like a
+/// browser would.
+pub struct TestClient<T> {
+    dispatcher: Dispatcher<T>,
+    cookies: Vec<(String, String)>,
+}
+
+impl<T: Send + Sync + 'static> TestClient<T> {
+    pub fn new(server: &HttpServer<T>) -> Self {
+        TestClient {
+            dispatcher: server.dispatcher(),
+            cookies: Vec::new(),
+        }
+    }
+
+    /// Request as the server would parse it off the wire, ready to get headers and a body
+    /// before it's sent with `send`.
+    pub fn request(&self, method: &str, uri: &str) -> HttpRequest {
+        HttpParser::new(&format!("{} {} HTTP/1.1\r\n\r\n", method, uri))
+            .parse_request()
+            .unwrap_or_else(|err| panic!("Invalid test request {} {}: {}", method, uri, err))
+    }
+
+    pub fn send(&mut self, mut request: HttpRequest) -> HttpResponse {
+        if !self.cookies.is_empty() {
+            let mut cookies: Vec<String> = request
+                .headers
+                .iter()
+                .filter(|(name, _)| name.eq_ignore_ascii_case("Cookie"))
+                .map(|(_, value)| value.clone())
+                .collect();
+            cookies.extend(
+                self.cookies
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value)),
+            );
+            request
+                .headers
+                .retain(|(name, _)| !name.eq_ignore_ascii_case("Cookie"));
+            request
+                .headers
+                .push(("Cookie".to_owned(), cookies.join("; ")));
+        }
+        let response = self.dispatcher.handle(request);
+        for (name, value) in response.headers() {
+            if name.eq_ignore_ascii_case("Set-Cookie") {
+                self.store_cookie(value);
+            }
+        }
+        response
+    }
+
+    pub fn get(&mut self, uri: &str) -> HttpResponse {
+        let request = self.request("GET", uri);
+        self.send(request)
+    }
+
+    pub fn post(&mut self, uri: &str, body: &str) -> HttpResponse {
+        let mut request = self.request("POST", uri);
+        request.body = body.to_owned();
+        self.send(request)
+    }
+
+    /// Sends `body` as `application/json` with `method`.
+    pub fn send_json(&mut self, method: &str, uri: &str, body: &JsonValue) -> HttpResponse {
+        let mut request = self.request(method, uri);
+        request
+            .headers
+            .push(("Content-Type".to_owned(), "application/json".to_owned()));
+        request.body = body.stringify();
+        self.send(request)
+    }
+
+    pub fn post_json(&mut self, uri: &str, body: &JsonValue) -> HttpResponse {
+        self.send_json("POST", uri, body)
+    }
+
+    /// Value of a cookie set by an earlier response, or with `set_cookie`.
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_cookie(&mut self, name: &str, value: &str) {
+        self.cookies.retain(|(key, _)| key != name);
+        self.cookies.push((name.to_owned(), value.to_owned()));
+    }
+
+    pub fn clear_cookies(&mut self) {
+        self.cookies.clear();
+    }
+
+    // Attributes other than Max-Age don't matter for a single client without a clock
+    fn store_cookie(&mut self, set_cookie: &str) {
+        let mut parts = set_cookie.split(';');
+        let (name, value) = match parts.next().and_then(|pair| pair.split_once('=')) {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => return,
+        };
+        let expired = parts.any(|attribute| {
+            attribute
+                .trim()
+                .split_once('=')
+                .filter(|(key, _)| key.trim().eq_ignore_ascii_case("Max-Age"))
+                .is_some_and(|(_, age)| age.trim().parse::<i64>().is_ok_and(|age| age <= 0))
+        });
+        if expired {
+            self.cookies.retain(|(key, _)| key != name);
+        } else {
+            self.set_cookie(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::server::Route;
+    use crate::http::Router;
+    use std::sync::Arc;
+
+    fn server() -> HttpServer<u32> {
+        let mut server = HttpServer::new(0);
+        server.get("/hello/:name", &|request, response, state| {
+            *state.lock().unwrap() += 1;
+            response.set_body(request.params["name"].clone());
+        });
+        server.get("/search", &|request, response, _| {
+            response.set_body(request.query.get("page").cloned().unwrap_or_default());
+        });
+        server.add_route(Route {
+            method: "POST".to_owned(),
+            uri: "/echo".to_owned(),
+            middleware: Arc::new(vec![Box::new(
+                |request: &mut HttpRequest, response: &mut HttpResponse, _| {
+                    if request.header("Content-Type") != Some("application/json") {
+                        response.set_status_code(415);
+                        return false;
+                    }
+                    true
+                },
+            )]),
+            handler: Arc::new(|request, response, _| {
+                response.set_body(request.body.clone());
+            }),
+        });
+        server.get("/login", &|_, response, _| {
+            response.add_header(
+                "Set-Cookie".to_owned(),
+                "session=abc; Path=/; HttpOnly".to_owned(),
+            );
+        });
+        server.get("/logout", &|_, response, _| {
+            response.add_header("Set-Cookie".to_owned(), "session=; Max-Age=0".to_owned());
+        });
+        server.get("/whoami", &|request, response, _| {
+            response.set_body(request.header("Cookie").unwrap_or("").to_owned());
+        });
+        server.after(|_, response, _| response.set_header("X-After", "1".to_owned()));
+        server
+    }
+
+    #[test]
+    fn dispatch_like_the_server() {
+        let server = server();
+        let mut client = TestClient::new(&server);
+        let response = client.get("/hello/moon");
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), b"moon");
+        assert_eq!(response.header("X-After"), Some("1"));
+        assert_eq!(*server.state().lock().unwrap(), 1);
+        assert_eq!(client.get("/search?page=2").body(), b"2");
+
+        let response = client.get("/missing");
+        assert_eq!(response.status_code(), 404);
+        assert_eq!(response.header("X-After"), Some("1"));
+    }
+
+    #[test]
+    fn json_requests() {
+        let mut client = TestClient::new(&server());
+        assert_eq!(client.post("/echo", "{}").status_code(), 415);
+        let body = JsonValue::from(vec![JsonValue::from(1.0), JsonValue::from("two")]);
+        let response = client.post_json("/echo", &body);
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.json(), body);
+    }
+
+    #[test]
+    fn keep_cookies() {
+        let mut client = TestClient::new(&server());
+        client.get("/login");
+        assert_eq!(client.cookie("session"), Some("abc"));
+        client.set_cookie("theme", "dark");
+        let mut request = client.request("GET", "/whoami");
+        request
+            .headers
+            .push(("Cookie".to_owned(), "lang=en".to_owned()));
+        assert_eq!(
+            client.send(request).body(),
+            b"lang=en; session=abc; theme=dark"
+        );
+
+        client.get("/logout");
+        assert_eq!(client.cookie("session"), None);
+        assert_eq!(client.get("/whoami").body(), b"theme=dark");
+    }
+
+    #[test]
+    fn route_by_host() {
+        let mut server = server();
+        let mut api = Router::new();
+        api.get("/hello/:name", &|_, response, _| {
+            response.set_body("api".to_owned())
+        });
+        server.host("api.example.com", api);
+        let mut client = TestClient::new(&server);
+        let mut request = client.request("GET", "/hello/moon");
+        request
+            .headers
+            .push(("Host".to_owned(), "api.example.com".to_owned()));
+        assert_eq!(client.send(request).body(), b"api");
+    }
+}
@@ -0,0 +1,96 @@
+use super::server::HttpServer;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// `HttpServer` listening on a free port of `127.0.0.1` in a background thread, for tests
+/// that need a real socket. It stops accepting connections when dropped.
+pub struct TestServer {
+    address: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl TestServer {
+    pub fn start<T: Send + Sync + 'static>(server: HttpServer<T>) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let dispatcher = server.dispatcher();
+        let accepting = Arc::clone(&running);
+        let thread = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !accepting.load(Ordering::SeqCst) {
+                    return;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        eprintln!("Error opening TCP stream: {}", err);
+                        continue;
+                    }
+                };
+                // A thread per connection, a pool would wait for them when dropped
+                let dispatcher = dispatcher.clone();
+                thread::spawn(move || dispatcher.serve(Box::new(stream)));
+            }
+        });
+        Ok(TestServer {
+            address,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// `http://127.0.0.1:<port><path>`, for the HTTP client.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // Wakes the listener up so it sees it has to stop
+        let _ = TcpStream::connect(self.address);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn serve_until_dropped() {
+        let mut server = HttpServer::new(());
+        server.get("/ping", &|_, response, _| {
+            response.set_body("pong".to_owned())
+        });
+        let server = TestServer::start(server).unwrap();
+        let address = server.addr();
+        assert_ne!(address.port(), 0);
+        assert_eq!(server.url("/ping"), format!("http://{}/ping", address));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 Ok\r\n"));
+        assert!(response.ends_with("pong"));
+
+        drop(server);
+        assert!(TcpStream::connect(address).is_err());
+    }
+}
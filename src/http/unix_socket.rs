@@ -0,0 +1,113 @@
+use super::connection::Connection;
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+impl Connection for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Path and file permissions of the socket `HttpServer` listens on instead of TCP.
+#[derive(Debug, Clone)]
+pub struct UnixSocket {
+    pub path: PathBuf,
+    pub mode: u32,
+}
+
+impl UnixSocket {
+    /// Binds the socket, replacing a stale one left by a previous run. Other files at the path
+    /// and sockets with a live server behind them are left alone and reported as errors.
+    pub fn bind(&self) -> io::Result<UnixListener> {
+        remove_stale_socket(&self.path)?;
+        // Bound in a directory only we can enter and moved in place once it has its
+        // permissions, it's never reachable with the ones the umask gives it
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let directory = self
+            .path
+            .with_file_name(format!(".{}.{}", file_name, process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        DirBuilder::new().mode(0o700).create(&directory)?;
+        let bind = || {
+            let path = directory.join("socket");
+            let listener = UnixListener::bind(&path)?;
+            fs::set_permissions(&path, fs::Permissions::from_mode(self.mode))?;
+            fs::rename(&path, &self.path)?;
+            Ok(listener)
+        };
+        let result = bind();
+        let _ = fs::remove_dir_all(&directory);
+        result
+    }
+}
+
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    match UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is already in use", path.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn socket(name: &str) -> UnixSocket {
+        let path = env::temp_dir().join(format!("moon-{}-{}.sock", process::id(), name));
+        let _ = fs::remove_file(&path);
+        UnixSocket { path, mode: 0o660 }
+    }
+
+    #[test]
+    fn bind_with_permissions() {
+        let socket = socket("permissions");
+        let _listener = socket.bind().unwrap();
+        let metadata = fs::metadata(&socket.path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o660);
+        let file_name = socket.path.file_name().unwrap().to_string_lossy();
+        let directory = socket
+            .path
+            .with_file_name(format!(".{}.{}", file_name, process::id()));
+        assert!(!directory.exists());
+        UnixStream::connect(&socket.path).unwrap();
+        // Someone is listening, the socket is not stale
+        assert_eq!(socket.bind().unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        fs::remove_file(&socket.path).unwrap();
+    }
+
+    #[test]
+    fn replace_stale_socket() {
+        let socket = socket("stale");
+        drop(socket.bind().unwrap());
+        assert!(socket.path.exists());
+        let _listener = socket.bind().unwrap();
+        fs::remove_file(&socket.path).unwrap();
+
+        fs::write(&socket.path, "not a socket").unwrap();
+        assert!(socket.bind().is_err());
+        assert!(socket.path.is_file());
+        fs::remove_file(&socket.path).unwrap();
+    }
+}
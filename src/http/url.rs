@@ -1,5 +1,7 @@
 // https://github.com/SerenityOS/serenity/blob/6c087480cf0b179918bd7b2b8c7d2017553043ad/AK/URL.cpp
 
+use std::collections::HashMap;
+
 #[derive(PartialEq, Debug)]
 pub struct URL {
     pub protocol: String,
@@ -222,6 +224,53 @@ impl<'a> URLParser<'a> {
     }
 }
 
+/// Decodes `%XX` escapes, and `+` as a space in form data. `None` if an escape is
+/// truncated or the result isn't UTF-8.
+pub fn percent_decode(input: &str, plus_as_space: bool) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                let hex = input.get(index + 1..index + 3)?;
+                output.push(u8::from_str_radix(hex, 16).ok()?);
+                index += 3;
+                continue;
+            }
+            b'+' if plus_as_space => output.push(b' '),
+            byte => output.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8(output).ok()
+}
+
+/// Escapes everything but unreserved characters, so the result is safe in a path segment
+/// or a query string.
+pub fn percent_encode(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                output.push(byte as char)
+            }
+            byte => output.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    output
+}
+
+/// Parses `application/x-www-form-urlencoded` data, like a query string or a form body.
+pub fn parse_urlencoded(input: &str) -> Option<HashMap<String, String>> {
+    let mut fields = HashMap::new();
+    for pair in input.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        fields.insert(percent_decode(name, true)?, percent_decode(value, true)?);
+    }
+    Some(fields)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -330,4 +379,26 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn decode_urlencoded() {
+        assert_eq!(percent_decode("a%20b+c", false), Some("a b+c".to_owned()));
+        assert_eq!(
+            percent_decode("caf%C3%A9+latte", true),
+            Some("café latte".to_owned())
+        );
+        assert_eq!(percent_decode("100%", true), None);
+        assert_eq!(percent_decode("%ff", true), None);
+        let fields = parse_urlencoded("name=Moon+Web&tag=a%26b&empty").unwrap();
+        assert_eq!(fields["name"], "Moon Web");
+        assert_eq!(fields["tag"], "a&b");
+        assert_eq!(fields["empty"], "");
+    }
+
+    #[test]
+    fn encode_percent() {
+        assert_eq!(percent_encode("moon-web_1.0~"), "moon-web_1.0~");
+        assert_eq!(percent_encode("a b/c?d&e=é"), "a%20b%2Fc%3Fd%26e%3D%C3%A9");
+        assert_eq!(percent_decode(&percent_encode("50% off"), false).unwrap(), "50% off");
+    }
 }
@@ -0,0 +1,131 @@
+use super::server::Router;
+
+/// Host name a virtual host answers to. `*.example.com` matches every subdomain of
+/// `example.com`, but not `example.com` itself.
+#[derive(Debug, PartialEq)]
+pub enum HostPattern {
+    Exact(String),
+    Wildcard(String),
+}
+
+impl HostPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let pattern = normalize_host(pattern);
+        match pattern.strip_prefix("*.") {
+            Some(domain) => HostPattern::Wildcard(format!(".{}", domain)),
+            None => HostPattern::Exact(pattern),
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Exact(name) => name == host,
+            HostPattern::Wildcard(suffix) => host.len() > suffix.len() && host.ends_with(suffix),
+        }
+    }
+}
+
+/// Lowercases `host` and removes the port and the trailing dot of fully qualified names.
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    // IPv6 literals have colons of their own, the port comes after the bracket
+    let host = match host.rfind(']') {
+        Some(bracket) => &host[..=bracket],
+        None => host.split(':').next().unwrap_or(host),
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Routers by `Host` header, with the default router for everything else.
+pub struct VirtualHosts<T> {
+    pub default: Router<T>,
+    hosts: Vec<(HostPattern, Router<T>)>,
+}
+
+impl<T> VirtualHosts<T> {
+    pub fn new(default: Router<T>) -> Self {
+        VirtualHosts {
+            default,
+            hosts: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, pattern: &str, router: Router<T>) {
+        self.hosts.push((HostPattern::parse(pattern), router));
+    }
+
+    /// Exact names win over wildcards, and longer wildcards over shorter ones.
+    pub fn router(&self, host: Option<&str>) -> &Router<T> {
+        let host = match host {
+            Some(host) => normalize_host(host),
+            None => return &self.default,
+        };
+        let mut best: Option<(usize, &Router<T>)> = None;
+        for (pattern, router) in &self.hosts {
+            if !pattern.matches(&host) {
+                continue;
+            }
+            let score = match pattern {
+                HostPattern::Exact(_) => usize::MAX,
+                HostPattern::Wildcard(suffix) => suffix.len(),
+            };
+            let better = match best {
+                Some((best_score, _)) => score > best_score,
+                None => true,
+            };
+            if better {
+                best = Some((score, router));
+            }
+        }
+        best.map_or(&self.default, |(_, router)| router)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::server::Route;
+    use std::sync::Arc;
+
+    fn router(uri: &str) -> Router<()> {
+        let mut router = Router::new();
+        router.add_route(Route {
+            method: "GET".to_owned(),
+            uri: uri.to_owned(),
+            middleware: Arc::new(Vec::new()),
+            handler: Arc::new(|_, _, _| ()),
+        });
+        router
+    }
+
+    fn first_uri(router: &Router<()>) -> &str {
+        &router.routes()[0].uri
+    }
+
+    #[test]
+    fn normalize_hosts() {
+        assert_eq!(normalize_host("News.Example.com.:8080"), "news.example.com");
+        assert_eq!(normalize_host("[::1]:7878"), "[::1]");
+        assert_eq!(normalize_host("localhost"), "localhost");
+    }
+
+    #[test]
+    fn choose_router_by_host() {
+        let mut hosts = VirtualHosts::new(router("/default"));
+        hosts.add("*.example.com", router("/wildcard"));
+        hosts.add("*.api.example.com", router("/api-wildcard"));
+        hosts.add("api.example.com", router("/api"));
+
+        assert_eq!(first_uri(hosts.router(Some("API.example.com:80"))), "/api");
+        assert_eq!(
+            first_uri(hosts.router(Some("news.example.com"))),
+            "/wildcard"
+        );
+        assert_eq!(
+            first_uri(hosts.router(Some("v2.api.example.com"))),
+            "/api-wildcard"
+        );
+        assert_eq!(first_uri(hosts.router(Some("example.com"))), "/default");
+        assert_eq!(first_uri(hosts.router(None)), "/default");
+    }
+}
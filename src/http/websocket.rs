@@ -0,0 +1,561 @@
+// https://tools.ietf.org/html/rfc6455
+use super::super::base64;
+use super::super::crypto::sha1;
+use super::connection::Connection;
+use super::{HttpRequest, HttpResponse};
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close(Option<CloseFrame>),
+}
+
+#[derive(Debug)]
+pub enum WebSocketError {
+    Io(io::Error),
+    Protocol(&'static str),
+    MessageTooBig(usize),
+    InvalidUtf8,
+    ConnectionClosed,
+}
+
+impl fmt::Display for WebSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "WebSocket IO error: {}", err),
+            Self::Protocol(message) => write!(f, "WebSocket protocol error: {}", message),
+            Self::MessageTooBig(size) => {
+                write!(f, "WebSocket message of {} bytes is over the limit", size)
+            }
+            Self::InvalidUtf8 => write!(f, "WebSocket text message is not valid UTF-8"),
+            Self::ConnectionClosed => write!(f, "WebSocket connection is closed"),
+        }
+    }
+}
+
+impl From<io::Error> for WebSocketError {
+    fn from(err: io::Error) -> Self {
+        WebSocketError::Io(err)
+    }
+}
+
+type Result<T> = std::result::Result<T, WebSocketError>;
+
+/// Value of `Sec-WebSocket-Accept` for a `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let mut input = key.trim().to_owned();
+    input.push_str(WEBSOCKET_GUID);
+    base64::encode(&sha1(input.as_bytes()))
+}
+
+pub(super) fn header_has_token(request: &HttpRequest, name: &str, token: &str) -> bool {
+    match request.header(name) {
+        Some(value) => value
+            .split(',')
+            .any(|value| value.trim().eq_ignore_ascii_case(token)),
+        None => false,
+    }
+}
+
+/// Validates the opening handshake of a request and fills the `101 Switching Protocols`
+/// response. When the request isn't a valid WebSocket handshake the response is turned into
+/// a 400 or a 426 and `false` is returned.
+pub fn handshake(request: &HttpRequest, response: &mut HttpResponse) -> bool {
+    let key = match request.header("Sec-WebSocket-Key") {
+        Some(key) => key,
+        None => {
+            response.set_status_code(400);
+            response.set_body("Missing Sec-WebSocket-Key header".to_owned());
+            return false;
+        }
+    };
+    if request.method != "GET"
+        || !header_has_token(request, "Upgrade", "websocket")
+        || !header_has_token(request, "Connection", "upgrade")
+    {
+        response.set_status_code(400);
+        response.set_body("Not a WebSocket handshake".to_owned());
+        return false;
+    }
+    if request
+        .header("Sec-WebSocket-Version")
+        .map(|version| version.trim())
+        != Some("13")
+    {
+        response.set_status_code(426);
+        response.set_header("Sec-WebSocket-Version", "13".to_owned());
+        return false;
+    }
+
+    response.set_status_code(101);
+    response.set_header("Upgrade", "websocket".to_owned());
+    response.set_header("Connection", "Upgrade".to_owned());
+    response.set_header("Sec-WebSocket-Accept", accept_key(key));
+    true
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Server side of an upgraded WebSocket connection.
+pub struct WebSocket {
+    connection: Box<dyn Connection>,
+    max_message_size: usize,
+    fragments: Option<(u8, Vec<u8>)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl WebSocket {
+    pub fn new(connection: Box<dyn Connection>) -> Self {
+        WebSocket {
+            connection,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            fragments: None,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// Messages bigger than this, fragmented or not, close the connection with 1009.
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = max_message_size;
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.connection.set_read_timeout(timeout)
+    }
+
+    fn read_exact(&mut self, length: usize) -> Result<Vec<u8>> {
+        let mut buffer = vec![0; length];
+        self.connection.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn read_frame(&mut self) -> Result<Frame> {
+        let head = self.read_exact(2)?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0f;
+        if head[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol("reserved bits are set"));
+        }
+        if head[1] & 0x80 == 0 {
+            return Err(WebSocketError::Protocol("client frames have to be masked"));
+        }
+
+        let length = match head[1] & 0x7f {
+            126 => {
+                let bytes = self.read_exact(2)?;
+                u16::from_be_bytes([bytes[0], bytes[1]]) as u64
+            }
+            127 => {
+                let mut bytes = [0; 8];
+                self.connection.read_exact(&mut bytes)?;
+                let length = u64::from_be_bytes(bytes);
+                if length >> 63 != 0 {
+                    return Err(WebSocketError::Protocol("invalid payload length"));
+                }
+                length
+            }
+            length => length as u64,
+        };
+        if opcode >= OPCODE_CLOSE && (!fin || length > 125) {
+            return Err(WebSocketError::Protocol("invalid control frame"));
+        }
+        let buffered = self.fragments.as_ref().map_or(0, |(_, data)| data.len());
+        if opcode < OPCODE_CLOSE
+            && length > (self.max_message_size - buffered.min(self.max_message_size)) as u64
+        {
+            return Err(WebSocketError::MessageTooBig(buffered + length as usize));
+        }
+
+        let mask = self.read_exact(4)?;
+        let mut payload = self.read_exact(length as usize)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        Ok(Frame {
+            fin,
+            opcode,
+            payload,
+        })
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        if self.close_sent {
+            return Err(WebSocketError::ConnectionClosed);
+        }
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        if payload.len() < 126 {
+            frame.push(payload.len() as u8);
+        } else if payload.len() <= u16::MAX as usize {
+            frame.push(126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        } else {
+            frame.push(127);
+            frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        }
+        frame.extend_from_slice(payload);
+        self.connection.write_all(&frame)?;
+        self.connection.flush()?;
+        Ok(())
+    }
+
+    // Sends a close frame for a protocol violation and gives back the error to return.
+    fn fail(&mut self, code: u16, error: WebSocketError) -> WebSocketError {
+        if let Err(err) = self.close(code, "") {
+            eprintln!("Error closing WebSocket: {}", err);
+        }
+        self.close_received = true;
+        error
+    }
+
+    fn finish_message(&mut self, opcode: u8, payload: Vec<u8>) -> Result<Message> {
+        if opcode == OPCODE_BINARY {
+            return Ok(Message::Binary(payload));
+        }
+        match String::from_utf8(payload) {
+            Ok(text) => Ok(Message::Text(text)),
+            Err(_) => Err(self.fail(CLOSE_INVALID_DATA, WebSocketError::InvalidUtf8)),
+        }
+    }
+
+    fn receive_close(&mut self, payload: Vec<u8>) -> Result<Message> {
+        let close_frame = match payload.len() {
+            0 => None,
+            1 => {
+                return Err(self.fail(
+                    CLOSE_PROTOCOL_ERROR,
+                    WebSocketError::Protocol("close frame with a one byte payload"),
+                ))
+            }
+            _ => {
+                let code = u16::from_be_bytes([payload[0], payload[1]]);
+                if !valid_close_code(code) {
+                    return Err(self.fail(
+                        CLOSE_PROTOCOL_ERROR,
+                        WebSocketError::Protocol("invalid close code"),
+                    ));
+                }
+                let reason = match String::from_utf8(payload[2..].to_vec()) {
+                    Ok(reason) => reason,
+                    Err(_) => {
+                        return Err(self.fail(CLOSE_INVALID_DATA, WebSocketError::InvalidUtf8))
+                    }
+                };
+                Some(CloseFrame { code, reason })
+            }
+        };
+        if !self.close_sent {
+            let code = close_frame
+                .as_ref()
+                .map_or(CLOSE_NORMAL, |frame| frame.code);
+            self.close(code, "")?;
+        }
+        self.close_received = true;
+        Ok(Message::Close(close_frame))
+    }
+
+    /// Waits for the next message. Fragmented messages are reassembled, pings are answered
+    /// with a pong before being returned and a close frame is echoed back.
+    pub fn recv(&mut self) -> Result<Message> {
+        if self.close_received {
+            return Err(WebSocketError::ConnectionClosed);
+        }
+        loop {
+            let frame = match self.read_frame() {
+                Ok(frame) => frame,
+                Err(WebSocketError::Protocol(message)) => {
+                    return Err(self.fail(CLOSE_PROTOCOL_ERROR, WebSocketError::Protocol(message)))
+                }
+                Err(WebSocketError::MessageTooBig(size)) => {
+                    return Err(
+                        self.fail(CLOSE_MESSAGE_TOO_BIG, WebSocketError::MessageTooBig(size))
+                    )
+                }
+                Err(err) => return Err(err),
+            };
+
+            match frame.opcode {
+                OPCODE_CLOSE => return self.receive_close(frame.payload),
+                OPCODE_PING => {
+                    self.write_frame(OPCODE_PONG, &frame.payload)?;
+                    return Ok(Message::Ping(frame.payload));
+                }
+                OPCODE_PONG => return Ok(Message::Pong(frame.payload)),
+                OPCODE_CONTINUATION => {
+                    let (opcode, mut data) = match self.fragments.take() {
+                        Some(fragments) => fragments,
+                        None => {
+                            return Err(self.fail(
+                                CLOSE_PROTOCOL_ERROR,
+                                WebSocketError::Protocol("continuation frame without a message"),
+                            ))
+                        }
+                    };
+                    data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return self.finish_message(opcode, data);
+                    }
+                    self.fragments = Some((opcode, data));
+                }
+                OPCODE_TEXT | OPCODE_BINARY => {
+                    if self.fragments.is_some() {
+                        return Err(self.fail(
+                            CLOSE_PROTOCOL_ERROR,
+                            WebSocketError::Protocol("expected a continuation frame"),
+                        ));
+                    }
+                    if frame.fin {
+                        return self.finish_message(frame.opcode, frame.payload);
+                    }
+                    self.fragments = Some((frame.opcode, frame.payload));
+                }
+                _ => {
+                    return Err(self.fail(
+                        CLOSE_PROTOCOL_ERROR,
+                        WebSocketError::Protocol("unknown opcode"),
+                    ))
+                }
+            }
+        }
+    }
+
+    pub fn send(&mut self, message: Message) -> Result<()> {
+        match message {
+            Message::Text(text) => self.write_frame(OPCODE_TEXT, text.as_bytes()),
+            Message::Binary(data) => self.write_frame(OPCODE_BINARY, &data),
+            Message::Ping(data) | Message::Pong(data) if data.len() > 125 => Err(
+                WebSocketError::Protocol("control frame payloads are limited to 125 bytes"),
+            ),
+            Message::Ping(data) => self.write_frame(OPCODE_PING, &data),
+            Message::Pong(data) => self.write_frame(OPCODE_PONG, &data),
+            Message::Close(None) => self.close(CLOSE_NORMAL, ""),
+            Message::Close(Some(frame)) => self.close(frame.code, &frame.reason),
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<()> {
+        self.write_frame(OPCODE_TEXT, text.as_bytes())
+    }
+
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<()> {
+        self.write_frame(OPCODE_BINARY, data)
+    }
+
+    /// Starts the closing handshake. Calling it again once the close frame is sent does
+    /// nothing.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<()> {
+        if self.close_sent {
+            return Ok(());
+        }
+        // control frames carry 125 bytes, the reason is cut between two characters
+        let mut reason_len = reason.len().min(123);
+        while !reason.is_char_boundary(reason_len) {
+            reason_len -= 1;
+        }
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(&reason.as_bytes()[..reason_len]);
+        self.write_frame(OPCODE_CLOSE, &payload)?;
+        self.close_sent = true;
+        Ok(())
+    }
+}
+
+// Codes a peer can send, RFC 6455 section 7.4. 1005, 1006 and 1015 only exist for APIs to
+// report closes without a code or frame.
+fn valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::connection::MockConnection;
+    use std::sync::{Arc, Mutex};
+
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        if payload.len() < 126 {
+            frame.push(0x80 | payload.len() as u8);
+        } else {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        frame.extend_from_slice(&mask);
+        for (i, byte) in payload.iter().enumerate() {
+            frame.push(byte ^ mask[i % 4]);
+        }
+        frame
+    }
+
+    fn websocket(input: Vec<u8>) -> (WebSocket, Arc<Mutex<Vec<u8>>>) {
+        let (connection, output) = MockConnection::new(input);
+        (WebSocket::new(Box::new(connection)), output)
+    }
+
+    #[test]
+    fn accept_key_from_rfc() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn handshake_response() {
+        let mut request = HttpRequest::new_with_uri("/ws".to_owned());
+        request.headers = vec![
+            ("Upgrade".to_owned(), "websocket".to_owned()),
+            ("Connection".to_owned(), "keep-alive, Upgrade".to_owned()),
+            (
+                "Sec-WebSocket-Key".to_owned(),
+                "dGhlIHNhbXBsZSBub25jZQ==".to_owned(),
+            ),
+            ("Sec-WebSocket-Version".to_owned(), "13".to_owned()),
+        ];
+        let mut response = HttpResponse::new();
+        assert!(handshake(&request, &mut response));
+        assert_eq!(response.status_code(), 101);
+        assert_eq!(
+            response.header("Sec-WebSocket-Accept"),
+            Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+        );
+
+        request.headers[3].1 = "8".to_owned();
+        let mut response = HttpResponse::new();
+        assert!(!handshake(&request, &mut response));
+        assert_eq!(response.status_code(), 426);
+    }
+
+    #[test]
+    fn receive_masked_text() {
+        let input = vec![
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let (mut websocket, _) = websocket(input);
+        assert_eq!(websocket.recv().unwrap(), Message::Text("Hello".to_owned()));
+    }
+
+    #[test]
+    fn receive_fragmented_message_with_ping() {
+        let mut input = client_frame(false, OPCODE_TEXT, b"Hel");
+        input.append(&mut client_frame(true, OPCODE_PING, b"?"));
+        input.append(&mut client_frame(true, OPCODE_CONTINUATION, b"lo"));
+        let (mut websocket, output) = websocket(input);
+        assert_eq!(websocket.recv().unwrap(), Message::Ping(b"?".to_vec()));
+        assert_eq!(*output.lock().unwrap(), vec![0x8a, 0x01, b'?']);
+        assert_eq!(websocket.recv().unwrap(), Message::Text("Hello".to_owned()));
+    }
+
+    #[test]
+    fn reject_unmasked_frames() {
+        let (mut websocket, output) = websocket(vec![0x81, 0x02, b'h', b'i']);
+        match websocket.recv() {
+            Err(WebSocketError::Protocol(_)) => {}
+            result => panic!("Expected a protocol error, got {:?}", result),
+        }
+        assert_eq!(*output.lock().unwrap(), vec![0x88, 0x02, 0x03, 0xea]);
+    }
+
+    #[test]
+    fn reject_big_messages() {
+        let mut input = client_frame(false, OPCODE_BINARY, &[0; 100]);
+        input.append(&mut client_frame(true, OPCODE_CONTINUATION, &[0; 100]));
+        let (mut websocket, output) = websocket(input);
+        websocket.set_max_message_size(150);
+        match websocket.recv() {
+            Err(WebSocketError::MessageTooBig(200)) => {}
+            result => panic!("Expected a message too big error, got {:?}", result),
+        }
+        assert_eq!(*output.lock().unwrap(), vec![0x88, 0x02, 0x03, 0xf1]);
+    }
+
+    #[test]
+    fn closing_handshake() {
+        let mut payload = CLOSE_GOING_AWAY.to_be_bytes().to_vec();
+        payload.extend_from_slice(b"bye");
+        let (mut websocket, output) = websocket(client_frame(true, OPCODE_CLOSE, &payload));
+        assert_eq!(
+            websocket.recv().unwrap(),
+            Message::Close(Some(CloseFrame {
+                code: CLOSE_GOING_AWAY,
+                reason: "bye".to_owned()
+            }))
+        );
+        assert_eq!(*output.lock().unwrap(), vec![0x88, 0x02, 0x03, 0xe9]);
+        assert!(websocket.send_text("too late").is_err());
+    }
+
+    #[test]
+    fn reject_invalid_close_codes() {
+        for code in [999u16, 1004, 1005, 1006, 1015, 1016, 2999, 5000] {
+            let payload = code.to_be_bytes();
+            let (mut websocket, output) = websocket(client_frame(true, OPCODE_CLOSE, &payload));
+            match websocket.recv() {
+                Err(WebSocketError::Protocol(_)) => {}
+                result => panic!("Expected a protocol error for {}, got {:?}", code, result),
+            }
+            assert_eq!(*output.lock().unwrap(), vec![0x88, 0x02, 0x03, 0xea]);
+        }
+        let payload = 4000u16.to_be_bytes();
+        let (mut websocket, output) = websocket(client_frame(true, OPCODE_CLOSE, &payload));
+        assert!(websocket.recv().is_ok());
+        assert_eq!(*output.lock().unwrap(), vec![0x88, 0x02, 0x0f, 0xa0]);
+    }
+
+    #[test]
+    fn close_reason_keeps_whole_characters() {
+        let (mut websocket, output) = websocket(Vec::new());
+        // 61 two byte characters fit, the next one would end past byte 123
+        let reason = "é".repeat(70);
+        websocket.close(CLOSE_NORMAL, &reason).unwrap();
+        let output = output.lock().unwrap();
+        assert_eq!(output[1], 2 + 122);
+        assert_eq!(&output[2..4], &CLOSE_NORMAL.to_be_bytes());
+        assert_eq!(String::from_utf8(output[4..].to_vec()).unwrap(), reason[..122]);
+    }
+
+    #[test]
+    fn send_long_binary_message() {
+        let (mut websocket, output) = websocket(Vec::new());
+        websocket.send_binary(&[7; 300]).unwrap();
+        let output = output.lock().unwrap();
+        assert_eq!(output[..4], [0x82, 126, 0x01, 0x2c]);
+        assert_eq!(output.len(), 304);
+    }
+}
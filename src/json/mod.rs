@@ -1,8 +1,78 @@
 mod parser;
 mod value;
-pub use parser::JsonParser;
+pub use parser::{JsonParseError, JsonParser};
 pub use value::JsonValue;
 
 pub trait ToJson {
     fn to_json(&self) -> JsonValue;
 }
+
+/// Conversion from parsed JSON, with a message saying what doesn't fit when it fails.
+pub trait FromJson: Sized {
+    fn from_json(value: &JsonValue) -> Result<Self, String>;
+}
+
+impl FromJson for JsonValue {
+    fn from_json(value: &JsonValue) -> Result<Self, String> {
+        Ok(value.clone())
+    }
+}
+
+impl FromJson for f64 {
+    fn from_json(value: &JsonValue) -> Result<Self, String> {
+        value
+            .as_number()
+            .ok_or_else(|| "expected a number".to_owned())
+    }
+}
+
+impl FromJson for u64 {
+    fn from_json(value: &JsonValue) -> Result<Self, String> {
+        match value.as_number() {
+            Some(number) if number >= 0.0 && number.fract() == 0.0 => Ok(number as u64),
+            _ => Err("expected a positive integer".to_owned()),
+        }
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(value: &JsonValue) -> Result<Self, String> {
+        match value {
+            JsonValue::Boolean(value) => Ok(*value),
+            _ => Err("expected a boolean".to_owned()),
+        }
+    }
+}
+
+impl FromJson for String {
+    fn from_json(value: &JsonValue) -> Result<Self, String> {
+        match value {
+            JsonValue::String(value) => Ok(value.clone()),
+            _ => Err("expected a string".to_owned()),
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(value: &JsonValue) -> Result<Self, String> {
+        match value {
+            JsonValue::Null => Ok(None),
+            value => T::from_json(value).map(Some),
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(value: &JsonValue) -> Result<Self, String> {
+        let array = value
+            .as_array()
+            .ok_or_else(|| "expected an array".to_owned())?;
+        array
+            .iter()
+            .enumerate()
+            .map(|(index, element)| {
+                T::from_json(element).map_err(|err| format!("[{}]: {}", index, err))
+            })
+            .collect()
+    }
+}
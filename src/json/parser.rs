@@ -1,9 +1,28 @@
 use super::JsonValue;
 use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonParseError(String);
+
+impl fmt::Display for JsonParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for JsonParseError {}
+
+type Result<T> = std::result::Result<T, JsonParseError>;
+
+/// Arrays and objects nested deeper than this are an error, every level of nesting uses
+/// stack and request bodies could otherwise overflow it.
+const MAX_DEPTH: usize = 128;
 
 pub struct JsonParser {
     input: Vec<char>,
     index: usize,
+    depth: usize,
 }
 
 impl JsonParser {
@@ -11,46 +30,53 @@ impl JsonParser {
         Self {
             input: input.chars().collect(),
             index: 0,
+            depth: 0,
         }
     }
 
-    fn expect_char(&self, ch: char) {
+    fn error<T>(&self, message: String) -> Result<T> {
+        Err(JsonParseError(message))
+    }
+
+    fn expect_char(&self, ch: char) -> Result<()> {
         match self.input.get(self.index) {
-            Some(input_ch) => assert_eq!(
-                *input_ch, ch,
+            Some(input_ch) if *input_ch == ch => Ok(()),
+            Some(input_ch) => self.error(format!(
                 "JsonParser: Expected char {:?}, got {:?} at index {}",
                 ch, input_ch, self.index
-            ),
-            None => panic!(
+            )),
+            None => self.error(format!(
                 "JsonParser: Expected char at index '{}' but input lenght is '{}'",
                 self.index,
                 self.input.len()
-            ),
+            )),
         }
     }
 
-    fn consume_specific(&mut self, ch: char) {
-        self.expect_char(ch);
+    fn consume_specific(&mut self, ch: char) -> Result<()> {
+        self.expect_char(ch)?;
         self.index = self.index + 1;
+        Ok(())
     }
 
-    fn consume_specific_string(&mut self, string: &str) {
-        string.chars().for_each(|ch| {
-            self.consume_specific(ch);
-        });
+    fn consume_specific_string(&mut self, string: &str) -> Result<()> {
+        for ch in string.chars() {
+            self.consume_specific(ch)?;
+        }
+        Ok(())
     }
 
-    fn consume(&mut self) -> char {
+    fn consume(&mut self) -> Result<char> {
         match self.input.get(self.index) {
             Some(input_ch) => {
                 self.index = self.index + 1;
-                *input_ch
+                Ok(*input_ch)
             }
-            None => panic!(
+            None => self.error(format!(
                 "JsonParser: Expected char at index '{}' but input lenght is '{}'",
                 self.index,
                 self.input.len()
-            ),
+            )),
         }
     }
 
@@ -70,12 +96,12 @@ impl JsonParser {
             if !ch.is_whitespace() {
                 break;
             }
-            self.consume();
+            self.index += 1;
         }
     }
 
-    fn consume_quoted_string(&mut self) -> String {
-        self.consume_specific('"');
+    fn consume_quoted_string(&mut self) -> Result<String> {
+        self.consume_specific('"')?;
         let mut result = String::new();
 
         loop {
@@ -88,7 +114,10 @@ impl JsonParser {
                 }
                 ch = self.peek_index(peek_index);
                 match ch {
-                    None => panic!("JsonParser::consume_quoted_string cannot peek"),
+                    None => {
+                        return self
+                            .error("JsonParser::consume_quoted_string cannot peek".to_owned())
+                    }
                     Some('"') | Some('\\') => {
                         break;
                     }
@@ -98,11 +127,14 @@ impl JsonParser {
                 }
             }
 
-            let ch = *ch.unwrap();
+            let ch = match ch {
+                Some(ch) => *ch,
+                None => break,
+            };
 
             if peek_index != self.index {
                 while peek_index != self.index {
-                    result.push(self.consume());
+                    result.push(self.consume()?);
                 }
             }
 
@@ -115,49 +147,100 @@ impl JsonParser {
             }
 
             if ch != '\\' {
-                result.push(self.consume());
+                result.push(self.consume()?);
                 continue;
             }
 
-            self.consume_specific('\\');
-            let escaped_ch = self.consume();
+            self.consume_specific('\\')?;
+            let escaped_ch = self.consume()?;
             match escaped_ch {
                 'n' => result.push('\n'),
                 'r' => result.push('\r'),
                 't' => result.push('\t'),
+                'f' => result.push('\u{0c}'),
+                'b' => result.push('\u{08}'),
+                'u' => result.push(self.consume_unicode_escape()?),
+                '"' | '\\' | '/' => result.push(escaped_ch),
                 _ => {
-                    // TODO: handel \f \b \u
-                    result.push(escaped_ch);
+                    return self.error(format!(
+                        "JsonParser: Invalid escape {:?} at index {}",
+                        escaped_ch,
+                        self.index - 1
+                    ))
                 }
             }
         }
 
-        self.consume_specific('"');
+        self.consume_specific('"')?;
 
-        result
+        Ok(result)
+    }
+
+    fn consume_hex4(&mut self) -> Result<u32> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let ch = self.consume()?;
+            let digit = match ch.to_digit(16) {
+                Some(digit) => digit,
+                None => {
+                    return self.error(format!(
+                        "JsonParser: Invalid hex digit {:?} in \\u escape at index {}",
+                        ch,
+                        self.index - 1
+                    ))
+                }
+            };
+            value = value * 16 + digit;
+        }
+        Ok(value)
+    }
+
+    // After `\u`, characters outside of the BMP are a surrogate pair like `\ud83c\udf19`
+    fn consume_unicode_escape(&mut self) -> Result<char> {
+        let first = self.consume_hex4()?;
+        let code = match first {
+            0xd800..=0xdbff => {
+                if self.consume_specific_string("\\u").is_err() {
+                    return self.error("JsonParser: Unpaired surrogate in \\u escape".to_owned());
+                }
+                let second = self.consume_hex4()?;
+                if !(0xdc00..=0xdfff).contains(&second) {
+                    return self.error("JsonParser: Unpaired surrogate in \\u escape".to_owned());
+                }
+                0x10000 + ((first - 0xd800) << 10) + (second - 0xdc00)
+            }
+            0xdc00..=0xdfff => {
+                return self.error("JsonParser: Unpaired surrogate in \\u escape".to_owned())
+            }
+            _ => first,
+        };
+        match std::char::from_u32(code) {
+            Some(ch) => Ok(ch),
+            None => self.error(format!("JsonParser: Invalid \\u escape {:x}", code)),
+        }
     }
 
-    fn parse_string(&mut self) -> JsonValue {
-        let result = self.consume_quoted_string();
-        JsonValue::String(result)
+    fn parse_string(&mut self) -> Result<JsonValue> {
+        let result = self.consume_quoted_string()?;
+        Ok(JsonValue::String(result))
     }
 
-    fn parse_true(&mut self) -> JsonValue {
-        self.consume_specific_string("true");
-        JsonValue::Boolean(true)
+    fn parse_true(&mut self) -> Result<JsonValue> {
+        self.consume_specific_string("true")?;
+        Ok(JsonValue::Boolean(true))
     }
 
-    fn parse_false(&mut self) -> JsonValue {
-        self.consume_specific_string("false");
-        JsonValue::Boolean(false)
+    fn parse_false(&mut self) -> Result<JsonValue> {
+        self.consume_specific_string("false")?;
+        Ok(JsonValue::Boolean(false))
     }
 
-    fn parse_null(&mut self) -> JsonValue {
-        self.consume_specific_string("null");
-        JsonValue::Null
+    fn parse_null(&mut self) -> Result<JsonValue> {
+        self.consume_specific_string("null")?;
+        Ok(JsonValue::Null)
     }
 
-    fn parse_number(&mut self) -> JsonValue {
+    fn parse_number(&mut self) -> Result<JsonValue> {
         let mut number_str = String::new();
         let mut fraction_str = String::new();
         let mut is_double = false;
@@ -170,7 +253,7 @@ impl JsonParser {
 
             if ch == '.' {
                 is_double = true;
-                self.consume();
+                self.consume()?;
                 continue;
             }
 
@@ -180,34 +263,33 @@ impl JsonParser {
                 } else {
                     number_str.push(ch);
                 }
-                self.consume();
+                self.consume()?;
                 continue;
             }
             break;
         }
         if number_str.len() == 0 || (is_double && fraction_str.len() == 0) {
-            panic!("sonParser::parse_number Error parsing number: no numbers were found");
+            return self.error(
+                "sonParser::parse_number Error parsing number: no numbers were found".to_owned(),
+            );
         }
 
-        let number;
-        if is_double {
-            let final_number_str = format!("{}.{}", number_str, fraction_str);
-            number = final_number_str.parse().expect(&format!(
-                "JsonParser::parse_number Error parsing number: invalid number {:?}",
-                final_number_str
-            ));
+        let number_str = if is_double {
+            format!("{}.{}", number_str, fraction_str)
         } else {
-            number = number_str.parse().expect(&format!(
+            number_str
+        };
+        match number_str.parse() {
+            Ok(number) => Ok(JsonValue::Number(number)),
+            Err(_) => self.error(format!(
                 "JsonParser::parse_number Error parsing number: invalid number {:?}",
                 number_str
-            ));
+            )),
         }
-
-        JsonValue::Number(number)
     }
 
-    fn parse_array(&mut self) -> JsonValue {
-        self.consume_specific('[');
+    fn parse_array(&mut self) -> Result<JsonValue> {
+        self.consume_specific('[')?;
         let mut array = vec![];
 
         loop {
@@ -217,27 +299,28 @@ impl JsonParser {
                 break;
             }
 
-            let element = self.parse_value();
+            let element = self.parse_value()?;
             array.push(element);
             self.consume_whitespace();
 
             if self.peek() == Some(&']') {
                 break;
             }
-            self.consume_specific(',');
+            self.consume_specific(',')?;
             if self.peek() == Some(&']') {
-                panic!("JsonParser::parse_array cannot close array after ','");
+                return self
+                    .error("JsonParser::parse_array cannot close array after ','".to_owned());
             }
         }
 
         self.consume_whitespace();
-        self.consume_specific(']');
+        self.consume_specific(']')?;
 
-        JsonValue::Array(array)
+        Ok(JsonValue::Array(array))
     }
 
-    fn parse_object(&mut self) -> JsonValue {
-        self.consume_specific('{');
+    fn parse_object(&mut self) -> Result<JsonValue> {
+        self.consume_specific('{')?;
         let mut object = HashMap::new();
 
         loop {
@@ -246,16 +329,16 @@ impl JsonParser {
                 break;
             }
 
-            let key = self.consume_quoted_string();
+            let key = self.consume_quoted_string()?;
             if key.is_empty() {
-                panic!("JsonParser::parse_object Empty object key");
+                return self.error("JsonParser::parse_object Empty object key".to_owned());
             }
 
             self.consume_whitespace();
-            self.consume_specific(':');
+            self.consume_specific(':')?;
             self.consume_whitespace();
 
-            let value = self.parse_value();
+            let value = self.parse_value()?;
             object.insert(key, value);
 
             self.consume_whitespace();
@@ -263,24 +346,25 @@ impl JsonParser {
                 break;
             }
 
-            self.consume_specific(',');
+            self.consume_specific(',')?;
             self.consume_whitespace();
             if self.peek() == Some(&'}') {
-                panic!("JsonParser::parse_object cannot close object after ','");
+                return self
+                    .error("JsonParser::parse_object cannot close object after ','".to_owned());
             }
         }
 
         self.consume_whitespace();
-        self.consume_specific('}');
+        self.consume_specific('}')?;
 
-        JsonValue::Object(object)
+        Ok(JsonValue::Object(object))
     }
 
-    fn parse_value(&mut self) -> JsonValue {
+    fn parse_value(&mut self) -> Result<JsonValue> {
         self.consume_whitespace();
         let type_hint = match self.peek() {
-            Some(ch) => ch,
-            None => panic!("JsonParser::parse_value nothing to peek!"),
+            Some(ch) => *ch,
+            None => return self.error("JsonParser::parse_value nothing to peek!".to_owned()),
         };
         match type_hint {
             '"' => self.parse_string(),
@@ -288,14 +372,45 @@ impl JsonParser {
             'f' => self.parse_false(),
             'n' => self.parse_null(),
             '-' | '0'..='9' => self.parse_number(),
-            '[' => self.parse_array(),
-            '{' => self.parse_object(),
-            _ => panic!("JsonParser::parse_value unknown type hint {:?}", type_hint),
+            '[' | '{' => {
+                if self.depth == MAX_DEPTH {
+                    return self.error(format!(
+                        "JsonParser: Nested deeper than {} at index {}",
+                        MAX_DEPTH, self.index
+                    ));
+                }
+                self.depth += 1;
+                let value = if type_hint == '[' {
+                    self.parse_array()
+                } else {
+                    self.parse_object()
+                };
+                self.depth -= 1;
+                value
+            }
+            _ => self.error(format!(
+                "JsonParser::parse_value unknown type hint {:?}",
+                type_hint
+            )),
         }
     }
 
+    /// Panics if the input is not valid JSON, see `try_parse`.
     pub fn parse(&mut self) -> JsonValue {
-        self.parse_value()
+        self.parse_value().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Parses the whole input, for JSON that comes from outside, like request bodies.
+    pub fn try_parse(&mut self) -> Result<JsonValue> {
+        let value = self.parse_value()?;
+        self.consume_whitespace();
+        if self.index < self.input.len() {
+            return self.error(format!(
+                "JsonParser: Unexpected {:?} after the value at index {}",
+                self.input[self.index], self.index
+            ));
+        }
+        Ok(value)
     }
 }
 
@@ -361,4 +476,40 @@ mod tests {
         object.insert("missing".to_owned(), JsonValue::Null);
         assert_eq!(value, JsonValue::Object(object));
     }
+
+    #[test]
+    fn try_parse_errors() {
+        let value = JsonParser::new(" [true] ").try_parse();
+        assert_eq!(
+            value.unwrap(),
+            JsonValue::Array(vec![JsonValue::Boolean(true)])
+        );
+        assert!(JsonParser::new("[true").try_parse().is_err());
+        assert!(JsonParser::new("{\"a\": 1} x").try_parse().is_err());
+        assert!(JsonParser::new("").try_parse().is_err());
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let value = JsonParser::new(r#""caf\u00e9 \ud83c\udf19 \"\\\/\b\f""#).try_parse();
+        assert_eq!(
+            value,
+            Ok(JsonValue::String("caf\u{e9} \u{1f319} \"\\/\u{8}\u{c}".to_owned()))
+        );
+        assert!(JsonParser::new(r#""\u00g9""#).try_parse().is_err());
+        assert!(JsonParser::new(r#""\ud83c""#).try_parse().is_err());
+        assert!(JsonParser::new(r#""\udf19""#).try_parse().is_err());
+        assert!(JsonParser::new(r#""\x""#).try_parse().is_err());
+    }
+
+    #[test]
+    fn nesting_depth() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(JsonParser::new(&nested(MAX_DEPTH)).try_parse().is_ok());
+        assert!(JsonParser::new(&nested(MAX_DEPTH + 1)).try_parse().is_err());
+        let objects = format!("{}1{}", "{\"a\":".repeat(MAX_DEPTH + 1), "}".repeat(MAX_DEPTH + 1));
+        assert!(JsonParser::new(&objects).try_parse().is_err());
+        // a body of only `[` is an error, not a stack overflow
+        assert!(JsonParser::new(&"[".repeat(100_000)).try_parse().is_err());
+    }
 }
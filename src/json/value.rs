@@ -1,3 +1,4 @@
+use super::FromJson;
 use std::collections::HashMap;
 use std::iter::FromIterator;
 
@@ -15,7 +16,7 @@ impl JsonValue {
     pub fn stringify(&self) -> String {
         match self {
             Self::Number(number) => number.to_string(),
-            Self::String(string) => format!("\"{}\"", string),
+            Self::String(string) => escape_string(string),
             Self::Boolean(boolean) => boolean.to_string(),
             Self::Null => String::from("null"),
             Self::Array(elements) => {
@@ -34,7 +35,7 @@ impl JsonValue {
             Self::Object(object) => {
                 let mut result = String::from("{");
                 for (i, (key, value)) in object.iter().enumerate() {
-                    result.push_str(&format!("\"{}\":{}", key, value.stringify()));
+                    result.push_str(&format!("{}:{}", escape_string(key), value.stringify()));
                     if i != object.len() - 1 {
                         result.push(',');
                     }
@@ -47,6 +48,24 @@ impl JsonValue {
     }
 }
 
+fn escape_string(string: &str) -> String {
+    let mut result = String::with_capacity(string.len() + 2);
+    result.push('"');
+    for ch in string.chars() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
+
 impl JsonValue {
     pub fn as_object(&self) -> Option<&HashMap<String, JsonValue>> {
         match self {
@@ -92,6 +111,18 @@ impl JsonValue {
             _ => None,
         }
     }
+
+    /// Converts the field `name` of an object, a missing field counts as `null` so
+    /// `Option` fields can be left out.
+    pub fn field<T: FromJson>(&self, name: &str) -> Result<T, String> {
+        let object = self
+            .as_object()
+            .ok_or_else(|| "expected an object".to_owned())?;
+        match object.get(name) {
+            Some(value) => T::from_json(value).map_err(|err| format!("{}: {}", name, err)),
+            None => T::from_json(&JsonValue::Null).map_err(|_| format!("missing field {}", name)),
+        }
+    }
 }
 
 impl FromIterator<JsonValue> for JsonValue {
@@ -162,6 +193,14 @@ mod tests {
         assert_eq!(JsonValue::String("test".to_owned()).stringify(), "\"test\"");
     }
 
+    #[test]
+    fn stringify_escaped_string() {
+        assert_eq!(
+            JsonValue::String("say \"hi\"\\\n\u{1}".to_owned()).stringify(),
+            "\"say \\\"hi\\\"\\\\\\n\\u0001\""
+        );
+    }
+
     #[test]
     fn stringify_false() {
         assert_eq!(JsonValue::Boolean(false).stringify(), "false");
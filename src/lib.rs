@@ -1,4 +1,11 @@
+// Lets the code generated by the route attributes, which uses `::webserver` paths, work
+// inside the crate too
+extern crate self as webserver;
+
+pub mod base64;
+pub mod crypto;
 pub mod http;
 pub mod json;
 pub mod templating;
 pub mod thread_pool;
+pub mod tls;
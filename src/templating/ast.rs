@@ -1,5 +1,5 @@
 use super::super::json::JsonValue;
-use super::render_with_partials;
+use super::{render_impl, HelperArguments, Helpers};
 use std::collections::HashMap;
 
 fn html_entity_map() -> Vec<(char, &'static str)> {
@@ -37,12 +37,70 @@ fn is_value_truthy(value: Option<&JsonValue>) -> bool {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum HelperValue {
+    Literal(JsonValue),
+    Variable(String),
+}
+
+impl HelperValue {
+    fn resolve(&self, context: &JsonValue) -> JsonValue {
+        match (self, context) {
+            (Self::Literal(value), _) => value.clone(),
+            (Self::Variable(name), JsonValue::Object(map)) => {
+                map.get(name).cloned().unwrap_or(JsonValue::Null)
+            }
+            (Self::Variable(_), _) => JsonValue::Null,
+        }
+    }
+}
+
+/// Argument of a helper tag, `name=value` or just a value.
+#[derive(Debug, PartialEq)]
+pub struct HelperArgument {
+    pub name: Option<String>,
+    pub value: HelperValue,
+}
+
+fn render_helper(
+    name: &str,
+    arguments: &[HelperArgument],
+    escape: bool,
+    context: &JsonValue,
+    helpers: &Helpers,
+) -> String {
+    let helper = match helpers.get(name) {
+        Some(helper) => helper,
+        None => {
+            eprintln!("Helper not found {:?}", name);
+            return String::default();
+        }
+    };
+    let mut resolved = HelperArguments::default();
+    for argument in arguments {
+        let value = argument.value.resolve(context);
+        match &argument.name {
+            Some(name) => resolved.named.push((name.to_owned(), value)),
+            None => resolved.positional.push(value),
+        }
+    }
+    match helper(&resolved) {
+        Ok(output) if escape => escape_html(&output),
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("Helper {:?} error: {}", name, err);
+            String::default()
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum MustacheLikeNode {
     Text(String),
     Variable(String, bool),
     Section(String, Vec<MustacheLikeNode>, bool),
     Partial(String),
+    Helper(String, Vec<HelperArgument>, bool),
 }
 
 impl MustacheLikeNode {
@@ -50,17 +108,23 @@ impl MustacheLikeNode {
         nodes: &Vec<MustacheLikeNode>,
         context: &JsonValue,
         partials: &HashMap<String, String>,
+        helpers: &Helpers,
     ) -> String {
         let mut result = String::new();
 
         for node in nodes {
-            result.push_str(&node.render(context, partials));
+            result.push_str(&node.render(context, partials, helpers));
         }
 
         result
     }
 
-    pub fn render(&self, context: &JsonValue, partials: &HashMap<String, String>) -> String {
+    pub fn render(
+        &self,
+        context: &JsonValue,
+        partials: &HashMap<String, String>,
+        helpers: &Helpers,
+    ) -> String {
         match self {
             Self::Text(text) => {
                 return String::from(text);
@@ -107,7 +171,7 @@ impl MustacheLikeNode {
                     }
 
                     let render = || {
-                        return MustacheLikeNode::render_section(nodes, context, partials);
+                        return MustacheLikeNode::render_section(nodes, context, partials, helpers);
                     };
 
                     let value = match value {
@@ -126,7 +190,7 @@ impl MustacheLikeNode {
                             let mut result = String::new();
                             for element in array {
                                 result.push_str(&MustacheLikeNode::render_section(
-                                    nodes, &element, partials,
+                                    nodes, &element, partials, helpers,
                                 ));
                             }
                             return result;
@@ -159,10 +223,13 @@ impl MustacheLikeNode {
                         return String::default();
                     }
                     Some(partial_src) => {
-                        return render_with_partials(partial_src, context, partials);
+                        return render_impl(partial_src, context, partials, helpers);
                     }
                 }
             }
-        };
+            Self::Helper(name, arguments, escape) => {
+                render_helper(name, arguments, *escape, context, helpers)
+            }
+        }
     }
 }
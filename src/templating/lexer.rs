@@ -14,6 +14,16 @@ pub enum MustacheLikeToken {
     Partial(String),       // name
     OpenTag(String, bool), // tag_name, inverted
     CloseTag(String),      // tag_name
+    Helper(String, bool),  // name and arguments, escape
+}
+
+// Tags with arguments, like `{{url_for "item" id=id}}`, call a helper
+fn name_or_helper(text: &str, escape: bool) -> MustacheLikeToken {
+    if text.contains(char::is_whitespace) {
+        MustacheLikeToken::Helper(text.to_owned(), escape)
+    } else {
+        MustacheLikeToken::Name(text.to_owned(), escape)
+    }
 }
 
 impl MustacheLikeLexer {
@@ -160,17 +170,16 @@ impl MustacheLikeLexer {
                     self.tokens.push(MustacheLikeToken::CloseTag(tag_name));
                 }
                 '&' => {
-                    let variable_name = text_inside_tag.chars().skip(1).collect();
+                    let variable_name: String = text_inside_tag.chars().skip(1).collect();
                     self.tokens
-                        .push(MustacheLikeToken::Name(variable_name, false));
+                        .push(name_or_helper(variable_name.trim(), false));
                 }
                 '>' => {
                     let partial_name = text_inside_tag.chars().skip(1).collect();
                     self.tokens.push(MustacheLikeToken::Partial(partial_name));
                 }
                 _ => {
-                    self.tokens
-                        .push(MustacheLikeToken::Name(text_inside_tag, true));
+                    self.tokens.push(name_or_helper(&text_inside_tag, true));
                 }
             }
         }
@@ -216,4 +225,16 @@ mod tests {
             )
         )
     }
+
+    #[test]
+    fn lexer_helpers() {
+        let lexer = MustacheLikeLexer::new("{{url_for \"item\" id=id}}{{& url_for \"home\"}}");
+        assert_eq!(
+            lexer.run(),
+            vec!(
+                MustacheLikeToken::Helper(String::from("url_for \"item\" id=id"), true),
+                MustacheLikeToken::Helper(String::from("url_for \"home\""), false),
+            )
+        )
+    }
 }
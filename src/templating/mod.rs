@@ -2,13 +2,55 @@ mod ast;
 mod lexer;
 mod parser;
 use super::json::JsonValue;
-pub use ast::MustacheLikeNode;
+pub use ast::{HelperArgument, HelperValue, MustacheLikeNode};
 pub use lexer::{MustacheLikeLexer, MustacheLikeToken};
 use parser::MustacheLikeParser;
 use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Function called by tags with arguments, like `{{url_for "item" id=id}}`. Quoted strings,
+/// numbers and booleans are passed as they are, other values are looked up in the context.
+pub type Helper = dyn Fn(&HelperArguments) -> Result<String, String> + Send + Sync;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct HelperArguments {
+    pub positional: Vec<JsonValue>,
+    pub named: Vec<(String, JsonValue)>,
+}
+
+impl HelperArguments {
+    pub fn get(&self, name: &str) -> Option<&JsonValue> {
+        self.named
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Helpers {
+    helpers: HashMap<String, Arc<Helper>>,
+}
+
+impl Helpers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<F>(&mut self, name: &str, helper: F)
+    where
+        F: Fn(&HelperArguments) -> Result<String, String> + Send + Sync + 'static,
+    {
+        self.helpers.insert(name.to_owned(), Arc::new(helper));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Helper> {
+        self.helpers.get(name).map(|helper| helper.as_ref())
+    }
+}
 
 pub fn render(input: &str, context: &JsonValue) -> String {
-    render_impl(input, context, &HashMap::new())
+    render_impl(input, context, &HashMap::new(), &Helpers::new())
 }
 
 pub fn render_with_partials(
@@ -16,15 +58,29 @@ pub fn render_with_partials(
     context: &JsonValue,
     partials: &HashMap<String, String>,
 ) -> String {
-    render_impl(input, context, partials)
+    render_impl(input, context, partials, &Helpers::new())
 }
 
-fn render_impl(input: &str, context: &JsonValue, partials: &HashMap<String, String>) -> String {
+pub fn render_with_helpers(
+    input: &str,
+    context: &JsonValue,
+    partials: &HashMap<String, String>,
+    helpers: &Helpers,
+) -> String {
+    render_impl(input, context, partials, helpers)
+}
+
+fn render_impl(
+    input: &str,
+    context: &JsonValue,
+    partials: &HashMap<String, String>,
+    helpers: &Helpers,
+) -> String {
     let tokens = MustacheLikeLexer::new(input).run();
     assert_ne!(tokens.len(), 0, "No tokens were generated");
     let nodes = MustacheLikeParser::new(tokens).parse();
     assert_ne!(nodes.len(), 0, "No nodes were generated");
-    MustacheLikeNode::render_section(&nodes, context, partials)
+    MustacheLikeNode::render_section(&nodes, context, partials, helpers)
 }
 
 #[cfg(test)]
@@ -234,4 +290,28 @@ mod tests {
             "X<Y<>>"
         );
     }
+
+    #[test]
+    fn render_helpers() {
+        let mut helpers = Helpers::new();
+        helpers.add("link", |arguments| match arguments.positional.as_slice() {
+            [JsonValue::String(text)] => Ok(format!(
+                "<a href=\"/item/{}\">{}</a>",
+                arguments.get("id").unwrap().stringify(),
+                text
+            )),
+            _ => Err("Expected the text of the link".to_owned()),
+        });
+        let context = JsonParser::new("{ \"parent\": 42 }").parse();
+        assert_eq!(
+            render_with_helpers(
+                "{{&link \"Parent item\" id=parent}}|{{link \"x\" id=1}}|{{link id=1}}",
+                &context,
+                &HashMap::new(),
+                &helpers
+            ),
+            "<a href=\"/item/42\">Parent item</a>|&lt;a href=&quot;&#x2F;item&#x2F;1&quot;&gt;x&lt;&#x2F;a&gt;|"
+        );
+        assert_eq!(render("[{{missing helper}}]", &context), "[]");
+    }
 }
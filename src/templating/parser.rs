@@ -1,4 +1,5 @@
-use super::{MustacheLikeNode, MustacheLikeToken};
+use super::{HelperArgument, HelperValue, MustacheLikeNode, MustacheLikeToken};
+use crate::json::JsonValue;
 
 pub struct MustacheLikeParser {
     tokens: Vec<MustacheLikeToken>,
@@ -93,6 +94,13 @@ impl MustacheLikeParser {
                     nodes.push(MustacheLikeNode::Partial(name.to_owned()));
                     self.consume();
                 }
+                MustacheLikeToken::Helper(text, escape) => {
+                    let mut words = split_words(text).into_iter();
+                    let name = words.next().unwrap_or_default();
+                    let arguments = words.map(|word| parse_argument(&word)).collect();
+                    nodes.push(MustacheLikeNode::Helper(name, arguments, *escape));
+                    self.consume();
+                }
             }
         }
 
@@ -100,6 +108,61 @@ impl MustacheLikeParser {
     }
 }
 
+// Splits at whitespace outside of double quotes
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for ch in text.chars() {
+        if ch.is_whitespace() && !quoted {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if ch == '"' {
+            quoted = !quoted;
+        }
+        word.push(ch);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn parse_argument(word: &str) -> HelperArgument {
+    if !word.starts_with('"') {
+        if let Some((name, value)) = word.split_once('=') {
+            return HelperArgument {
+                name: Some(name.to_owned()),
+                value: parse_value(value),
+            };
+        }
+    }
+    HelperArgument {
+        name: None,
+        value: parse_value(word),
+    }
+}
+
+fn parse_value(value: &str) -> HelperValue {
+    if let Some(string) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        return HelperValue::Literal(JsonValue::String(string.to_owned()));
+    }
+    match value {
+        "true" => HelperValue::Literal(JsonValue::Boolean(true)),
+        "false" => HelperValue::Literal(JsonValue::Boolean(false)),
+        _ => match value.parse() {
+            Ok(number) => HelperValue::Literal(JsonValue::Number(number)),
+            Err(_) => HelperValue::Variable(value.to_owned()),
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -142,4 +205,34 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn parser_helper() {
+        let tokens = vec![MustacheLikeToken::Helper(
+            String::from("url_for  \"hn item\" id=parent page=2"),
+            true,
+        )];
+        let nodes = MustacheLikeParser::new(tokens).parse();
+        assert_eq!(
+            nodes,
+            vec![MustacheLikeNode::Helper(
+                String::from("url_for"),
+                vec![
+                    HelperArgument {
+                        name: None,
+                        value: HelperValue::Literal(JsonValue::String(String::from("hn item"))),
+                    },
+                    HelperArgument {
+                        name: Some(String::from("id")),
+                        value: HelperValue::Variable(String::from("parent")),
+                    },
+                    HelperArgument {
+                        name: Some(String::from("page")),
+                        value: HelperValue::Literal(JsonValue::Number(2.0)),
+                    },
+                ],
+                true
+            )],
+        );
+    }
 }
@@ -1,13 +1,55 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: mpsc::Sender<Message>,
+    stats: PoolStats,
+}
+
+/// Load of a pool, shared with its workers.
+#[derive(Clone, Debug, Default)]
+pub struct PoolStats {
+    size: usize,
+    queued: Arc<AtomicUsize>,
+    busy: Arc<AtomicUsize>,
+}
+
+impl PoolStats {
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Jobs waiting for a free worker.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Workers running a job.
+    pub fn busy(&self) -> usize {
+        self.busy.load(Ordering::SeqCst)
+    }
 }
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+/// Counts a worker as busy until dropped, even if its job panics.
+struct Busy(Arc<AtomicUsize>);
+
+impl Busy {
+    fn start(busy: &Arc<AtomicUsize>) -> Self {
+        busy.fetch_add(1, Ordering::SeqCst);
+        Busy(Arc::clone(busy))
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 impl ThreadPool {
     pub fn new(size: usize) -> ThreadPool {
         assert!(size > 0);
@@ -15,14 +57,26 @@ impl ThreadPool {
         let (sender, receiver) = mpsc::channel();
 
         let receiver = Arc::new(Mutex::new(receiver));
+        let stats = PoolStats {
+            size,
+            ..PoolStats::default()
+        };
 
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver), stats.clone()));
         }
 
-        ThreadPool { workers, sender }
+        ThreadPool {
+            workers,
+            sender,
+            stats,
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats.clone()
     }
 
     pub fn execute<F>(&self, f: F)
@@ -31,6 +85,7 @@ impl ThreadPool {
     {
         let job = Box::new(f);
 
+        self.stats.queued.fetch_add(1, Ordering::SeqCst);
         self.sender.send(Message::NewJob(job)).unwrap();
     }
 }
@@ -66,15 +121,14 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>, stats: PoolStats) -> Worker {
         let thread = thread::spawn(move || loop {
             let message = receiver.lock().unwrap().recv().unwrap();
 
             match message {
                 Message::NewJob(job) => {
-                    #[cfg(debug_assertions)]
-                    println!("Worker {} got a job; executing.", id);
-
+                    stats.queued.fetch_sub(1, Ordering::SeqCst);
+                    let _busy = Busy::start(&stats.busy);
                     job();
                 }
                 Message::Terminate => {
@@ -91,3 +145,30 @@ impl Worker {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn panicking_job_is_not_busy() {
+        let pool = ThreadPool::new(2);
+        let stats = pool.stats();
+        let (started, job_started) = mpsc::channel();
+        pool.execute(move || {
+            started.send(()).unwrap();
+            panic!("job failed");
+        });
+        job_started.recv().unwrap();
+        for _ in 0..100 {
+            if stats.busy() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(stats.busy(), 0);
+        // dropping the pool would fail to join the panicked worker
+        std::mem::forget(pool);
+    }
+}
@@ -0,0 +1,115 @@
+// https://tools.ietf.org/html/rfc8446
+//
+// Server side TLS 1.3 with X25519 key exchange, TLS_CHACHA20_POLY1305_SHA256 and RSA
+// certificates. No session resumption, client certificates or HelloRetryRequest.
+mod handshake;
+mod pem;
+mod record;
+mod stream;
+pub use handshake::accept;
+pub use stream::TlsStream;
+
+use crate::crypto::RsaPrivateKey;
+use std::fmt;
+use std::fs;
+use std::io;
+
+pub mod alert {
+    pub const CLOSE_NOTIFY: u8 = 0;
+    pub const UNEXPECTED_MESSAGE: u8 = 10;
+    pub const BAD_RECORD_MAC: u8 = 20;
+    pub const RECORD_OVERFLOW: u8 = 22;
+    pub const HANDSHAKE_FAILURE: u8 = 40;
+    pub const ILLEGAL_PARAMETER: u8 = 47;
+    pub const DECODE_ERROR: u8 = 50;
+    pub const DECRYPT_ERROR: u8 = 51;
+    pub const PROTOCOL_VERSION: u8 = 70;
+    pub const INTERNAL_ERROR: u8 = 80;
+    pub const NO_APPLICATION_PROTOCOL: u8 = 120;
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    Io(io::Error),
+    InvalidPem(&'static str),
+    InvalidPrivateKey(&'static str),
+    /// The peer broke the protocol, `alert` is the description sent back to it.
+    Protocol {
+        alert: u8,
+        reason: &'static str,
+    },
+    /// The peer sent a fatal alert.
+    Alert(u8),
+}
+
+impl TlsError {
+    fn protocol(alert: u8, reason: &'static str) -> Self {
+        TlsError::Protocol { alert, reason }
+    }
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "TLS IO error: {}", err),
+            Self::InvalidPem(message) => write!(f, "Invalid PEM file: {}", message),
+            Self::InvalidPrivateKey(message) => write!(f, "Invalid private key: {}", message),
+            Self::Protocol { alert, reason } => {
+                write!(f, "TLS protocol error: {} (alert {})", reason, alert)
+            }
+            Self::Alert(alert) => write!(f, "TLS alert {} received", alert),
+        }
+    }
+}
+
+impl From<io::Error> for TlsError {
+    fn from(err: io::Error) -> Self {
+        TlsError::Io(err)
+    }
+}
+
+impl From<TlsError> for io::Error {
+    fn from(err: TlsError) -> Self {
+        match err {
+            TlsError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        }
+    }
+}
+
+/// Certificate chain, private key and protocols offered through ALPN.
+pub struct TlsConfig {
+    certificates: Vec<Vec<u8>>,
+    private_key: RsaPrivateKey,
+    alpn_protocols: Vec<String>,
+}
+
+impl TlsConfig {
+    /// Builds a config from PEM encoded certificates, leaf first, and an RSA private key in
+    /// PKCS #1 or PKCS #8 format.
+    pub fn from_pem(certificates: &str, private_key: &str) -> Result<Self, TlsError> {
+        Ok(TlsConfig {
+            certificates: pem::parse_certificates(certificates)?,
+            private_key: pem::parse_private_key(private_key)?,
+            alpn_protocols: vec!["h2".to_owned(), "http/1.1".to_owned()],
+        })
+    }
+
+    pub fn from_pem_files(
+        certificates_path: &str,
+        private_key_path: &str,
+    ) -> Result<Self, TlsError> {
+        let certificates = fs::read_to_string(certificates_path)?;
+        let private_key = fs::read_to_string(private_key_path)?;
+        Self::from_pem(&certificates, &private_key)
+    }
+
+    /// Protocols to negotiate with ALPN, in order of preference. Clients that offer none of
+    /// them get a `no_application_protocol` alert.
+    pub fn set_alpn_protocols(&mut self, protocols: &[&str]) {
+        self.alpn_protocols = protocols
+            .iter()
+            .map(|protocol| (*protocol).to_owned())
+            .collect();
+    }
+}
@@ -0,0 +1,226 @@
+// https://tools.ietf.org/html/rfc7468
+use super::TlsError;
+use crate::base64;
+use crate::crypto::RsaPrivateKey;
+
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OBJECT_IDENTIFIER: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+
+/// Returns the label and the decoded contents of every PEM block in `input`.
+pub fn parse_pem(input: &str) -> Result<Vec<(String, Vec<u8>)>, TlsError> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(String, String)> = None;
+    for line in input.lines() {
+        let line = line.trim();
+        if let Some(label) = line
+            .strip_prefix("-----BEGIN ")
+            .and_then(|rest| rest.strip_suffix("-----"))
+        {
+            current = Some((label.to_owned(), String::new()));
+        } else if let Some(label) = line
+            .strip_prefix("-----END ")
+            .and_then(|rest| rest.strip_suffix("-----"))
+        {
+            let (begin_label, contents) = current
+                .take()
+                .ok_or(TlsError::InvalidPem("END line without BEGIN line"))?;
+            if begin_label != label {
+                return Err(TlsError::InvalidPem("BEGIN and END labels don't match"));
+            }
+            let contents =
+                base64::decode(&contents).ok_or(TlsError::InvalidPem("invalid base64"))?;
+            blocks.push((begin_label, contents));
+        } else if let Some((_, contents)) = current.as_mut() {
+            contents.push_str(line);
+        }
+    }
+    if current.is_some() {
+        return Err(TlsError::InvalidPem("BEGIN line without END line"));
+    }
+    Ok(blocks)
+}
+
+/// Minimal DER reader, enough for private keys.
+struct Der<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    fn read(&mut self, expected_tag: u8) -> Result<&'a [u8], TlsError> {
+        let invalid = TlsError::InvalidPrivateKey("malformed DER");
+        let (&tag, rest) = self.data.split_first().ok_or(invalid)?;
+        let (&first, mut rest) = rest
+            .split_first()
+            .ok_or(TlsError::InvalidPrivateKey("malformed DER"))?;
+        let len = if first < 0x80 {
+            first as usize
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err(TlsError::InvalidPrivateKey("malformed DER"));
+            }
+            let len = rest[..count]
+                .iter()
+                .fold(0usize, |len, &byte| (len << 8) | byte as usize);
+            rest = &rest[count..];
+            len
+        };
+        if tag != expected_tag || rest.len() < len {
+            return Err(TlsError::InvalidPrivateKey("malformed DER"));
+        }
+        self.data = &rest[len..];
+        Ok(&rest[..len])
+    }
+}
+
+// RSAPrivateKey from PKCS #1
+fn parse_rsa_private_key(der: &[u8]) -> Result<RsaPrivateKey, TlsError> {
+    let mut sequence = Der {
+        data: Der { data: der }.read(TAG_SEQUENCE)?,
+    };
+    let _version = sequence.read(TAG_INTEGER)?;
+    let modulus = sequence.read(TAG_INTEGER)?;
+    let public_exponent = sequence.read(TAG_INTEGER)?;
+    let _private_exponent = sequence.read(TAG_INTEGER)?;
+    let prime1 = sequence.read(TAG_INTEGER)?;
+    let prime2 = sequence.read(TAG_INTEGER)?;
+    let exponent1 = sequence.read(TAG_INTEGER)?;
+    let exponent2 = sequence.read(TAG_INTEGER)?;
+    let coefficient = sequence.read(TAG_INTEGER)?;
+    RsaPrivateKey::new(
+        modulus,
+        public_exponent,
+        prime1,
+        prime2,
+        exponent1,
+        exponent2,
+        coefficient,
+    )
+    .ok_or(TlsError::InvalidPrivateKey(
+        "RSA keys need a modulus of at least 1024 bits",
+    ))
+}
+
+// PrivateKeyInfo from PKCS #8
+fn parse_private_key_info(der: &[u8]) -> Result<RsaPrivateKey, TlsError> {
+    let mut sequence = Der {
+        data: Der { data: der }.read(TAG_SEQUENCE)?,
+    };
+    let _version = sequence.read(TAG_INTEGER)?;
+    let mut algorithm = Der {
+        data: sequence.read(TAG_SEQUENCE)?,
+    };
+    if algorithm.read(TAG_OBJECT_IDENTIFIER)? != RSA_ENCRYPTION_OID {
+        return Err(TlsError::InvalidPrivateKey("only RSA keys are supported"));
+    }
+    parse_rsa_private_key(sequence.read(TAG_OCTET_STRING)?)
+}
+
+/// Reads an RSA private key from a `RSA PRIVATE KEY` or `PRIVATE KEY` PEM block.
+pub fn parse_private_key(input: &str) -> Result<RsaPrivateKey, TlsError> {
+    for (label, contents) in parse_pem(input)? {
+        match label.as_str() {
+            "RSA PRIVATE KEY" => return parse_rsa_private_key(&contents),
+            "PRIVATE KEY" => return parse_private_key_info(&contents),
+            "ENCRYPTED PRIVATE KEY" => {
+                return Err(TlsError::InvalidPrivateKey(
+                    "encrypted private keys are not supported",
+                ))
+            }
+            "EC PRIVATE KEY" => {
+                return Err(TlsError::InvalidPrivateKey("only RSA keys are supported"))
+            }
+            _ => {}
+        }
+    }
+    Err(TlsError::InvalidPrivateKey("no private key found"))
+}
+
+/// RSA public key of a DER encoded certificate, for the client of the handshake tests.
+#[cfg(test)]
+pub(super) fn parse_certificate_public_key(
+    der: &[u8],
+) -> Result<crate::crypto::RsaPublicKey, TlsError> {
+    const TAG_BIT_STRING: u8 = 0x03;
+    const TAG_VERSION: u8 = 0xa0;
+    let mut certificate = Der {
+        data: Der { data: der }.read(TAG_SEQUENCE)?,
+    };
+    let mut tbs_certificate = Der {
+        data: certificate.read(TAG_SEQUENCE)?,
+    };
+    let _version = tbs_certificate.read(TAG_VERSION)?;
+    let _serial_number = tbs_certificate.read(TAG_INTEGER)?;
+    for _signature_issuer_validity_subject in 0..4 {
+        tbs_certificate.read(TAG_SEQUENCE)?;
+    }
+    let mut public_key_info = Der {
+        data: tbs_certificate.read(TAG_SEQUENCE)?,
+    };
+    let mut algorithm = Der {
+        data: public_key_info.read(TAG_SEQUENCE)?,
+    };
+    if algorithm.read(TAG_OBJECT_IDENTIFIER)? != RSA_ENCRYPTION_OID {
+        return Err(TlsError::InvalidPrivateKey("only RSA keys are supported"));
+    }
+    // the first byte of the bit string is the number of unused bits, 0
+    let public_key = public_key_info.read(TAG_BIT_STRING)?;
+    let mut public_key = Der {
+        data: Der {
+            data: &public_key[1..],
+        }
+        .read(TAG_SEQUENCE)?,
+    };
+    let modulus = public_key.read(TAG_INTEGER)?;
+    let public_exponent = public_key.read(TAG_INTEGER)?;
+    crate::crypto::RsaPublicKey::new(modulus, public_exponent)
+        .ok_or(TlsError::InvalidPrivateKey("invalid RSA public key"))
+}
+
+/// Reads the DER encoded certificates of a chain, leaf certificate first.
+pub fn parse_certificates(input: &str) -> Result<Vec<Vec<u8>>, TlsError> {
+    let certificates: Vec<Vec<u8>> = parse_pem(input)?
+        .into_iter()
+        .filter(|(label, _)| label == "CERTIFICATE")
+        .map(|(_, contents)| contents)
+        .collect();
+    if certificates.is_empty() {
+        return Err(TlsError::InvalidPem("no certificate found"));
+    }
+    Ok(certificates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pem_blocks() {
+        let input = "junk\n-----BEGIN CERTIFICATE-----\nZm9v\nYmFy\n-----END CERTIFICATE-----\n\
+                     -----BEGIN OTHER-----\nZg==\n-----END OTHER-----\n";
+        assert_eq!(
+            parse_pem(input).unwrap(),
+            vec![
+                ("CERTIFICATE".to_owned(), b"foobar".to_vec()),
+                ("OTHER".to_owned(), b"f".to_vec())
+            ]
+        );
+        assert_eq!(parse_certificates(input).unwrap(), vec![b"foobar".to_vec()]);
+        assert!(parse_pem("-----BEGIN A-----\nZm9v\n-----END B-----").is_err());
+        assert!(parse_private_key(input).is_err());
+    }
+
+    #[test]
+    fn read_der() {
+        let mut der = Der {
+            data: &[0x30, 0x03, 0x02, 0x01, 0x05, 0x04, 0x81, 0x01, 0xff],
+        };
+        assert_eq!(der.read(TAG_SEQUENCE).unwrap(), &[0x02, 0x01, 0x05]);
+        assert_eq!(der.read(TAG_OCTET_STRING).unwrap(), &[0xff]);
+        assert!(der.read(TAG_INTEGER).is_err());
+    }
+}
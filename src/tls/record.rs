@@ -0,0 +1,229 @@
+// https://tools.ietf.org/html/rfc8446#section-5
+use super::{alert, TlsError};
+use crate::crypto::chacha20poly1305::{self, KEY_LEN, NONCE_LEN, TAG_LEN};
+use crate::crypto::hkdf_expand;
+use std::io::{self, ErrorKind, Read, Write};
+
+pub const CHANGE_CIPHER_SPEC: u8 = 20;
+pub const ALERT: u8 = 21;
+pub const HANDSHAKE: u8 = 22;
+pub const APPLICATION_DATA: u8 = 23;
+
+const MAX_FRAGMENT_LEN: usize = 1 << 14;
+// Content type and padding can add up to 256 bytes to the encrypted fragment
+const MAX_CIPHERTEXT_LEN: usize = MAX_FRAGMENT_LEN + 256;
+
+pub fn hkdf_expand_label(secret: &[u8], label: &str, context: &[u8], len: usize) -> Vec<u8> {
+    let label = format!("tls13 {}", label);
+    let mut info = (len as u16).to_be_bytes().to_vec();
+    info.push(label.len() as u8);
+    info.extend_from_slice(label.as_bytes());
+    info.push(context.len() as u8);
+    info.extend_from_slice(context);
+    hkdf_expand(secret, &info, len)
+}
+
+/// Key and nonce for one direction of the connection, derived from a traffic secret.
+pub struct TrafficKey {
+    key: [u8; KEY_LEN],
+    iv: [u8; NONCE_LEN],
+    sequence: u64,
+}
+
+impl TrafficKey {
+    pub fn new(secret: &[u8]) -> Self {
+        let mut key = [0u8; KEY_LEN];
+        key.copy_from_slice(&hkdf_expand_label(secret, "key", &[], KEY_LEN));
+        let mut iv = [0u8; NONCE_LEN];
+        iv.copy_from_slice(&hkdf_expand_label(secret, "iv", &[], NONCE_LEN));
+        TrafficKey {
+            key,
+            iv,
+            sequence: 0,
+        }
+    }
+
+    fn next_nonce(&mut self) -> [u8; NONCE_LEN] {
+        let mut nonce = self.iv;
+        for (byte, sequence_byte) in nonce[4..].iter_mut().zip(self.sequence.to_be_bytes()) {
+            *byte ^= sequence_byte;
+        }
+        self.sequence += 1;
+        nonce
+    }
+}
+
+/// Splits the byte stream into records, encrypting them once the keys are set.
+pub struct RecordLayer<S> {
+    pub stream: S,
+    pub read_key: Option<TrafficKey>,
+    pub write_key: Option<TrafficKey>,
+    // Bytes of a record that hasn't been received entirely yet. Kept between calls so a
+    // read timeout doesn't lose data.
+    incoming: Vec<u8>,
+}
+
+impl<S: Read + Write> RecordLayer<S> {
+    pub fn new(stream: S) -> Self {
+        RecordLayer {
+            stream,
+            read_key: None,
+            write_key: None,
+            incoming: Vec::new(),
+        }
+    }
+
+    fn read_raw_record(&mut self) -> Result<Option<(u8, Vec<u8>)>, TlsError> {
+        loop {
+            if self.incoming.len() >= 5 {
+                let len = u16::from_be_bytes([self.incoming[3], self.incoming[4]]) as usize;
+                if len > MAX_CIPHERTEXT_LEN {
+                    return Err(TlsError::protocol(
+                        alert::RECORD_OVERFLOW,
+                        "record is too long",
+                    ));
+                }
+                if self.incoming.len() >= 5 + len {
+                    let record: Vec<u8> = self.incoming.drain(..5 + len).collect();
+                    return Ok(Some((record[0], record)));
+                }
+            }
+            let mut buffer = [0u8; 4096];
+            let read = match self.stream.read(&mut buffer) {
+                Ok(read) => read,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(TlsError::Io(err)),
+            };
+            if read == 0 {
+                if self.incoming.is_empty() {
+                    return Ok(None);
+                }
+                return Err(TlsError::Io(ErrorKind::UnexpectedEof.into()));
+            }
+            self.incoming.extend_from_slice(&buffer[..read]);
+        }
+    }
+
+    /// Reads the next record and returns its content type and plaintext, or `None` if the
+    /// peer closed the connection between records.
+    pub fn read_record(&mut self) -> Result<Option<(u8, Vec<u8>)>, TlsError> {
+        let (content_type, record) = match self.read_raw_record()? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let (header, fragment) = record.split_at(5);
+        let read_key = match self.read_key.as_mut() {
+            // Compatibility change_cipher_spec records are never encrypted
+            Some(read_key) if content_type != CHANGE_CIPHER_SPEC => read_key,
+            _ => return Ok(Some((content_type, fragment.to_vec()))),
+        };
+        if content_type != APPLICATION_DATA {
+            return Err(TlsError::protocol(
+                alert::UNEXPECTED_MESSAGE,
+                "unencrypted record after the handshake keys were set",
+            ));
+        }
+
+        let nonce = read_key.next_nonce();
+        let mut plaintext = chacha20poly1305::open(&read_key.key, &nonce, header, fragment)
+            .ok_or_else(|| TlsError::protocol(alert::BAD_RECORD_MAC, "record failed to decrypt"))?;
+        // The real content type is the last non zero byte
+        while plaintext.last() == Some(&0) {
+            plaintext.pop();
+        }
+        let content_type = plaintext.pop().ok_or_else(|| {
+            TlsError::protocol(alert::UNEXPECTED_MESSAGE, "record without content type")
+        })?;
+        if plaintext.len() > MAX_FRAGMENT_LEN {
+            return Err(TlsError::protocol(
+                alert::RECORD_OVERFLOW,
+                "record is too long",
+            ));
+        }
+        Ok(Some((content_type, plaintext)))
+    }
+
+    /// Writes `data` in as many records as needed.
+    pub fn write_record(&mut self, content_type: u8, data: &[u8]) -> io::Result<()> {
+        let mut output = Vec::with_capacity(data.len() + 32);
+        // An empty write still sends one empty record
+        let fragments: Vec<&[u8]> = if data.is_empty() {
+            vec![data]
+        } else {
+            data.chunks(MAX_FRAGMENT_LEN).collect()
+        };
+        for fragment in fragments {
+            match self.write_key.as_mut() {
+                Some(write_key) if content_type != CHANGE_CIPHER_SPEC => {
+                    let len = fragment.len() + 1 + TAG_LEN;
+                    let header = [APPLICATION_DATA, 3, 3, (len >> 8) as u8, len as u8];
+                    let mut plaintext = fragment.to_vec();
+                    plaintext.push(content_type);
+                    let nonce = write_key.next_nonce();
+                    let sealed =
+                        chacha20poly1305::seal(&write_key.key, &nonce, &header, &plaintext);
+                    output.extend_from_slice(&header);
+                    output.extend_from_slice(&sealed);
+                }
+                _ => {
+                    let len = fragment.len();
+                    output.extend_from_slice(&[content_type, 3, 3, (len >> 8) as u8, len as u8]);
+                    output.extend_from_slice(fragment);
+                }
+            }
+        }
+        self.stream.write_all(&output)?;
+        self.stream.flush()
+    }
+
+    pub fn send_alert(&mut self, description: u8) -> io::Result<()> {
+        let level = if description == alert::CLOSE_NOTIFY {
+            1
+        } else {
+            2
+        };
+        self.write_record(ALERT, &[level, description])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn encrypted_round_trip() {
+        let mut writer = RecordLayer::new(Cursor::new(Vec::new()));
+        writer.write_key = Some(TrafficKey::new(&[1; 32]));
+        writer.write_record(APPLICATION_DATA, b"first").unwrap();
+        writer.write_record(HANDSHAKE, b"second").unwrap();
+        let written = writer.stream.into_inner();
+        assert_eq!(written[0], APPLICATION_DATA);
+
+        let mut reader = RecordLayer::new(Cursor::new(written));
+        reader.read_key = Some(TrafficKey::new(&[1; 32]));
+        assert_eq!(
+            reader.read_record().unwrap(),
+            Some((APPLICATION_DATA, b"first".to_vec()))
+        );
+        assert_eq!(
+            reader.read_record().unwrap(),
+            Some((HANDSHAKE, b"second".to_vec()))
+        );
+        assert_eq!(reader.read_record().unwrap(), None);
+    }
+
+    #[test]
+    fn wrong_key_fails_to_decrypt() {
+        let mut writer = RecordLayer::new(Cursor::new(Vec::new()));
+        writer.write_key = Some(TrafficKey::new(&[1; 32]));
+        writer.write_record(APPLICATION_DATA, b"secret").unwrap();
+
+        let mut reader = RecordLayer::new(Cursor::new(writer.stream.into_inner()));
+        reader.read_key = Some(TrafficKey::new(&[2; 32]));
+        match reader.read_record() {
+            Err(TlsError::Protocol { alert: sent, .. }) => assert_eq!(sent, alert::BAD_RECORD_MAC),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use super::handshake::{Session, KEY_UPDATE};
+use super::record::{self, hkdf_expand_label, RecordLayer, TrafficKey};
+use super::{alert, TlsError};
+use crate::http::{Connection, TlsInfo};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Encrypted connection, returned by `accept` once the handshake is done.
+pub struct TlsStream<S: Read + Write> {
+    records: RecordLayer<S>,
+    client_secret: Vec<u8>,
+    server_secret: Vec<u8>,
+    plaintext: Vec<u8>,
+    alpn_protocol: Option<String>,
+    server_name: Option<String>,
+    closed: bool,
+}
+
+impl<S: Read + Write> TlsStream<S> {
+    pub(super) fn new(records: RecordLayer<S>, session: Session) -> Self {
+        TlsStream {
+            records,
+            client_secret: session.client_secret,
+            server_secret: session.server_secret,
+            plaintext: Vec::new(),
+            alpn_protocol: session.alpn_protocol,
+            server_name: session.server_name,
+            closed: false,
+        }
+    }
+
+    /// Protocol negotiated with ALPN, if the client asked for one.
+    pub fn alpn_protocol(&self) -> Option<&str> {
+        self.alpn_protocol.as_deref()
+    }
+
+    /// Host name the client sent with SNI.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.records.stream
+    }
+
+    fn next_secret(secret: &[u8]) -> Vec<u8> {
+        hkdf_expand_label(secret, "traffic upd", &[], 32)
+    }
+
+    fn handle_key_update(&mut self, messages: &[u8]) -> Result<(), TlsError> {
+        let unexpected = || TlsError::Protocol {
+            alert: alert::UNEXPECTED_MESSAGE,
+            reason: "unexpected handshake message after the handshake",
+        };
+        if messages.len() != 5 || messages[0] != KEY_UPDATE || messages[1..4] != [0, 0, 1] {
+            return Err(unexpected());
+        }
+        self.client_secret = Self::next_secret(&self.client_secret);
+        self.records.read_key = Some(TrafficKey::new(&self.client_secret));
+        // 1 is update_requested, the peer wants us to update our keys too
+        if messages[4] == 1 {
+            self.records
+                .write_record(record::HANDSHAKE, &[KEY_UPDATE, 0, 0, 1, 0])?;
+            self.server_secret = Self::next_secret(&self.server_secret);
+            self.records.write_key = Some(TrafficKey::new(&self.server_secret));
+        }
+        Ok(())
+    }
+
+    fn read_application_data(&mut self) -> Result<(), TlsError> {
+        while self.plaintext.is_empty() && !self.closed {
+            match self.records.read_record()? {
+                None => self.closed = true,
+                Some((record::APPLICATION_DATA, data)) => self.plaintext = data,
+                Some((record::ALERT, data)) => match data.get(1).copied() {
+                    Some(alert::CLOSE_NOTIFY) => {
+                        self.closed = true;
+                        let _ = self.records.send_alert(alert::CLOSE_NOTIFY);
+                    }
+                    description => return Err(TlsError::Alert(description.unwrap_or(0))),
+                },
+                Some((record::HANDSHAKE, data)) => self.handle_key_update(&data)?,
+                Some(_) => {
+                    return Err(TlsError::Protocol {
+                        alert: alert::UNEXPECTED_MESSAGE,
+                        reason: "unexpected record",
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> Read for TlsStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Err(err) = self.read_application_data() {
+            if let TlsError::Protocol { alert, .. } = err {
+                let _ = self.records.send_alert(alert);
+                self.closed = true;
+            }
+            return Err(err.into());
+        }
+        let len = buf.len().min(self.plaintext.len());
+        buf[..len].copy_from_slice(&self.plaintext[..len]);
+        self.plaintext.drain(..len);
+        Ok(len)
+    }
+}
+
+impl<S: Read + Write> Write for TlsStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.records.write_record(record::APPLICATION_DATA, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.records.stream.flush()
+    }
+}
+
+impl<S: Read + Write> Drop for TlsStream<S> {
+    fn drop(&mut self) {
+        if !self.closed {
+            let _ = self.records.send_alert(alert::CLOSE_NOTIFY);
+        }
+    }
+}
+
+impl<S: Connection> Connection for TlsStream<S> {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.records.stream.set_read_timeout(timeout)
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.records.stream.peer_addr()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.records.stream.local_addr()
+    }
+
+    fn is_secure(&self) -> bool {
+        true
+    }
+
+    fn tls_info(&self) -> Option<TlsInfo> {
+        Some(TlsInfo {
+            version: "TLSv1.3",
+            cipher_suite: "TLS_CHACHA20_POLY1305_SHA256",
+            server_name: self.server_name.clone(),
+            alpn_protocol: self.alpn_protocol.clone(),
+        })
+    }
+}